@@ -4,7 +4,7 @@ import { listen } from '@tauri-apps/api/event'
 
 import { type Chat } from '~/entities/chat'
 import type { BridgeEvent } from '~/entities/events'
-import { useToast } from '~/shared/lib'
+import { getErrorMessage, useToast } from '~/shared/lib'
 import {
   createChat as createChatReq,
   deleteChat as deleteChatReq,
@@ -75,7 +75,7 @@ export const useChatsStore = defineStore('chats', () => {
     const chat = event.payload.data
     updateChat(chat)
   }).catch((error) => {
-    useToast().errorToast(String(error))
+    useToast().errorToast(getErrorMessage(error))
   })
 
   const $reset = async () => {
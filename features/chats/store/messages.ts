@@ -5,7 +5,7 @@ import { listen } from '@tauri-apps/api/event'
 import { useChatsStore, type EditMessage } from '~/features/chats'
 import { type Message } from '~/entities/chat'
 import type { BridgeEvent } from '~/entities/events'
-import { useToast } from '~/shared/lib'
+import { getErrorMessage, useToast } from '~/shared/lib'
 import {
   createMessage as createMessageReq,
   deleteMessage as deleteMessageReq,
@@ -93,13 +93,13 @@ export const useMessagesStore = defineStore('messages', () => {
   const msgCreatedUnlisten = listen<BridgeEvent<Message>>('messages:created', (event) => {
     addMessage(event.payload.data)
   }).catch((e) => {
-    useToast().errorToast(String(e))
+    useToast().errorToast(getErrorMessage(e))
   })
   const msgUpdatedUnlisten = listen<BridgeEvent<Message>>('messages:updated', (event) => {
     const msg = event.payload.data
     updateMessage(msg)
   }).catch((e) => {
-    useToast().errorToast(String(e))
+    useToast().errorToast(getErrorMessage(e))
   })
 
   const $reset = async () => {
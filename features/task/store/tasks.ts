@@ -7,7 +7,7 @@ import { useRouteQuery } from '@vueuse/router'
 import { useChatsStore } from '~/features/chats'
 import type { BridgeEvent } from '~/entities/events'
 import { TaskStatus, type Task, type SelectedTask } from '~/entities/tasks'
-import { usePagination, useToast } from '~/shared/lib'
+import { getErrorMessage, usePagination, useToast } from '~/shared/lib'
 import {
   listRootTasks as listRootTasksReq,
   listChildTasks as listChildTasksReq,
@@ -127,7 +127,7 @@ export const useTasksStore = defineStore('tasks', () => {
         selectedTaskQuery.value = null
       }
     } catch (error) {
-      useToast().errorToast(String(error))
+      useToast().errorToast(getErrorMessage(error))
     }
   }
   const getDefaultTasksGroupsByStatus = () => {
@@ -328,7 +328,7 @@ export const useTasksStore = defineStore('tasks', () => {
       listChats()
     }
   }).catch((error) => {
-    useToast().errorToast(String(error))
+    useToast().errorToast(getErrorMessage(error))
   })
   const tasksCreatedUnlisten = listen<BridgeEvent<Task>>('tasks:created', async (event) => {
     const task = event.payload.data
@@ -338,7 +338,7 @@ export const useTasksStore = defineStore('tasks', () => {
       selectedTask.value.children = data.value?.tasks || []
     }
   }).catch((error) => {
-    useToast().errorToast(String(error))
+    useToast().errorToast(getErrorMessage(error))
   })
 
   const $reset = async () => {
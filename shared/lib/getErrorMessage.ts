@@ -0,0 +1,13 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+import type { CommandError } from '~/shared/model'
+
+export const isCommandError = (error: unknown): error is CommandError => {
+  return typeof error === 'object' && error !== null && 'code' in error && 'message' in error
+}
+
+// Commands reject with a `CommandError`, while other APIs reject with strings or `Error`s
+export const getErrorMessage = (error: unknown) => {
+  return isCommandError(error) ? error.message : String(error)
+}
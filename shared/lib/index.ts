@@ -5,6 +5,7 @@ export * from './abbreviateNumber'
 export * from './bridgeAgentId'
 export * from './copyToClipboard'
 export * from './formatBytes'
+export * from './getErrorMessage'
 export * from './getMarkdown'
 export * from './getNumberWithDividers'
 export * from './getTimeAgo'
@@ -4,16 +4,18 @@
 import type { InvokeArgs } from '@tauri-apps/api/tauri'
 import { toast } from 'vue3-toastify'
 import 'vue3-toastify/dist/index.css'
+import type { CommandError } from '~/shared/model'
+import { getErrorMessage } from './getErrorMessage'
 
 // TODO replace direct calls of invoke in app with useInvoke
-interface ExtendedInvoke<T = unknown, E = unknown> {
+interface ExtendedInvoke<T = unknown, E = CommandError> {
   cmd: string
   args?: InvokeArgs
   onSuccess?: (data?: T) => void
   onError?: (error: E) => void
   instantCall?: boolean
 }
-export const useInvoke = async <T = unknown, E = unknown>({
+export const useInvoke = async <T = unknown, E = CommandError>({
   cmd,
   args,
   onSuccess,
@@ -37,7 +39,7 @@ export const useInvoke = async <T = unknown, E = unknown>({
       if (onError) {
         onError(error.value)
       }
-      toast(`Error: ${error.value}`, {
+      toast(`Error: ${getErrorMessage(error.value)}`, {
         theme: 'dark',
         type: 'error',
         dangerouslyHTMLString: false,
@@ -0,0 +1,11 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+// Error a failed command rejects with
+export interface CommandError {
+  // Stable identifier of the error, e.g. `not_found` or `invalid_settings`
+  code: string
+  message: string
+  // Structured data specific to the code, `null` if there is none
+  details: unknown
+}
@@ -1,6 +1,7 @@
 // Copyright 2024 StarfleetAI
 // SPDX-License-Identifier: Apache-2.0
 
+export * from './CommandError'
 export * from './Month'
 export * from './Pagination'
 export * from './Provider'
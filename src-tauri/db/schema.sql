@@ -0,0 +1,208 @@
+-- Copyright 2024 StarfleetAI
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Tables owned by the application itself. The core schema is managed by `bridge-common`
+-- migrations, so every statement here must be idempotent, and the tables of `bridge-common` are
+-- left as they are: what the application keeps on top of them goes in side tables keyed by their id.
+-- Rows scoped by company are deleted along with it, as workspaces are.
+
+CREATE TABLE IF NOT EXISTS ability_test_cases (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    ability_id INTEGER REFERENCES abilities(id) ON DELETE CASCADE NOT NULL,
+    name TEXT NOT NULL,
+    arguments JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_ability_test_cases_on_company_id_and_ability_id
+    ON ability_test_cases (company_id, ability_id);
+
+-- How abilities are run, one row per ability
+CREATE TABLE IF NOT EXISTS ability_runtimes (
+    ability_id INTEGER PRIMARY KEY REFERENCES abilities(id) ON DELETE CASCADE,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    language TEXT NOT NULL DEFAULT 'Python',
+    requirements TEXT[] NOT NULL DEFAULT '{}',
+    -- NULL means the global tool call timeout from the settings applies
+    timeout_secs INTEGER CHECK (timeout_secs > 0)
+);
+
+CREATE TABLE IF NOT EXISTS tool_call_policies (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    -- NULL means the policy applies to every agent
+    agent_id INTEGER REFERENCES agents(id) ON DELETE CASCADE,
+    target TEXT NOT NULL DEFAULT 'AnyTool',
+    ability_id INTEGER REFERENCES abilities(id) ON DELETE CASCADE,
+    action TEXT NOT NULL DEFAULT 'AlwaysAsk',
+    rules JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CHECK ((target = 'Ability') = (ability_id IS NOT NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS index_tool_call_policies_on_scope
+    ON tool_call_policies (company_id, COALESCE(agent_id, 0), target, COALESCE(ability_id, 0));
+
+CREATE TABLE IF NOT EXISTS tool_call_decisions (
+    id BIGSERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    message_id BIGINT REFERENCES messages(id) ON DELETE CASCADE NOT NULL,
+    tool_call_id TEXT NOT NULL,
+    decision TEXT NOT NULL,
+    -- NULL if no policy matched, or the policy has been deleted since
+    policy_id INTEGER REFERENCES tool_call_policies(id) ON DELETE SET NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS index_tool_call_decisions_on_message_id_and_tool_call_id
+    ON tool_call_decisions (message_id, tool_call_id);
+
+CREATE TABLE IF NOT EXISTS artifacts (
+    id BIGSERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    chat_id INTEGER REFERENCES chats(id) ON DELETE CASCADE NOT NULL,
+    -- Message with the tool call which produced the artifact
+    message_id BIGINT REFERENCES messages(id) ON DELETE CASCADE NOT NULL,
+    tool_call_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_artifacts_on_company_id_and_chat_id
+    ON artifacts (company_id, chat_id);
+
+-- Salt and check value of the secrets encryption key derived from `BRIDGE_SECRETS_PASSPHRASE`,
+-- shared by every install using the database. There is only one row, if any.
+CREATE TABLE IF NOT EXISTS secrets_passphrase (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    salt TEXT NOT NULL,
+    check_value TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    url TEXT NOT NULL,
+    -- Names of the events to send, e.g. `tasks:updated`
+    events TEXT[] NOT NULL DEFAULT '{}',
+    -- Key the payloads are signed with, encrypted
+    secret TEXT NOT NULL,
+    is_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_webhooks_on_company_id ON webhooks (company_id);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    webhook_id INTEGER REFERENCES webhooks(id) ON DELETE CASCADE NOT NULL,
+    event TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'Pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- Of the last attempt, NULL if no response was received
+    response_status INTEGER,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_webhook_deliveries_on_webhook_id ON webhook_deliveries (webhook_id, id);
+CREATE INDEX IF NOT EXISTS index_webhook_deliveries_on_status ON webhook_deliveries (status);
+
+-- Append-only log of the events emitted, for the clients to catch up on, and as an audit trail
+-- Events of a company are appended one at a time, under an advisory lock, so they're committed in
+-- the order of `seq`
+CREATE TABLE IF NOT EXISTS events (
+    seq BIGSERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    name TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_events_on_company_id_and_seq ON events (company_id, seq);
+
+CREATE TABLE IF NOT EXISTS task_templates (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    name TEXT NOT NULL,
+    -- Title and summary of the task, with `{{parameter}}` placeholders
+    title TEXT NOT NULL,
+    summary TEXT NOT NULL DEFAULT '',
+    -- NULL if the agent has been deleted, then it must be given on instantiation
+    agent_id INTEGER REFERENCES agents(id) ON DELETE SET NULL,
+    parameters JSONB NOT NULL DEFAULT '[]',
+    -- Pre-planned subtask tree, empty if the task is to be planned on its own
+    subtasks JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_task_templates_on_company_id ON task_templates (company_id);
+
+-- Plans proposed for a task, kept until the user accepts or rejects them
+CREATE TABLE IF NOT EXISTS plan_proposals (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    task_id INTEGER REFERENCES tasks(id) ON DELETE CASCADE NOT NULL,
+    status TEXT NOT NULL DEFAULT 'Pending',
+    rationale TEXT NOT NULL DEFAULT '',
+    subtasks JSONB NOT NULL DEFAULT '[]',
+    -- Why the user rejected the plan, handed to the next proposal
+    feedback TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_plan_proposals_on_task_id ON plan_proposals (task_id, id);
+
+-- Place of the root tasks in the execution queue
+CREATE TABLE IF NOT EXISTS task_schedules (
+    task_id INTEGER PRIMARY KEY REFERENCES tasks(id) ON DELETE CASCADE,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    -- Root tasks of higher priority are executed first
+    priority INTEGER NOT NULL DEFAULT 0,
+    -- When the task took its place in the queue, NULL until it's given one. Tasks of the same
+    -- priority are executed in this order
+    queued_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Order of the subtasks among their siblings, once they're put in order by hand. The siblings
+-- without a position come after the ones with it, in the order they were created
+CREATE TABLE IF NOT EXISTS task_positions (
+    task_id INTEGER PRIMARY KEY REFERENCES tasks(id) ON DELETE CASCADE,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    position INTEGER NOT NULL
+);
+
+-- Steps of task execution, each a completion of the agent or a self-reflection along with the
+-- tool calls it made
+CREATE TABLE IF NOT EXISTS task_steps (
+    id BIGSERIAL PRIMARY KEY,
+    company_id INTEGER REFERENCES companies(id) ON DELETE CASCADE NOT NULL,
+    task_id INTEGER REFERENCES tasks(id) ON DELETE CASCADE NOT NULL,
+    -- Assistant message of the completion, NULL once deleted
+    message_id BIGINT REFERENCES messages(id) ON DELETE SET NULL,
+    is_self_reflection BOOLEAN NOT NULL DEFAULT FALSE,
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER,
+    tool_calls JSONB NOT NULL DEFAULT '[]',
+    verdict TEXT NOT NULL DEFAULT 'Continue',
+    error TEXT,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- NULL while the step is being executed
+    finished_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS index_task_steps_on_task_id ON task_steps (task_id, started_at);
@@ -0,0 +1,570 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context};
+use bridge_common::{
+    channel::{Channel, Event},
+    clients::openai::{FunctionCall, ToolCall, ToolType},
+    repo::messages::CreateParams,
+    types::messages::{Message, Role, Status},
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tracing::{debug, error, trace};
+
+use self::venvs::Venv;
+use crate::{
+    docker::{self, RunParams},
+    repo,
+    settings::{Sandbox, Settings},
+    types::{
+        abilities::{Ability, Language},
+        DbPool, Result,
+    },
+};
+
+pub mod outputs;
+pub mod runtimes;
+pub mod signatures;
+pub mod venvs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid arguments: {}", .0.join("; "))]
+    InvalidArguments(Vec<String>),
+    #[error("ability exited with status {exit_code} without reporting a result")]
+    NoResult { exit_code: i64 },
+    #[error("no function declaration found in the ability code")]
+    NoFunctionFound,
+    #[error("unsupported parameter `{0}`, declare the parameters schema explicitly")]
+    UnsupportedParameter(String),
+    #[error("signatures can't be extracted from {0} code")]
+    UnsupportedLanguage(Language),
+    #[error("failed to get function definition:\n{0}")]
+    FunctionDefinition(String),
+    #[error("invalid requirement `{0}`")]
+    InvalidRequirement(String),
+    #[error("requirement `{0}` must be pinned to an exact version, e.g. `package==1.0.0`")]
+    RequirementNotPinned(String),
+    #[error("requirements are only supported for Python abilities, not {0}")]
+    RequirementsNotSupported(Language),
+    #[error("failed to install requirements:\n{0}")]
+    RequirementsInstall(String),
+}
+
+/// Result of a single ability run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Execution {
+    pub stdout: String,
+    pub stderr: String,
+    pub return_value: Value,
+    /// Error raised by the ability, if any.
+    pub error: Option<String>,
+    /// Time spent inside the ability function itself.
+    pub duration_ms: f64,
+    /// Time spent on the whole run, including container startup.
+    pub wall_time_ms: u64,
+    /// Whether the run was stopped for exceeding the sandbox output limit.
+    pub is_output_truncated: bool,
+    /// Timeout the run was stopped at, if it was.
+    pub timed_out_after_secs: Option<u64>,
+}
+
+impl Execution {
+    /// Text handed back to LLM as the tool call output.
+    #[must_use]
+    pub fn to_tool_output(&self) -> String {
+        let result = match (&self.error, &self.return_value) {
+            (Some(error), _) => error.trim().to_string(),
+            (None, Value::Null) => String::new(),
+            (None, Value::String(value)) => value.clone(),
+            (None, value) => value.to_string(),
+        };
+
+        let truncated = if self.is_output_truncated {
+            "[output truncated]"
+        } else {
+            ""
+        };
+
+        let timed_out = self
+            .timed_out_after_secs
+            .map(|secs| format!("[timed out after {secs}s, the process was killed]"))
+            .unwrap_or_default();
+
+        [
+            self.stdout.trim(),
+            self.stderr.trim(),
+            truncated,
+            &timed_out,
+            result.trim(),
+        ]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RunResult {
+    #[serde(default)]
+    return_value: Value,
+    error: Option<String>,
+    #[serde(default)]
+    duration_ms: f64,
+}
+
+/// Get `parameters_json` for the ability code.
+///
+/// Python abilities are inspected by running them in the `sandbox` (inside `venv`, if they have
+/// requirements), the rest are parsed from their `JSDoc`, type annotations or `@param` comments.
+///
+/// # Errors
+///
+/// Returns error if the function signature can't be determined.
+pub async fn get_function_definition(
+    language: Language,
+    code: &str,
+    venv: Option<&Venv>,
+    sandbox: &Sandbox,
+) -> Result<Value> {
+    if language != Language::Python {
+        return Ok(signatures::parse(language, code)?.to_parameters_json());
+    }
+
+    let python = venv.map_or_else(|| "python".to_string(), Venv::python);
+    let volumes = venv.map(Venv::volume).into_iter().collect::<Vec<_>>();
+    let script = runtimes::python_function_definition_script(code)?;
+
+    let output = docker::run(
+        RunParams {
+            image: runtimes::PYTHON_IMAGE,
+            volumes: &volumes,
+            cmd: vec![python, "-c".to_string(), script],
+            ..Default::default()
+        },
+        sandbox,
+    )
+    .await?;
+
+    debug!("Function definition script output: {:?}", output);
+
+    if output.exit_code != 0 {
+        return Err(Error::FunctionDefinition(output.stderr.trim().to_string()).into());
+    }
+
+    // Anything the code prints on import goes first, the definition is always the last line
+    let tool: Value = serde_json::from_str(output.stdout.trim().lines().last().unwrap_or_default())
+        .with_context(|| "Failed to parse function definition script output")?;
+
+    Ok(tool["function"].clone())
+}
+
+/// Validate tool call arguments against ability `parameters_json`.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidArguments` listing every mismatch found.
+pub fn validate_arguments(parameters_json: &Value, arguments: &Value) -> Result<()> {
+    let Some(arguments) = arguments.as_object() else {
+        return Err(
+            Error::InvalidArguments(vec!["arguments must be a JSON object".to_string()]).into(),
+        );
+    };
+
+    let empty = Map::new();
+    let parameters = &parameters_json["parameters"];
+    let properties = parameters["properties"].as_object().unwrap_or(&empty);
+
+    let mut errors = Vec::new();
+
+    for (name, value) in arguments {
+        match properties.get(name) {
+            Some(property) => {
+                if let Some(expected) = property["type"].as_str() {
+                    if !matches_type(expected, value) {
+                        errors.push(format!("`{name}` must be of type `{expected}`"));
+                    }
+                }
+            }
+            None => errors.push(format!("unknown argument `{name}`")),
+        }
+    }
+
+    if let Some(required) = parameters["required"].as_array() {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !arguments.contains_key(name) {
+                errors.push(format!("missing required argument `{name}`"));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidArguments(errors).into())
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        // Unknown types are not ours to judge
+        _ => true,
+    }
+}
+
+/// Run ability with given arguments in a fresh workdir, the way it's run for a tool call of LLM.
+///
+/// The workdir is created under `workdir_root` and removed after the run. Virtualenvs are cached
+/// under `workdir_root` as well.
+///
+/// # Errors
+///
+/// Returns error if the arguments are not valid, or the ability can't be run.
+pub async fn test(
+    ability: &Ability,
+    arguments: &Value,
+    workdir_root: &Path,
+    sandbox: &Sandbox,
+    default_timeout: Duration,
+) -> Result<Execution> {
+    debug!("Testing ability `{}`", ability.id);
+
+    let run_id = unique_id()?;
+    let workdir = workdir_root.join(format!("wd-ability-test-{}-{run_id}", ability.id));
+
+    fs::create_dir_all(&workdir)
+        .await
+        .with_context(|| "Failed to create workdir")?;
+
+    let name = ability
+        .function_name()
+        .with_context(|| format!("Ability `{}` has no function name", ability.id))?;
+    let tool_call = ToolCall {
+        id: format!("test-{run_id}"),
+        type_: ToolType::Function,
+        function: FunctionCall {
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        },
+    };
+
+    let venvs_root = venvs::root(workdir_root);
+    let execution = call(
+        ability,
+        &tool_call,
+        &workdir,
+        &venvs_root,
+        sandbox,
+        default_timeout,
+        &run_id,
+    )
+    .await;
+
+    fs::remove_dir_all(&workdir)
+        .await
+        .with_context(|| "Failed to remove workdir")?;
+
+    execution
+}
+
+/// Call ability for the tool call in the workdir: parse and validate its arguments, then run it.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidArguments` if the arguments are not valid, and error if the ability
+/// can't be run.
+pub async fn call(
+    ability: &Ability,
+    tool_call: &ToolCall,
+    workdir: &Path,
+    venvs_root: &Path,
+    sandbox: &Sandbox,
+    default_timeout: Duration,
+    run_id: &str,
+) -> Result<Execution> {
+    let arguments =
+        serde_json::from_str::<Value>(&tool_call.function.arguments).map_err(|err| {
+            Error::InvalidArguments(vec![format!("arguments are not valid JSON: {err}")])
+        })?;
+    validate_arguments(&ability.parameters_json, &arguments)?;
+
+    run(
+        ability,
+        &arguments,
+        workdir,
+        venvs_root,
+        sandbox,
+        default_timeout,
+        run_id,
+    )
+    .await
+}
+
+/// Run ability with given arguments in the workdir, using the runtime of its language, within the
+/// limits of the `sandbox`.
+///
+/// The run is killed after the ability timeout, or `default_timeout` if the ability has none.
+///
+/// # Errors
+///
+/// Returns error if ability requirements can't be installed, if the script can't be written,
+/// executed or removed, or if its result can't be read.
+pub async fn run(
+    ability: &Ability,
+    arguments: &Value,
+    workdir: &Path,
+    venvs_root: &Path,
+    sandbox: &Sandbox,
+    default_timeout: Duration,
+    run_id: &str,
+) -> Result<Execution> {
+    let venv = venvs::ensure(venvs_root, &ability.requirements, sandbox).await?;
+
+    let result_name = format!("tc-{run_id}.result.json");
+    let script = runtimes::script(ability, arguments, run_id, &result_name, venv.as_ref())?;
+
+    trace!("Script name: {}", script.name);
+    trace!("Script content: {}", script.content);
+
+    let script_path = workdir.join(&script.name);
+    let result_path = workdir.join(&result_name);
+
+    fs::write(&script_path, &script.content)
+        .await
+        .with_context(|| "Failed to write script to workdir")?;
+
+    let timeout = ability.timeout(default_timeout);
+    let started_at = Instant::now();
+    let output = docker::run(
+        RunParams {
+            image: script.image,
+            workdir: Some(workdir),
+            volumes: &script.volumes,
+            env: script.env,
+            cmd: script.cmd,
+            timeout: Some(timeout),
+        },
+        sandbox,
+    )
+    .await;
+    let wall_time_ms = u64::try_from(started_at.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+    fs::remove_file(&script_path)
+        .await
+        .with_context(|| "Failed to remove script from workdir")?;
+
+    let output = output?;
+
+    let result = match fs::read_to_string(&result_path).await {
+        Ok(result) => {
+            fs::remove_file(&result_path)
+                .await
+                .with_context(|| "Failed to remove result from workdir")?;
+
+            serde_json::from_str(&result)
+                .with_context(|| format!("Failed to parse ability result: {result}"))?
+        }
+        // The script died before it could report anything, e.g. on a syntax error
+        Err(_) => RunResult {
+            return_value: Value::Null,
+            // Being killed on timeout is reported on its own
+            error: (!output.is_timed_out).then(|| {
+                Error::NoResult {
+                    exit_code: output.exit_code,
+                }
+                .to_string()
+            }),
+            duration_ms: 0.0,
+        },
+    };
+
+    Ok(Execution {
+        stdout: output.stdout,
+        stderr: output.stderr,
+        return_value: result.return_value,
+        error: result.error,
+        duration_ms: result.duration_ms,
+        wall_time_ms,
+        is_output_truncated: output.is_truncated,
+        timed_out_after_secs: output.is_timed_out.then_some(timeout.as_secs()),
+    })
+}
+
+/// Executes given tool calls of the message, at most `tool_calls.execution_concurrency` at once.
+///
+/// Every result is sent as a separate tool message as soon as it's ready, cut down to the output
+/// limit. Tool calls which failed to run are left without a result, so they can be retried.
+///
+/// # Errors
+///
+/// Will return the first error occurred while executing tool calls, after all of them are done.
+pub async fn execute_tool_calls(
+    pool: &DbPool,
+    channel: &Channel,
+    user_id: i32,
+    workdir_root: &Path,
+    settings: &Settings,
+    message: &Message,
+    tool_calls: &[ToolCall],
+) -> Result<()> {
+    // Load agent abilities
+    let abilities = match message.agent_id {
+        Some(agent_id) => {
+            repo::abilities::list_for_agent(pool, message.company_id, agent_id).await?
+        }
+        None => return Err(anyhow!("Agent is not set for the message").into()),
+    };
+
+    let venvs_root = venvs::root(workdir_root);
+    let workdir = workdir_root.join(format!("wd-{}", message.chat_id));
+    if !workdir.exists() {
+        fs::create_dir_all(&workdir)
+            .await
+            .with_context(|| "Failed to create workdir")?;
+    }
+
+    let default_timeout = Duration::from_secs(settings.tool_calls.timeout_secs);
+    // Collected first, as a lazy iterator of futures can't be proven `Send`
+    let executions = tool_calls
+        .iter()
+        .map(|tool_call| {
+            execute(
+                &abilities,
+                &workdir,
+                &venvs_root,
+                &settings.sandbox,
+                default_timeout,
+                message,
+                tool_call,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut results = stream::iter(executions)
+        .buffer_unordered(usize::from(settings.tool_calls.execution_concurrency).max(1));
+
+    let mut first_error = None;
+    while let Some((tool_call_id, output)) = results.next().await {
+        let output = match output {
+            Ok(output) => outputs::limit(pool, settings, message, &tool_call_id, output).await,
+            Err(err) => Err(err),
+        };
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                error!("Failed to execute tool call `{}`: {}", tool_call_id, err);
+                first_error.get_or_insert(err);
+                continue;
+            }
+        };
+
+        // Wrap output in a code block
+        let results_message = bridge_common::repo::messages::create(
+            pool,
+            message.company_id,
+            CreateParams {
+                chat_id: message.chat_id,
+                status: Status::Completed,
+                role: Role::Tool,
+                content: Some(format!("```\n{output}\n```")),
+                tool_call_id: Some(tool_call_id),
+
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        channel
+            .emit(user_id, Event::MessageCreated(&results_message))
+            .await?;
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Execute a single tool call, returning its id alongside the output for LLM.
+///
+/// Problems LLM can fix on its own, like a misspelled ability name or invalid arguments, are
+/// reported back as the output instead of failing the whole message.
+async fn execute(
+    abilities: &[Ability],
+    workdir: &Path,
+    venvs_root: &Path,
+    sandbox: &Sandbox,
+    default_timeout: Duration,
+    message: &Message,
+    tool_call: &ToolCall,
+) -> (String, Result<String>) {
+    debug!(
+        "Executing tool call `{}` for message `{}`",
+        tool_call.id, message.id
+    );
+
+    let name = &tool_call.function.name;
+
+    // Internal tools are only handled by the task executor, but every tool call needs an answer
+    if name.starts_with("sfai_") {
+        return (
+            tool_call.id.clone(),
+            Ok(format!("Error: `{name}` is not available in chats")),
+        );
+    }
+
+    let Some(ability) = abilities
+        .iter()
+        .find(|ability| ability.function_name() == Some(name.as_str()))
+    else {
+        return (
+            tool_call.id.clone(),
+            Ok(format!("Error: ability `{name}` is not available")),
+        );
+    };
+
+    let run_id = format!("{}-{}", message.id, tool_call_digest(&tool_call.id));
+    let output = match call(
+        ability,
+        tool_call,
+        workdir,
+        venvs_root,
+        sandbox,
+        default_timeout,
+        &run_id,
+    )
+    .await
+    {
+        Ok(execution) => Ok(execution.to_tool_output()),
+        Err(err @ crate::errors::Error::Abilities(Error::InvalidArguments(_))) => {
+            Ok(format!("Error: {err}"))
+        }
+        Err(err) => Err(err),
+    };
+
+    (tool_call.id.clone(), output)
+}
+
+/// Digest of the tool call id, which comes from LLM, to be safely used in file names.
+fn tool_call_digest(id: &str) -> String {
+    hex::encode(&Sha256::digest(id.as_bytes())[..8])
+}
+
+fn unique_id() -> Result<String> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .with_context(|| "Failed to get current time")?
+        .as_nanos()
+        .to_string())
+}
@@ -0,0 +1,136 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fitting tool call outputs into the limit of what is sent to LLM.
+
+use anyhow::anyhow;
+use bridge_common::{
+    clients::openai::{Client, CreateChatCompletionRequest, Message as LlmMessage},
+    types::messages::Message,
+};
+use tracing::warn;
+
+use crate::{
+    repo::{self, artifacts::CreateParams},
+    settings::{OutputOverflow, Settings},
+    types::{DbPool, Result},
+};
+
+/// Most of the output handed to LLM for summarizing, so it fits into the context window.
+const SUMMARIZE_INPUT_LIMIT_CHARS: usize = 64 * 1024;
+
+/// Fit the output of the tool call into `tool_calls.output_limit_chars`, truncating or summarizing
+/// it as configured.
+///
+/// The full output of a tool call over the limit is saved as an artifact, and the returned text
+/// ends with a marker referring to it. Summarizing falls back to truncating if LLM fails.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while saving the artifact.
+pub async fn limit(
+    pool: &DbPool,
+    settings: &Settings,
+    message: &Message,
+    tool_call_id: &str,
+    output: String,
+) -> Result<String> {
+    let limit = settings.tool_calls.output_limit_chars;
+    let length = output.chars().count();
+
+    if length <= limit {
+        return Ok(output);
+    }
+
+    let summary = match settings.tool_calls.output_overflow {
+        OutputOverflow::Truncate => None,
+        OutputOverflow::Summarize => {
+            match summarize(pool, settings, message, &output, limit).await {
+                Ok(summary) => Some(summary),
+                Err(err) => {
+                    warn!("Failed to summarize output of tool call `{tool_call_id}`: {err}");
+                    None
+                }
+            }
+        }
+    };
+
+    let artifact = repo::artifacts::create(
+        pool,
+        message.company_id,
+        CreateParams {
+            chat_id: message.chat_id,
+            message_id: message.id,
+            tool_call_id: tool_call_id.to_string(),
+            content: output,
+        },
+    )
+    .await?;
+
+    Ok(match summary {
+        Some(summary) => format!(
+            "{}\n[output of {length} characters summarized, full output saved as artifact {}]",
+            truncate(&summary, limit),
+            artifact.id
+        ),
+        None => format!(
+            "{}\n[output truncated to {limit} of {length} characters, full output saved as artifact {}]",
+            truncate(&artifact.content, limit),
+            artifact.id
+        ),
+    })
+}
+
+fn truncate(text: &str, limit: usize) -> &str {
+    match text.char_indices().nth(limit) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+async fn summarize(
+    pool: &DbPool,
+    settings: &Settings,
+    message: &Message,
+    output: &str,
+    limit: usize,
+) -> Result<String> {
+    let chat = bridge_common::repo::chats::get(pool, message.company_id, message.chat_id).await?;
+    let (model, api_key) =
+        crate::conversations::model_for_chat(pool, &settings.common, &chat).await?;
+
+    let client = Client::new(&api_key, model.api_url_or_default(), &crate::USER_AGENT);
+    let response = client
+        .create_chat_completion(CreateChatCompletionRequest {
+            model: &model.name,
+            messages: vec![
+                LlmMessage::System {
+                    content: format!(
+                        "Summarize the tool call output below in at most {limit} characters. \
+                        Keep errors, numbers and identifiers which may matter for the next steps. \
+                        Respond with the summary only."
+                    ),
+                    name: None,
+                },
+                LlmMessage::User {
+                    content: truncate(output, SUMMARIZE_INPUT_LIMIT_CHARS).to_string(),
+                    name: None,
+                },
+            ],
+            ..Default::default()
+        })
+        .await?;
+
+    match response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message)
+    {
+        Some(LlmMessage::Assistant {
+            content: Some(summary),
+            ..
+        }) if !summary.trim().is_empty() => Ok(summary.trim().to_string()),
+        _ => Err(anyhow!("LLM returned no summary").into()),
+    }
+}
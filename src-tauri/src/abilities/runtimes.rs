@@ -0,0 +1,270 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use askama::Template;
+use serde_json::Value;
+
+use super::{
+    signatures::{self, Calling, Signature},
+    venvs::Venv,
+};
+use crate::{
+    docker::Volume,
+    types::{
+        abilities::{Ability, Language},
+        Result,
+    },
+};
+
+pub const PYTHON_IMAGE: &str = "python:slim";
+const NODE_IMAGE: &str = "node:slim";
+const DENO_IMAGE: &str = "denoland/deno";
+const DENO_DIR: &str = "/tmp/deno";
+// There's no need to pull yet another image just to get a POSIX shell
+const SHELL_IMAGE: &str = PYTHON_IMAGE;
+
+#[derive(Template)]
+#[template(path = "python/get_function_definition.py", escape = "none")]
+struct PythonGetFunctionDefinitionTemplate<'a> {
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "python/call_tools.py", escape = "none")]
+struct PythonCallToolsTemplate<'a> {
+    code: &'a str,
+    name: &'a str,
+    arguments: &'a str,
+    result_path: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "javascript/call_tools.mjs", escape = "none")]
+struct JavaScriptCallToolsTemplate<'a> {
+    code: &'a str,
+    name: &'a str,
+    arguments: &'a str,
+    call_arguments: &'a str,
+    result_path: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "typescript/call_tools.ts", escape = "none")]
+struct TypeScriptCallToolsTemplate<'a> {
+    code: &'a str,
+    name: &'a str,
+    arguments: &'a str,
+    call_arguments: &'a str,
+    result_path: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "shell/call_tools.sh", escape = "none")]
+struct ShellCallToolsTemplate<'a> {
+    code: &'a str,
+    name: &'a str,
+    exports: &'a str,
+    call_arguments: &'a str,
+    result_path: &'a str,
+}
+
+/// Script calling an ability, along with the way to run it.
+#[derive(Debug)]
+pub struct Script {
+    pub name: String,
+    pub content: String,
+    pub image: &'static str,
+    pub volumes: Vec<Volume>,
+    pub env: Vec<String>,
+    pub cmd: Vec<String>,
+}
+
+/// Render a Python script printing the definition of the first function in the code.
+///
+/// # Errors
+///
+/// Returns error if the script can't be rendered.
+pub fn python_function_definition_script(code: &str) -> Result<String> {
+    Ok(PythonGetFunctionDefinitionTemplate {
+        code: &to_json_string(code)?,
+    }
+    .render()
+    .with_context(|| "Failed to render `get_function_definition` script")?)
+}
+
+/// Render a script which calls the ability with given arguments and writes the result to
+/// `result_name` in the workdir.
+///
+/// Python abilities with requirements are run with the interpreter of their `venv`.
+///
+/// # Errors
+///
+/// Returns error if the ability signature can't be determined or the script can't be rendered.
+pub fn script(
+    ability: &Ability,
+    arguments: &Value,
+    run_id: &str,
+    result_name: &str,
+    venv: Option<&Venv>,
+) -> Result<Script> {
+    let code = &ability.code;
+    let result_path = to_json_string(result_name)?;
+    // Arguments are passed as a JSON string literal, to be parsed by the script itself
+    let arguments_json = to_json_string(&to_json_string(arguments)?)?;
+
+    let mut env = Vec::new();
+    let (extension, content, image, mut cmd) = match ability.language {
+        Language::Python => {
+            let name = ability
+                .function_name()
+                .with_context(|| format!("Ability `{}` has no function name", ability.id))?;
+            let content = PythonCallToolsTemplate {
+                code,
+                name: &to_json_string(name)?,
+                arguments: &arguments_json,
+                result_path: &result_path,
+            }
+            .render()
+            .with_context(|| "Failed to render `call_tools` script")?;
+
+            let python = venv.map_or_else(|| "python".to_string(), Venv::python);
+
+            ("py", content, PYTHON_IMAGE, vec![python])
+        }
+        Language::JavaScript => {
+            let signature = signatures::parse(ability.language, code)?;
+            let content = JavaScriptCallToolsTemplate {
+                code,
+                name: &signature.name,
+                arguments: &arguments_json,
+                call_arguments: &js_call_arguments(&signature)?,
+                result_path: &result_path,
+            }
+            .render()
+            .with_context(|| "Failed to render `call_tools` script")?;
+
+            // `.mjs` allows top-level `await` and `import`s in the ability code
+            ("mjs", content, NODE_IMAGE, vec!["node".to_string()])
+        }
+        Language::TypeScript => {
+            let signature = signatures::parse(ability.language, code)?;
+            let content = TypeScriptCallToolsTemplate {
+                code,
+                name: &signature.name,
+                arguments: &arguments_json,
+                call_arguments: &js_call_arguments(&signature)?,
+                result_path: &result_path,
+            }
+            .render()
+            .with_context(|| "Failed to render `call_tools` script")?;
+
+            // The default cache dir is on the read-only root filesystem
+            env.push(format!("DENO_DIR={DENO_DIR}"));
+
+            (
+                "ts",
+                content,
+                DENO_IMAGE,
+                ["deno", "run", "--allow-all", "--quiet"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+        }
+        Language::Shell => {
+            let signature = signatures::parse(ability.language, code)?;
+            let (exports, call_arguments) = shell_arguments(&signature, arguments);
+            let content = ShellCallToolsTemplate {
+                code,
+                name: &signature.name,
+                exports: &exports,
+                call_arguments: &call_arguments,
+                result_path: &shell_quote(result_name),
+            }
+            .render()
+            .with_context(|| "Failed to render `call_tools` script")?;
+
+            ("sh", content, SHELL_IMAGE, vec!["sh".to_string()])
+        }
+    };
+
+    let name = format!("tc-{run_id}.{extension}");
+    cmd.push(name.clone());
+
+    Ok(Script {
+        name,
+        content,
+        image,
+        volumes: venv.map(Venv::volume).into_iter().collect(),
+        env,
+        cmd,
+    })
+}
+
+/// Build the argument list for a JS call, reading values from `__bridgeArguments`.
+fn js_call_arguments(signature: &Signature) -> Result<String> {
+    match &signature.calling {
+        Calling::Object(_) => Ok("__bridgeArguments".to_string()),
+        Calling::Positional(parameters) => Ok(parameters
+            .iter()
+            .map(|parameter| {
+                let value = format!("__bridgeArguments[{}]", to_json_string(&parameter.name)?);
+
+                Ok(if parameter.is_rest {
+                    format!("...({value} ?? [])")
+                } else {
+                    value
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(", ")),
+    }
+}
+
+/// Shell functions get arguments both positionally, in `@param` order, and as environment
+/// variables named after the parameters.
+fn shell_arguments(signature: &Signature, arguments: &Value) -> (String, String) {
+    let value_of = |value: &Value| match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+
+    let exports = arguments
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .map(|(name, value)| format!("export {name}={}", shell_quote(&value_of(value))))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut positional = signature
+        .parameters()
+        .iter()
+        .map(|parameter| arguments.get(&parameter.name))
+        .collect::<Vec<_>>();
+    // Omitted trailing arguments should stay unset rather than become empty strings
+    while positional.last().is_some_and(Option::is_none) {
+        positional.pop();
+    }
+
+    let call_arguments = positional
+        .into_iter()
+        .map(|value| shell_quote(&value.map(value_of).unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (exports, call_arguments)
+}
+
+fn to_json_string<T: serde::Serialize + ?Sized>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value).with_context(|| "Failed to serialize value to JSON")?)
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,427 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Extracts function signatures from JavaScript, TypeScript and shell abilities.
+//!
+//! This is not a real parser: it looks for the first top-level function declaration and the doc
+//! comment right above it, which is enough for the way abilities are usually written. Anything
+//! more exotic should declare its parameters schema explicitly.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+use super::Error;
+use crate::types::{abilities::Language, Result};
+
+lazy_static! {
+    static ref JS_FUNCTION: Regex = Regex::new(
+        r"(?m)^[ \t]*(?:export[ \t]+(?:default[ \t]+)?)?(?:async[ \t]+)?function\b[ \t]*\*?[ \t]*(?P<name>[A-Za-z_$][\w$]*)[ \t]*(?:<[^>(]*>)?[ \t]*\((?P<params>[^)]*)\)"
+    )
+    .expect("Failed to compile JS function regex");
+    static ref JS_ARROW_FUNCTION: Regex = Regex::new(
+        r"(?m)^[ \t]*(?:export[ \t]+)?(?:const|let|var)[ \t]+(?P<name>[A-Za-z_$][\w$]*)[ \t]*(?::[^=]+)?=[ \t]*(?:async[ \t]+)?(?:function\b[^(]*\((?P<fn_params>[^)]*)\)|\((?P<params>[^)]*)\)\s*(?::[^=]+)?=>)"
+    )
+    .expect("Failed to compile JS arrow function regex");
+    static ref SHELL_FUNCTION: Regex =
+        Regex::new(r"(?m)^[ \t]*(?:function[ \t]+)?(?P<name>[A-Za-z_]\w*)[ \t]*\(\)")
+            .expect("Failed to compile shell function regex");
+    static ref DOC_PARAM: Regex = Regex::new(
+        r"^@param\s+(?:\{(?P<type>[^}]*)\}\s*)?(?P<name>\[[^\]]*\]|[\w$.]+)\s*(?:-\s*)?(?P<description>.*)$"
+    )
+    .expect("Failed to compile doc param regex");
+}
+
+/// How the arguments object is handed over to the function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Calling {
+    /// Arguments are passed positionally, in the order of these names.
+    Positional(Vec<Parameter>),
+    /// The whole arguments object is passed as a single (destructured) parameter.
+    Object(Vec<Parameter>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub name: String,
+    pub type_: String,
+    pub description: Option<String>,
+    pub is_optional: bool,
+    pub is_rest: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub description: Option<String>,
+    pub calling: Calling,
+}
+
+impl Signature {
+    #[must_use]
+    pub fn parameters(&self) -> &[Parameter] {
+        match &self.calling {
+            Calling::Positional(parameters) | Calling::Object(parameters) => parameters,
+        }
+    }
+
+    /// Build `parameters_json` for the signature, in the same shape as
+    /// `bridge_common::abilities::get_function_definition` produces for Python.
+    #[must_use]
+    pub fn to_parameters_json(&self) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        for parameter in self.parameters() {
+            let mut property = json!({ "type": parameter.type_ });
+            if let Some(description) = &parameter.description {
+                property["description"] = json!(description);
+            }
+            properties.insert(parameter.name.clone(), property);
+
+            if !parameter.is_optional {
+                required.push(parameter.name.clone());
+            }
+        }
+
+        let mut function = json!({
+            "name": self.name,
+            "parameters": {
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            },
+        });
+        if let Some(description) = &self.description {
+            function["description"] = json!(description);
+        }
+
+        function
+    }
+}
+
+#[derive(Debug, Default)]
+struct DocComment {
+    description: Option<String>,
+    params: Vec<DocParam>,
+}
+
+#[derive(Debug)]
+struct DocParam {
+    name: String,
+    type_: Option<String>,
+    description: Option<String>,
+    is_optional: bool,
+}
+
+impl DocComment {
+    fn param(&self, name: &str) -> Option<&DocParam> {
+        self.params.iter().find(|param| param.name == name)
+    }
+}
+
+/// Extract the signature of the first function declared in the code.
+///
+/// # Errors
+///
+/// Returns error if no function declaration can be found, or if its parameters can't be mapped
+/// to a JSON schema.
+pub fn parse(language: Language, code: &str) -> Result<Signature> {
+    match language {
+        Language::JavaScript | Language::TypeScript => parse_js(code),
+        Language::Shell => parse_shell(code),
+        Language::Python => Err(Error::UnsupportedLanguage(language).into()),
+    }
+}
+
+fn parse_js(code: &str) -> Result<Signature> {
+    let captures = [&*JS_FUNCTION, &*JS_ARROW_FUNCTION]
+        .iter()
+        .filter_map(|regex| regex.captures(code))
+        .min_by_key(|captures| captures.get(0).map_or(usize::MAX, |m| m.start()))
+        .ok_or(Error::NoFunctionFound)?;
+
+    let declaration = captures.get(0).ok_or(Error::NoFunctionFound)?;
+    let doc = js_doc_comment(&code[..declaration.start()]).unwrap_or_default();
+    let params = captures
+        .name("params")
+        .or_else(|| captures.name("fn_params"))
+        .map_or("", |m| m.as_str());
+
+    let parameters = split_top_level(params, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|param| !param.is_empty() && !param.starts_with("this:"))
+        .collect::<Vec<_>>();
+
+    let calling = match parameters.as_slice() {
+        [param] if param.starts_with('{') => {
+            Calling::Object(parse_destructured_param(param, &doc)?)
+        }
+        params => Calling::Positional(
+            params
+                .iter()
+                .map(|param| parse_js_param(param, &doc))
+                .collect::<Result<_>>()?,
+        ),
+    };
+
+    Ok(Signature {
+        name: captures["name"].to_string(),
+        description: doc.description,
+        calling,
+    })
+}
+
+fn parse_js_param(param: &str, doc: &DocComment) -> Result<Parameter> {
+    let (param, default) = split_once_top_level(param, '=');
+    let (param, annotation) = split_once_top_level(param, ':');
+    let param = param.trim();
+
+    let is_rest = param.starts_with("...");
+    let name = param.trim_start_matches("...").trim_end_matches('?').trim();
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    {
+        return Err(Error::UnsupportedParameter(param.to_string()).into());
+    }
+
+    let doc_param = doc.param(name);
+    let type_ = if is_rest {
+        "array".to_string()
+    } else {
+        annotation
+            .or_else(|| doc_param.and_then(|p| p.type_.as_deref()))
+            .map_or("string", json_type)
+            .to_string()
+    };
+
+    Ok(Parameter {
+        name: name.to_string(),
+        type_,
+        description: doc_param.and_then(|p| p.description.clone()),
+        is_optional: is_rest
+            || default.is_some()
+            || param.ends_with('?')
+            || doc_param.is_some_and(|p| p.is_optional),
+        is_rest,
+    })
+}
+
+/// Parse `{ a, b = 1 }: { a: string, b?: number }` into separate parameters.
+fn parse_destructured_param(param: &str, doc: &DocComment) -> Result<Vec<Parameter>> {
+    let (pattern, _default) = split_once_top_level(param, '=');
+    let (pattern, annotation) = split_once_top_level(pattern, ':');
+    let pattern =
+        strip_braces(pattern).ok_or_else(|| Error::UnsupportedParameter(param.to_string()))?;
+    let annotations = annotation
+        .and_then(strip_braces)
+        .map(|fields| {
+            split_top_level(fields, ';')
+                .into_iter()
+                .flat_map(|part| split_top_level(part, ','))
+                .filter_map(|field| {
+                    let (name, type_) = split_once_top_level(field, ':');
+                    Some((name.trim().trim_end_matches('?').to_string(), type_?.trim()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    split_top_level(pattern, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (field, default) = split_once_top_level(field, '=');
+            let name = field.trim();
+
+            if !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+            {
+                return Err(Error::UnsupportedParameter(field.to_string()).into());
+            }
+
+            // JSDoc documents destructured fields as `options.name`
+            let doc_param = doc
+                .params
+                .iter()
+                .find(|p| p.name.rsplit('.').next() == Some(name));
+            let annotation = annotations
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, type_)| *type_);
+
+            Ok(Parameter {
+                name: name.to_string(),
+                type_: annotation
+                    .or_else(|| doc_param.and_then(|p| p.type_.as_deref()))
+                    .map_or("string", json_type)
+                    .to_string(),
+                description: doc_param.and_then(|p| p.description.clone()),
+                is_optional: default.is_some() || doc_param.is_some_and(|p| p.is_optional),
+                is_rest: false,
+            })
+        })
+        .collect()
+}
+
+fn parse_shell(code: &str) -> Result<Signature> {
+    let captures = SHELL_FUNCTION
+        .captures(code)
+        .ok_or(Error::NoFunctionFound)?;
+    let declaration = captures.get(0).ok_or(Error::NoFunctionFound)?;
+
+    // Shell functions have no named parameters, so `@param` tags are the only source of them
+    let doc = shell_doc_comment(&code[..declaration.start()]);
+    let parameters = doc
+        .params
+        .iter()
+        .map(|param| Parameter {
+            name: param.name.clone(),
+            type_: param
+                .type_
+                .as_deref()
+                .map_or("string", json_type)
+                .to_string(),
+            description: param.description.clone(),
+            is_optional: param.is_optional,
+            is_rest: false,
+        })
+        .collect();
+
+    Ok(Signature {
+        name: captures["name"].to_string(),
+        description: doc.description,
+        calling: Calling::Positional(parameters),
+    })
+}
+
+/// Find a `/** ... */` comment that directly precedes the declaration.
+fn js_doc_comment(before: &str) -> Option<DocComment> {
+    let before = before.trim_end();
+    let body = before.strip_suffix("*/")?;
+    let start = body.rfind("/**")?;
+
+    let lines = body[start + 3..]
+        .lines()
+        .map(|line| line.trim().trim_start_matches('*').trim())
+        .collect::<Vec<_>>();
+
+    Some(parse_doc_lines(&lines))
+}
+
+/// Collect `#` comment lines that directly precede the declaration.
+fn shell_doc_comment(before: &str) -> DocComment {
+    let mut lines = before
+        .trim_end()
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with('#') && !line.starts_with("#!"))
+        .map(|line| line.trim_start_matches('#').trim())
+        .collect::<Vec<_>>();
+    lines.reverse();
+
+    parse_doc_lines(&lines)
+}
+
+fn parse_doc_lines(lines: &[&str]) -> DocComment {
+    let mut doc = DocComment::default();
+    let mut description = Vec::new();
+
+    for line in lines {
+        if let Some(captures) = DOC_PARAM.captures(line) {
+            let name = &captures["name"];
+            let (name, is_optional) = match name.strip_prefix('[') {
+                Some(name) => {
+                    let name = name.trim_end_matches(']');
+                    (name.split('=').next().unwrap_or(name).trim(), true)
+                }
+                None => (name, false),
+            };
+            let description = captures["description"].trim();
+            let type_ = captures.name("type").map(|m| m.as_str().trim());
+
+            doc.params.push(DocParam {
+                name: name.to_string(),
+                is_optional: is_optional || type_.is_some_and(|t| t.ends_with('=')),
+                type_: type_.map(|t| t.trim_end_matches('=').to_string()),
+                description: (!description.is_empty()).then(|| description.to_string()),
+            });
+        } else if !line.starts_with('@') && doc.params.is_empty() {
+            description.push(*line);
+        }
+    }
+
+    let description = description.join("\n").trim().to_string();
+    doc.description = (!description.is_empty()).then_some(description);
+
+    doc
+}
+
+/// Map a TypeScript or `JSDoc` type to a JSON schema type.
+fn json_type(type_: &str) -> &'static str {
+    // Pick the first meaningful member of a union
+    let type_ = split_top_level(type_, '|')
+        .into_iter()
+        .map(str::trim)
+        .find(|t| !t.is_empty() && !matches!(*t, "null" | "undefined"))
+        .unwrap_or("");
+    let lowercase = type_.to_lowercase();
+
+    match lowercase.as_str() {
+        "number" | "float" | "double" => "number",
+        "bigint" | "int" | "integer" => "integer",
+        "boolean" | "bool" | "true" | "false" => "boolean",
+        "object" => "object",
+        t if t.ends_with("[]") || t.starts_with("array") || t.starts_with("readonly ") => "array",
+        t if t.starts_with('{') || t.starts_with("record<") || t.starts_with("map<") => "object",
+        _ => "string",
+    }
+}
+
+fn strip_braces(value: &str) -> Option<&str> {
+    value.trim().strip_prefix('{')?.strip_suffix('}')
+}
+
+/// Split by `separator`, ignoring separators nested in brackets or string literals.
+fn split_top_level(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0_i32;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '(' | '[' | '{' | '<') => depth += 1,
+            // `=>` is not a closing bracket
+            (None, '>') if value[..i].ends_with('=') => {}
+            (None, ')' | ']' | '}' | '>') => depth -= 1,
+            (None, c) if c == separator && depth == 0 && !value[i..].starts_with("=>") => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+
+    parts
+}
+
+fn split_once_top_level(value: &str, separator: char) -> (&str, Option<&str>) {
+    let parts = split_top_level(value, separator);
+    if parts.len() < 2 {
+        return (value, None);
+    }
+
+    let (head, tail) = value.split_at(parts[0].len());
+    (head, Some(&tail[separator.len_utf8()..]))
+}
@@ -0,0 +1,218 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Virtualenvs for Python abilities which declare requirements.
+//!
+//! Virtualenvs are built once per (image, requirements) pair and cached in the app data dir, so
+//! abilities sharing the same requirements share the virtualenv as well.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use tokio::{fs, sync::Mutex};
+use tracing::{debug, info};
+
+use super::{runtimes::PYTHON_IMAGE, Error};
+use crate::{
+    docker::{self, RunParams, Volume},
+    settings::Sandbox,
+    types::{abilities::Language, Result},
+};
+
+/// Virtualenvs are not relocatable, so they're always mounted at the path they were built at.
+const CONTAINER_PATH: &str = "/opt/bridge-venv";
+const READY_MARKER: &str = ".bridge-ready";
+const REQUIREMENTS_FILE: &str = "requirements.txt";
+/// How many trailing lines of installer output to report on failure.
+const INSTALL_LOG_LINES: usize = 30;
+/// Building wheels may take a while, so installs get more CPU time than regular runs.
+const INSTALL_CPU_TIME_LIMIT_SECS: u64 = 600;
+
+lazy_static! {
+    // Building the same virtualenv twice at once would only waste time and break both builds
+    static ref BUILD_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Clone)]
+pub struct Venv {
+    pub path: PathBuf,
+}
+
+impl Venv {
+    #[must_use]
+    pub fn python(&self) -> String {
+        format!("{CONTAINER_PATH}/bin/python")
+    }
+
+    #[must_use]
+    pub fn volume(&self) -> Volume {
+        Volume {
+            host_path: self.path.clone(),
+            container_path: CONTAINER_PATH.to_string(),
+            is_read_only: true,
+        }
+    }
+}
+
+/// Directory virtualenvs are cached in.
+#[must_use]
+pub fn root(data_dir: &Path) -> PathBuf {
+    data_dir.join("venvs")
+}
+
+/// Trim, validate, sort and deduplicate ability requirements.
+///
+/// Every requirement has to be pinned to an exact version, otherwise the same ability could end
+/// up with different packages on different machines.
+///
+/// # Errors
+///
+/// Returns error if requirements are declared for a language other than Python, or if any of
+/// them is not pinned.
+pub fn normalize_requirements(language: Language, requirements: &[String]) -> Result<Vec<String>> {
+    let mut normalized = requirements
+        .iter()
+        .map(|requirement| requirement.trim())
+        .filter(|requirement| !requirement.is_empty())
+        .map(|requirement| {
+            // Options like `--index-url` or `-r` have no place in a requirements list
+            if requirement.starts_with('-') {
+                return Err(Error::InvalidRequirement(requirement.to_string()).into());
+            }
+
+            match requirement.split_once("==") {
+                Some((name, version)) if !name.trim().is_empty() && !version.trim().is_empty() => {
+                    Ok(requirement.to_string())
+                }
+                _ => Err(Error::RequirementNotPinned(requirement.to_string()).into()),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if !normalized.is_empty() && language != Language::Python {
+        return Err(Error::RequirementsNotSupported(language).into());
+    }
+
+    normalized.sort();
+    normalized.dedup();
+
+    Ok(normalized)
+}
+
+/// Get a virtualenv with given requirements installed, building it if needed.
+///
+/// Returns `None` if there are no requirements. Requirements are installed within the limits of
+/// the `sandbox`, except that network is always enabled.
+///
+/// # Errors
+///
+/// Returns `Error::RequirementsInstall` with the installer output if requirements can't be
+/// installed, or other error if there was a problem while running the container.
+pub async fn ensure(
+    venvs_root: &Path,
+    requirements: &[String],
+    sandbox: &Sandbox,
+) -> Result<Option<Venv>> {
+    if requirements.is_empty() {
+        return Ok(None);
+    }
+
+    // Virtualenvs are bound to the interpreter they were built with, so a new image means a new
+    // virtualenv
+    let image_id = docker::image_id(PYTHON_IMAGE).await?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(image_id.as_bytes());
+    for requirement in requirements {
+        hasher.update(b"\n");
+        hasher.update(requirement.as_bytes());
+    }
+    let key = hex::encode(hasher.finalize());
+
+    let venv = Venv {
+        path: venvs_root.join(&key[..16]),
+    };
+
+    if venv.path.join(READY_MARKER).exists() {
+        return Ok(Some(venv));
+    }
+
+    let _lock = BUILD_LOCK.lock().await;
+
+    // Someone could've built it while we were waiting
+    if venv.path.join(READY_MARKER).exists() {
+        return Ok(Some(venv));
+    }
+
+    build(&venv, requirements, sandbox).await?;
+
+    Ok(Some(venv))
+}
+
+async fn build(venv: &Venv, requirements: &[String], sandbox: &Sandbox) -> Result<()> {
+    info!("Building virtualenv for requirements: {:?}", requirements);
+
+    // Clean up after a previous failed build
+    if venv.path.exists() {
+        fs::remove_dir_all(&venv.path)
+            .await
+            .with_context(|| "Failed to remove incomplete virtualenv")?;
+    }
+
+    fs::create_dir_all(&venv.path)
+        .await
+        .with_context(|| "Failed to create virtualenv dir")?;
+    fs::write(
+        venv.path.join(REQUIREMENTS_FILE),
+        requirements.join("\n") + "\n",
+    )
+    .await
+    .with_context(|| "Failed to write requirements file")?;
+
+    let script = format!(
+        "python -m venv {CONTAINER_PATH} && \
+         {CONTAINER_PATH}/bin/pip install --no-input --disable-pip-version-check \
+         --no-cache-dir -r {CONTAINER_PATH}/{REQUIREMENTS_FILE}"
+    );
+    let output = docker::run(
+        RunParams {
+            image: PYTHON_IMAGE,
+            volumes: &[Volume {
+                host_path: venv.path.clone(),
+                container_path: CONTAINER_PATH.to_string(),
+                is_read_only: false,
+            }],
+            cmd: vec!["sh".to_string(), "-c".to_string(), script],
+            ..Default::default()
+        },
+        &Sandbox {
+            cpu_time_limit_secs: sandbox.cpu_time_limit_secs.max(INSTALL_CPU_TIME_LIMIT_SECS),
+            is_network_enabled: true,
+            ..sandbox.clone()
+        },
+    )
+    .await?;
+
+    debug!("Virtualenv build output: {:?}", output);
+
+    if output.exit_code != 0 {
+        fs::remove_dir_all(&venv.path)
+            .await
+            .with_context(|| "Failed to remove failed virtualenv")?;
+
+        // pip puts the actual reason at the very end
+        let log = format!("{}\n{}", output.stdout.trim(), output.stderr.trim());
+        let lines = log.trim().lines().collect::<Vec<_>>();
+        let tail = lines[lines.len().saturating_sub(INSTALL_LOG_LINES)..].join("\n");
+
+        return Err(Error::RequirementsInstall(tail).into());
+    }
+
+    fs::write(venv.path.join(READY_MARKER), "")
+        .await
+        .with_context(|| "Failed to mark virtualenv as ready")?;
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Headless Bridge, serving the commands over HTTP and streaming events over WebSocket, with the
+//! task executor running the same way it does in the desktop app.
+//!
+//! Configured with the environment:
+//! - `BRIDGE_SERVER_ADDR`: address to listen on, `127.0.0.1:8765` by default;
+//! - `BRIDGE_SERVER_TOKEN`: token the clients have to present, none by default;
+//! - `BRIDGE_DATA_DIR`: app local data dir, the one of the desktop app by default;
+//! - `BRIDGE_SECRETS_PASSPHRASE`: passphrase the secrets encryption key is derived from, required
+//!   when there is no OS keyring to keep the key in, as is usual for a headless server.
+
+use std::{env, net::SocketAddr};
+
+use anyhow::Context;
+use dotenvy::dotenv;
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt, EnvFilter};
+
+use bridge::{
+    errors::Error, secrets, server::Server, state, task_executor, types::Result, webhooks,
+};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8765";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+
+    let format = fmt::format();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .event_format(format)
+        .init();
+
+    let addr: SocketAddr = env::var("BRIDGE_SERVER_ADDR")
+        .unwrap_or_else(|_| DEFAULT_ADDR.to_string())
+        .parse()
+        .context("Failed to parse `BRIDGE_SERVER_ADDR`")?;
+    let token = env::var("BRIDGE_SERVER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if token.is_none() && !addr.ip().is_loopback() {
+        warn!("`BRIDGE_SERVER_TOKEN` is not set, anyone who can reach {addr} can use the server");
+    }
+
+    let app_local_data_dir = state::resolve_app_local_data_dir()?;
+
+    info!("Starting Bridge server...");
+    let loaded = state::load(&app_local_data_dir).await.map_err(|err| {
+        if matches!(err, Error::Secrets(secrets::Error::NoKeyStorage)) {
+            error!(
+                "There is no OS keyring to keep the secrets encryption key in. Set \
+                `BRIDGE_SECRETS_PASSPHRASE` to derive the key from a passphrase instead, the same \
+                one on every install sharing the database."
+            );
+        }
+
+        err
+    })?;
+    let server = Server::new(loaded, app_local_data_dir, token);
+
+    webhooks::resume(server.state()).await?;
+    task_executor::start_loop(server.state()).await;
+
+    server.serve(addr).await
+}
@@ -1,47 +1,119 @@
 // Copyright 2024 StarfleetAI
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Context;
-use async_trait::async_trait;
-use bridge_common::{
-    channel::{Emitter, Event},
-    types::Result,
+//! Sinks the events end up in, one per kind of client: the frontend of the desktop app, the
+//! WebSocket clients of `bridge-server`, and the terminal of the CLI.
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
 };
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::{json, Value};
 use tauri::{AppHandle, Manager};
-use tracing::{debug, instrument, trace};
+use tokio::sync::broadcast;
+
+use crate::notifications::Notifier;
+
+/// Event ready to be sent, as `{"seq": ..., "event": ..., "data": ...}`.
+#[derive(Serialize, Debug)]
+pub struct Emitted {
+    #[serde(skip)]
+    pub name: &'static str,
+    /// Sequence number of the event in the event log, `null` if it's not logged.
+    pub seq: Option<i64>,
+    #[serde(flatten)]
+    pub payload: Value,
+}
+
+/// Where the events are sent to.
+pub trait Sink: Send + Sync {
+    /// Send the event.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the event can't be sent.
+    fn send(&self, event: &Emitted) -> anyhow::Result<()>;
+}
 
+/// Sends events to every window of the desktop app, notifying about the tasks needing attention.
 #[allow(clippy::module_name_repetitions)]
-pub struct TauriChannel(AppHandle);
+pub struct TauriChannel {
+    app_handle: AppHandle,
+    notifier: Arc<Notifier>,
+}
 
-#[async_trait]
-impl Emitter for TauriChannel {
-    #[instrument(skip(self, event))]
-    async fn emit<'a>(&self, _user_id: i32, event: Event<'a>) -> Result<()> {
-        debug!("Emitting event");
-        trace!("Event: {:?}", event);
+impl Sink for TauriChannel {
+    fn send(&self, event: &Emitted) -> anyhow::Result<()> {
+        self.notifier.observe(event);
 
-        self.0
-            .emit_all(&Self::event_name(&event), &event)
-            .context("Failed to emit event")?;
+        self.app_handle
+            .emit_all(event.name, event)
+            .context("Failed to emit event")
+    }
+}
+
+impl TauriChannel {
+    #[must_use]
+    pub fn new(app_handle: AppHandle, notifier: Arc<Notifier>) -> Self {
+        Self {
+            app_handle,
+            notifier,
+        }
+    }
+}
+
+/// Sends events to every subscriber as `{"name": ..., "payload": ...}` JSON, e.g. to the
+/// WebSocket clients of `bridge-server`.
+#[allow(clippy::module_name_repetitions)]
+pub struct BroadcastChannel(broadcast::Sender<String>);
+
+impl Sink for BroadcastChannel {
+    fn send(&self, event: &Emitted) -> anyhow::Result<()> {
+        let message = serde_json::to_string(&json!({
+            "name": event.name,
+            "payload": event,
+        }))
+        .context("Failed to serialize event")?;
+
+        // Nobody may be listening, which is fine
+        let _ = self.0.send(message);
 
         Ok(())
     }
 }
 
-impl TauriChannel {
+impl BroadcastChannel {
     #[must_use]
-    pub fn new(app_handle: AppHandle) -> Self {
-        Self(app_handle)
+    pub fn new(sender: broadcast::Sender<String>) -> Self {
+        Self(sender)
     }
+}
 
-    fn event_name(event: &Event) -> String {
-        match event {
-            Event::ChatUpdated(_) => "chats:updated".to_string(),
-            Event::TaskCreated(_) => "tasks:created".to_string(),
-            Event::TaskUpdated(_) => "tasks:updated".to_string(),
-            Event::MessageCreated(_) => "messages:created".to_string(),
-            Event::MessageUpdated(_) => "messages:updated".to_string(),
-            Event::TaskResultCreated(_) => "task_results:created".to_string(),
+/// Prints to stdout what assistants write, as it's streamed. Used by the CLI, which reports the
+/// other events on its own.
+#[derive(Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct TerminalChannel;
+
+impl Sink for TerminalChannel {
+    fn send(&self, event: &Emitted) -> anyhow::Result<()> {
+        if event.name != "messages:delta" {
+            return Ok(());
+        }
+
+        let delta = &event.payload["data"];
+        let mut stdout = io::stdout().lock();
+        if let Some(content) = delta["delta"].as_str() {
+            write!(stdout, "{content}").context("Failed to print message")?;
         }
+        if delta["is_done"].as_bool().unwrap_or_default() {
+            writeln!(stdout).context("Failed to print message")?;
+        }
+        stdout.flush().context("Failed to print message")?;
+
+        Ok(())
     }
 }
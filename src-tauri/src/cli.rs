@@ -0,0 +1,483 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Command-line client, run by the `bridge` binary when it's given a command instead of starting
+//! the app.
+//!
+//! It works with the same database and app local data dir as the app, within the active
+//! workspace, calling the same commands. Data is printed as JSON, so it can be piped to `jq`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Read},
+    iter,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use bridge_common::{
+    repo,
+    types::{
+        messages::Status as MessageStatus,
+        pagination::Pagination,
+        tasks::{Status, Task},
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::time::sleep;
+
+use crate::{
+    channel::TerminalChannel,
+    commands::{self, chats::CreateChat, messages::CreateMessage, tasks::CreateTask},
+    events::Events,
+    state::{self, Owned, State},
+    task_executor,
+    types::Result,
+    webhooks,
+};
+
+const USAGE: &str = "\
+Usage: bridge [<command>]
+
+Starts the app if no command is given. Commands work within the active workspace.
+
+Commands:
+  tasks list [--page <page>] [--per-page <count>]
+  tasks create --agent <id> --title <title> [--summary <summary>]
+  tasks execute <id> [--detach]   Execute the task here, printing its progress until it's
+                                  finished, or only queue it for the app with `--detach`
+  tasks tail <id>                 Print the progress of the task until it's finished
+  tasks results <id>
+  chats send (--agent <id> | --chat <id>) [<text>]
+                                  Send the message to a new chat with the agent, or to the chat,
+                                  and print the reply. The text is read from stdin if not given
+  agents list
+  agents create <file>            Create the agent described in the JSON file, `-` for stdin
+  agents update <file>
+  agents delete <id>
+  abilities list
+  abilities create <file>
+  abilities update <file>
+  abilities delete <id>
+  help
+
+Environment:
+  BRIDGE_DATA_DIR                 App local data dir, the one of the app by default
+";
+
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("abilities", &["list", "create", "update", "delete"]),
+    ("agents", &["list", "create", "update", "delete"]),
+    ("chats", &["send"]),
+    ("help", &[""]),
+    ("tasks", &["list", "create", "execute", "tail", "results"]),
+];
+/// Options which take no value.
+const SWITCHES: &[&str] = &["--detach"];
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the arguments the binary is started with are a command rather than something to be
+/// passed to the app.
+#[must_use]
+pub fn is_command(args: &[String]) -> bool {
+    args.first()
+        .is_some_and(|group| COMMANDS.iter().any(|(name, _)| name == group))
+}
+
+/// Run the command.
+///
+/// # Errors
+///
+/// Returns error if the command is unknown or malformed, if it fails, or if the task it executes
+/// fails.
+pub async fn run(args: &[String]) -> Result<()> {
+    let group = args.first().map_or("help", String::as_str);
+    let action = args.get(1).map_or("", String::as_str);
+
+    let is_known = COMMANDS
+        .iter()
+        .any(|(name, actions)| *name == group && actions.contains(&action));
+    if !is_known {
+        return Err(anyhow!("Unknown command `{group} {action}`, see `bridge help`").into());
+    }
+
+    if group == "help" {
+        print!("{USAGE}");
+        return Ok(());
+    }
+
+    let args = Args::parse(args.get(2..).unwrap_or_default())?;
+
+    let app_local_data_dir = state::resolve_app_local_data_dir()?;
+    let loaded = state::load(&app_local_data_dir).await?;
+    let (events, queue) = Events::new(Box::new(TerminalChannel), loaded.pool.clone());
+    let state = Owned::new(loaded, events, app_local_data_dir);
+    // Deliveries still being retried when the command is done are resumed by the app
+    webhooks::start(&state, queue);
+
+    match (group, action) {
+        ("tasks", "list") => list_tasks(&state, &args).await,
+        ("tasks", "create") => create_task(&state, &args).await,
+        ("tasks", "execute") => execute_task(&state, &args).await,
+        ("tasks", "tail") => tail_task(&state, &args).await,
+        ("tasks", "results") => list_task_results(&state, &args).await,
+        ("chats", "send") => send_message(&state, &args).await,
+        ("agents", "list") => print_json(
+            &commands::agents::list_agents(State::new(&state.pool), State::new(&state.workspace))
+                .await?,
+        ),
+        ("agents", "create") => print_json(
+            &commands::agents::create_agent(
+                args.json()?,
+                State::new(&state.pool),
+                State::new(&state.events),
+                State::new(&state.workspace),
+            )
+            .await?,
+        ),
+        ("agents", "update") => print_json(
+            &commands::agents::update_agent(
+                args.json()?,
+                State::new(&state.pool),
+                State::new(&state.events),
+                State::new(&state.workspace),
+            )
+            .await?,
+        ),
+        ("agents", "delete") => {
+            commands::agents::delete_agent(
+                args.id()?,
+                State::new(&state.pool),
+                State::new(&state.events),
+                State::new(&state.workspace),
+            )
+            .await
+        }
+        ("abilities", "list") => print_json(
+            &commands::abilities::list_abilities(
+                State::new(&state.pool),
+                State::new(&state.workspace),
+            )
+            .await?,
+        ),
+        ("abilities", "create") => print_json(
+            &commands::abilities::create_ability(
+                args.json()?,
+                State::new(&state.pool),
+                State::new(&state.events),
+                State::new(&state.settings),
+                State::new(&state.app_local_data_dir),
+                State::new(&state.workspace),
+            )
+            .await?,
+        ),
+        ("abilities", "update") => print_json(
+            &commands::abilities::update_ability(
+                args.json()?,
+                State::new(&state.pool),
+                State::new(&state.events),
+                State::new(&state.settings),
+                State::new(&state.app_local_data_dir),
+                State::new(&state.workspace),
+            )
+            .await?,
+        ),
+        ("abilities", "delete") => {
+            commands::abilities::delete_ability(
+                args.id()?,
+                State::new(&state.pool),
+                State::new(&state.events),
+                State::new(&state.workspace),
+            )
+            .await
+        }
+        _ => unreachable!("command `{group} {action}` is known but not handled"),
+    }
+}
+
+async fn list_tasks(state: &Owned, args: &Args<'_>) -> Result<()> {
+    let pagination = Pagination {
+        page: args.number("--page")?.unwrap_or(1),
+        per_page: args.number("--per-page")?.unwrap_or(20),
+    };
+
+    print_json(
+        &commands::tasks::list_root_tasks(
+            State::new(&state.pool),
+            pagination,
+            State::new(&state.workspace),
+        )
+        .await?,
+    )
+}
+
+async fn create_task(state: &Owned, args: &Args<'_>) -> Result<()> {
+    let request = CreateTask {
+        agent_id: args.required_number("--agent")?,
+        title: args.required("--title")?.to_string(),
+        summary: args.option("--summary").map(ToString::to_string),
+        ancestry: None,
+        status: Status::Draft,
+    };
+
+    print_json(
+        &commands::tasks::create_task(
+            request,
+            State::new(&state.pool),
+            State::new(&state.workspace),
+        )
+        .await?,
+    )
+}
+
+async fn execute_task(state: &Arc<Owned>, args: &Args<'_>) -> Result<()> {
+    let id = args.id()?;
+    let task =
+        commands::tasks::execute_task(id, State::new(&state.pool), State::new(&state.workspace))
+            .await?;
+
+    if args.has("--detach") {
+        return print_json(&task);
+    }
+
+    // Root tasks are executed in queue order, so the ones ahead of this one are executed first
+    let mut progress = Progress::default();
+    loop {
+        let task = progress.print(state, id).await?;
+        if is_finished(&task) {
+            return finish(&task);
+        }
+
+        if !task_executor::execute_next(state).await {
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+async fn tail_task(state: &Owned, args: &Args<'_>) -> Result<()> {
+    let id = args.id()?;
+
+    let mut progress = Progress::default();
+    loop {
+        let task = progress.print(state, id).await?;
+        if is_finished(&task) {
+            return finish(&task);
+        }
+
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn list_task_results(state: &Owned, args: &Args<'_>) -> Result<()> {
+    print_json(
+        &commands::task_results::list_task_results(
+            State::new(&state.pool),
+            args.id()?,
+            State::new(&state.workspace),
+        )
+        .await?,
+    )
+}
+
+async fn send_message(state: &Owned, args: &Args<'_>) -> Result<()> {
+    let chat_id = match (args.number("--chat")?, args.number("--agent")?) {
+        (Some(chat_id), _) => chat_id,
+        (None, Some(agent_id)) => {
+            let chat = commands::chats::create_chat(
+                CreateChat { agent_id },
+                State::new(&state.pool),
+                State::new(&state.workspace),
+            )
+            .await?;
+            eprintln!("Chat #{}", chat.id);
+
+            chat.id
+        }
+        (None, None) => return Err(anyhow!("Either `--agent` or `--chat` is required").into()),
+    };
+
+    let text = if args.positional.is_empty() {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read message from stdin")?;
+        text
+    } else {
+        args.positional.join(" ")
+    };
+
+    // The reply is printed by the channel as it's streamed
+    commands::messages::create_message(
+        CreateMessage { chat_id, text },
+        State::new(&state.channel),
+        State::new(&state.pool),
+        State::new(&state.settings),
+        State::new(&state.app_local_data_dir),
+        State::new(&state.workspace),
+    )
+    .await?;
+
+    let last_message =
+        repo::messages::get_last_message(&state.pool, state.workspace.id(), chat_id).await?;
+    if let Some(message) = last_message {
+        if message.status == MessageStatus::WaitingForToolCall {
+            eprintln!(
+                "Message #{} waits for its tool calls to be approved in the app",
+                message.id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Statuses and results of the task and its children printed so far.
+#[derive(Default)]
+struct Progress {
+    statuses: HashMap<i32, Status>,
+    results: HashSet<i32>,
+}
+
+impl Progress {
+    /// Print what has changed since the last time, returning the task.
+    async fn print(&mut self, state: &Owned, id: i32) -> Result<Task> {
+        let cid = state.workspace.id();
+        let task = repo::tasks::get(&state.pool, cid, id).await?;
+        let ancestry = task.children_ancestry();
+        let children = repo::tasks::list_all_children(&state.pool, cid, &ancestry).await?;
+
+        for task in iter::once(&task).chain(&children) {
+            if self.statuses.insert(task.id, task.status) != Some(task.status) {
+                println!("Task #{} `{}`: {}", task.id, task.title, task.status);
+            }
+
+            for result in repo::task_results::list(&state.pool, cid, task.id).await? {
+                if self.results.insert(result.id) {
+                    println!(
+                        "Result of task #{} ({:?}): {}",
+                        task.id, result.kind, result.data
+                    );
+                }
+            }
+        }
+
+        Ok(task)
+    }
+}
+
+fn is_finished(task: &Task) -> bool {
+    !matches!(task.status, Status::ToDo | Status::InProgress)
+}
+
+fn finish(task: &Task) -> Result<()> {
+    match task.status {
+        Status::Failed => Err(anyhow!("Task #{} failed", task.id).into()),
+        Status::WaitingForUser => {
+            eprintln!("Task #{} waits for user input in the app", task.id);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).context("Failed to serialize output")?;
+    println!("{json}");
+
+    Ok(())
+}
+
+/// Positional arguments and `--name value` options of a command.
+struct Args<'a> {
+    positional: Vec<&'a str>,
+    options: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(args: &'a [String]) -> Result<Self> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+
+        let mut args = args.iter().map(String::as_str);
+        while let Some(arg) = args.next() {
+            if SWITCHES.contains(&arg) {
+                options.insert(arg, "");
+            } else if arg.starts_with("--") {
+                let value = args
+                    .next()
+                    .with_context(|| format!("`{arg}` requires a value"))?;
+                options.insert(arg, value);
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn option(&self, name: &str) -> Option<&'a str> {
+        self.options.get(name).copied()
+    }
+
+    fn required(&self, name: &str) -> Result<&'a str> {
+        Ok(self
+            .option(name)
+            .with_context(|| format!("`{name}` is required"))?)
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>> {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("`{name}` must be a number, got `{value}`").into())
+            })
+            .transpose()
+    }
+
+    fn required_number<T: std::str::FromStr>(&self, name: &str) -> Result<T> {
+        Ok(self
+            .number(name)?
+            .with_context(|| format!("`{name}` is required"))?)
+    }
+
+    /// The id given as the first positional argument.
+    fn id(&self) -> Result<i32> {
+        let id = self
+            .positional
+            .first()
+            .context("Id is required, see `bridge help`")?;
+
+        Ok(id
+            .parse()
+            .with_context(|| format!("Id must be a number, got `{id}`"))?)
+    }
+
+    /// The JSON file given as the first positional argument, `-` meaning stdin.
+    fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        let path = self
+            .positional
+            .first()
+            .context("File is required, see `bridge help`")?;
+
+        let content = if *path == "-" {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read stdin")?;
+            content
+        } else {
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read `{path}`"))?
+        };
+
+        Ok(serde_json::from_str(&content).with_context(|| format!("Failed to parse `{path}`"))?)
+    }
+}
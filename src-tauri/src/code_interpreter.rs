@@ -0,0 +1,190 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Running the code blocks of execution chats in the sandbox.
+//!
+//! The agent marks the code blocks of its messages to be executed or saved into the task workdir.
+//! The task executor hands them to the [`Interpreter`], which runs each of them in the sandbox and
+//! describes the outcome for the code interpreter message.
+
+use std::{
+    path::{Component, Path},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use markdown::mdast::Node;
+use tokio::fs;
+
+use crate::{
+    abilities::runtimes::PYTHON_IMAGE,
+    docker::{self, Output, RunParams},
+    settings::Sandbox,
+    types::Result,
+};
+
+/// Function the agent calls the code interpreter with. Decisions on the code blocks of a message
+/// without tool calls are recorded under its name, as if it was called.
+pub const FUNCTION_NAME: &str = "sfai_code_interpreter";
+
+pub struct Interpreter<'a> {
+    sandbox: &'a Sandbox,
+    timeout: Duration,
+}
+
+impl<'a> Interpreter<'a> {
+    #[must_use]
+    pub fn new(sandbox: &'a Sandbox, timeout: Duration) -> Self {
+        Self { sandbox, timeout }
+    }
+
+    /// Run or save the code blocks in the workdir one by one, describing the outcome of each.
+    pub async fn interpret(&self, workdir: &Path, code_blocks: Vec<CodeBlock>) -> String {
+        let mut results = Vec::with_capacity(code_blocks.len());
+
+        for code_block in code_blocks {
+            let result = match &code_block.filename {
+                Some(filename) => save(workdir, filename, &code_block.code).await,
+                None => self.run(workdir, &code_block).await,
+            };
+
+            results.push(format!("```\n{result}\n```"));
+        }
+
+        results.join("\n\n")
+    }
+
+    async fn run(&self, workdir: &Path, code_block: &CodeBlock) -> String {
+        let cmd = match code_block.language.as_str() {
+            "sh" | "shell" => vec!["sh".to_string(), "-c".to_string(), code_block.code.clone()],
+            "python" => vec![
+                "python".to_string(),
+                "-c".to_string(),
+                code_block.code.clone(),
+            ],
+            language => {
+                return format!("Error: language `{language}` is not supported for code execution")
+            }
+        };
+
+        match docker::run(
+            RunParams {
+                image: PYTHON_IMAGE,
+                workdir: Some(workdir),
+                cmd,
+                timeout: Some(self.timeout),
+                ..Default::default()
+            },
+            self.sandbox,
+        )
+        .await
+        {
+            Ok(output) => describe(&output, self.timeout),
+            Err(err) => format!("Failed to interpret code: {err}"),
+        }
+    }
+}
+
+/// Code block marked to be executed or saved, the same way `bridge_common` marks them: with a
+/// `> Execute` or a ``> Save: `filename` `` quote right before the block.
+#[derive(Debug, Default)]
+pub struct CodeBlock {
+    pub code: String,
+    pub language: String,
+    /// File to save the code to, `None` if it's to be executed.
+    pub filename: Option<String>,
+}
+
+/// Code blocks of the message content marked to be executed or saved.
+///
+/// # Errors
+///
+/// Returns error if the content can't be parsed as markdown.
+pub fn parse(content: &str) -> Result<Vec<CodeBlock>> {
+    let ast = markdown::to_mdast(content, &markdown::ParseOptions::default())
+        .map_err(|err| anyhow!("Failed to parse markdown AST: {err}"))?;
+
+    let mut code_blocks = Vec::new();
+    // Filename to save the next code block to, `Some(None)` if it's to be executed
+    let mut action = None;
+
+    for node in ast.children().into_iter().flatten() {
+        match node {
+            Node::BlockQuote(blockquote) => {
+                let [Node::Paragraph(paragraph)] = blockquote.children.as_slice() else {
+                    continue;
+                };
+
+                match paragraph.children.as_slice() {
+                    [Node::Text(text)] if text.value.trim().eq_ignore_ascii_case("execute") => {
+                        action = Some(None);
+                    }
+                    [Node::Text(text), Node::InlineCode(filename)]
+                        if text.value.trim().eq_ignore_ascii_case("save:") =>
+                    {
+                        action = Some(Some(filename.value.clone()));
+                    }
+                    _ => {}
+                }
+            }
+            Node::Code(code) => {
+                if let Some(filename) = action.take() {
+                    code_blocks.push(CodeBlock {
+                        code: code.value.clone(),
+                        language: code.lang.clone().unwrap_or_default(),
+                        filename,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(code_blocks)
+}
+
+/// Save the code block into the workdir, which is the only place it's allowed to go.
+async fn save(workdir: &Path, filename: &str, code: &str) -> String {
+    let path = Path::new(filename);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return format!("Failed to save file `{filename}`: the path must be inside the workdir");
+    }
+
+    match fs::write(workdir.join(path), code).await {
+        Ok(()) => format!("File `{filename}` has been saved"),
+        Err(err) => format!("Failed to save file `{filename}`: {err}"),
+    }
+}
+
+fn describe(output: &Output, timeout: Duration) -> String {
+    let truncated = if output.is_truncated {
+        "[output truncated]".to_string()
+    } else {
+        String::new()
+    };
+
+    let stopped = if output.is_timed_out {
+        format!(
+            "[timed out after {}s, the process was killed]",
+            timeout.as_secs()
+        )
+    } else if output.exit_code != 0 {
+        format!("[exited with code {}]", output.exit_code)
+    } else {
+        String::new()
+    };
+
+    [
+        output.stdout.trim(),
+        output.stderr.trim(),
+        &truncated,
+        &stopped,
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
@@ -3,16 +3,26 @@
 
 #![allow(clippy::used_underscore_binding)]
 
-use anyhow::Context;
-use bridge_common::{
-    abilities::{get_function_definition, preprocess_code},
-    repo,
-    types::abilities::Ability,
-};
+use std::{num::NonZeroU16, time::Duration};
+
+use anyhow::{anyhow, Context};
+use bridge_common::{abilities::preprocess_code, repo};
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use serde_json::Value;
+use tokio::sync::RwLock;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    abilities::{get_function_definition, venvs, Execution},
+    events::{AppEvent, Deleted, Events},
+    repo::abilities::{CreateParams, UpdateParams},
+    settings::{Sandbox, Settings},
+    state::{AppLocalDataDir, State},
+    types::{
+        abilities::{Ability, Language},
+        DbPool, Result,
+    },
+    workspaces::ActiveWorkspace,
+};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -25,14 +35,24 @@ pub struct CreateAbility {
     pub name: String,
     pub description: String,
     pub code: String,
+    #[serde(default)]
+    pub language: Language,
+    /// Explicitly declared parameters schema. Extracted from the code if not set.
+    #[serde(default)]
+    pub parameters_json: Option<Value>,
+    /// Pinned Python requirements, e.g. `pandas==2.2.2`.
+    #[serde(default)]
+    pub requirements: Vec<String>,
+    /// Wall time a single run may take, overriding the global tool call timeout.
+    #[serde(default)]
+    pub timeout_secs: Option<NonZeroU16>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpdateAbility {
     pub id: i32,
-    pub name: String,
-    pub description: String,
-    pub code: String,
+    #[serde(flatten)]
+    pub ability: CreateAbility,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -47,8 +67,12 @@ pub struct GetFunctionParameters {
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-pub async fn list_abilities(pool: State<'_, DbPool>) -> Result<AbilitiesList> {
-    let abilities = repo::abilities::list(&*pool, crate::CID).await?;
+pub async fn list_abilities(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<AbilitiesList> {
+    let cid = workspace.id();
+    let abilities = crate::repo::abilities::list(&*pool, cid).await?;
 
     Ok(AbilitiesList { abilities })
 }
@@ -57,58 +81,54 @@ pub async fn list_abilities(pool: State<'_, DbPool>) -> Result<AbilitiesList> {
 ///
 /// # Errors
 ///
-/// Returns error if there was a problem while inserting new ability.
+/// Returns error if requirements are not pinned or can't be installed, or if there was a problem
+/// while inserting new ability.
 #[tauri::command]
-pub async fn create_ability(request: CreateAbility, pool: State<'_, DbPool>) -> Result<Ability> {
-    let code = preprocess_code(&request.code);
-
-    let parameters_json = get_function_definition(&code)
-        .await
-        .with_context(|| format!("Failed to get function parameters for code: {code}"))?;
-
-    let ability = repo::abilities::create(
-        &*pool,
-        crate::CID,
-        repo::abilities::CreateParams {
-            name: request.name,
-            description: request.description,
-            code,
-            parameters_json,
-        },
+pub async fn create_ability(
+    request: CreateAbility,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    settings: State<'_, RwLock<Settings>>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Ability> {
+    save(
+        None,
+        request,
+        &pool,
+        &events,
+        &settings,
+        &app_local_data_dir,
+        workspace.id(),
     )
-    .await?;
-
-    Ok(ability)
+    .await
 }
 
 /// Update ability by id.
 ///
 /// # Errors
 ///
-/// Returns error if ability with given id does not exist or there was an error
-/// while accessing database.
+/// Returns error if ability with given id does not exist, if requirements are not pinned or can't
+/// be installed, or if there was an error while accessing database.
 #[tauri::command]
-pub async fn update_ability(request: UpdateAbility, pool: State<'_, DbPool>) -> Result<Ability> {
-    let code = preprocess_code(&request.code);
-
-    let parameters_json = get_function_definition(&code)
-        .await
-        .with_context(|| format!("Failed to get function parameters for code: {code}"))?;
-
-    let ability = repo::abilities::update(
-        &*pool,
-        crate::CID,
-        repo::abilities::UpdateParams {
-            id: request.id,
-            name: request.name,
-            description: request.description,
-            code,
-            parameters_json,
-        },
+pub async fn update_ability(
+    request: UpdateAbility,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    settings: State<'_, RwLock<Settings>>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Ability> {
+    save(
+        Some(request.id),
+        request.ability,
+        &pool,
+        &events,
+        &settings,
+        &app_local_data_dir,
+        workspace.id(),
     )
-    .await?;
-
-    Ok(ability)
+    .await
 }
 
 /// Delete ability by id.
@@ -117,13 +137,19 @@ pub async fn update_ability(request: UpdateAbility, pool: State<'_, DbPool>) ->
 ///
 /// Returns error if ability with given id does not exist.
 #[tauri::command]
-pub async fn delete_ability(id: i32, pool: State<'_, DbPool>) -> Result<()> {
+pub async fn delete_ability(
+    id: i32,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
         .with_context(|| "Failed to begin transaction")?;
 
-    let agents_count = repo::agent_abilities::get_agents_count(&mut *tx, crate::CID, id).await?;
+    let agents_count = repo::agent_abilities::get_agents_count(&mut *tx, cid, id).await?;
 
     if agents_count > 0 {
         return Err(crate::errors::Error::from(
@@ -131,11 +157,144 @@ pub async fn delete_ability(id: i32, pool: State<'_, DbPool>) -> Result<()> {
         ));
     }
 
-    repo::abilities::delete(&mut *tx, crate::CID, id).await?;
+    repo::abilities::delete(&mut *tx, cid, id).await?;
 
     tx.commit()
         .await
         .with_context(|| "Failed to commit transaction")?;
 
-    Ok(())
+    events
+        .emit_app(cid, AppEvent::AbilityDeleted(Deleted::new(id)))
+        .await
+}
+
+/// Run ability with given arguments in an isolated workdir, the way it's run for a tool call.
+///
+/// Arguments are validated against ability parameters before running.
+///
+/// # Errors
+///
+/// Returns error if ability with given id does not exist, if arguments are not valid, or if
+/// there was a problem while running the ability.
+#[tauri::command]
+pub async fn test_ability(
+    id: i32,
+    arguments_json: String,
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Execution> {
+    let cid = workspace.id();
+    let ability = crate::repo::abilities::get(&*pool, cid, id).await?;
+    let arguments =
+        serde_json::from_str(&arguments_json).with_context(|| "Failed to parse arguments JSON")?;
+
+    let (sandbox, default_timeout) = {
+        let settings = settings.read().await;
+
+        (
+            settings.sandbox.clone(),
+            Duration::from_secs(settings.tool_calls.timeout_secs),
+        )
+    };
+
+    crate::abilities::test(
+        &ability,
+        &arguments,
+        &app_local_data_dir,
+        &sandbox,
+        default_timeout,
+    )
+    .await
+}
+
+/// Validate the ability and save it, creating it if `id` is not set.
+///
+/// Requirements are installed before the ability is saved, so install errors surface right away.
+async fn save(
+    id: Option<i32>,
+    request: CreateAbility,
+    pool: &DbPool,
+    events: &Events,
+    settings: &RwLock<Settings>,
+    app_local_data_dir: &AppLocalDataDir,
+    cid: i32,
+) -> Result<Ability> {
+    let code = preprocess_code(&request.code);
+    let sandbox = settings.read().await.sandbox.clone();
+    let requirements = venvs::normalize_requirements(request.language, &request.requirements)?;
+    let venv = venvs::ensure(&venvs::root(app_local_data_dir), &requirements, &sandbox).await?;
+    let parameters_json = parameters_json_for(
+        request.language,
+        &code,
+        request.parameters_json,
+        venv.as_ref(),
+        &sandbox,
+    )
+    .await?;
+    let timeout_secs = request.timeout_secs.map(|secs| i32::from(secs.get()));
+
+    if let Some(id) = id {
+        let ability = crate::repo::abilities::update(
+            pool,
+            cid,
+            UpdateParams {
+                id,
+                name: request.name,
+                description: request.description,
+                code,
+                parameters_json,
+                language: request.language,
+                requirements,
+                timeout_secs,
+            },
+        )
+        .await?;
+        events
+            .emit_app(cid, AppEvent::AbilityUpdated(&ability))
+            .await?;
+
+        Ok(ability)
+    } else {
+        let ability = crate::repo::abilities::create(
+            pool,
+            cid,
+            CreateParams {
+                name: request.name,
+                description: request.description,
+                code,
+                parameters_json,
+                language: request.language,
+                requirements,
+                timeout_secs,
+            },
+        )
+        .await?;
+        events
+            .emit_app(cid, AppEvent::AbilityCreated(&ability))
+            .await?;
+
+        Ok(ability)
+    }
+}
+
+async fn parameters_json_for(
+    language: Language,
+    code: &str,
+    declared: Option<Value>,
+    venv: Option<&venvs::Venv>,
+    sandbox: &Sandbox,
+) -> Result<Value> {
+    if let Some(parameters_json) = declared {
+        if !parameters_json["name"].is_string() {
+            return Err(anyhow!("Declared parameters schema must have a function `name`").into());
+        }
+
+        return Ok(parameters_json);
+    }
+
+    Ok(get_function_definition(language, code, venv, sandbox)
+        .await
+        .with_context(|| format!("Failed to get function parameters for code: {code}"))?)
 }
@@ -0,0 +1,221 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::{
+    abilities::Execution,
+    repo::{self, ability_test_cases::CreateParams, ability_test_cases::UpdateParams},
+    settings::Settings,
+    state::{AppLocalDataDir, State},
+    types::{ability_test_cases::AbilityTestCase, DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AbilityTestCasesList {
+    pub ability_test_cases: Vec<AbilityTestCase>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateAbilityTestCase {
+    pub ability_id: i32,
+    pub name: String,
+    pub arguments_json: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateAbilityTestCase {
+    pub id: i32,
+    pub name: String,
+    pub arguments_json: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AbilityTestCaseRun {
+    pub ability_test_case_id: i32,
+    pub run: Option<Execution>,
+    /// Set if the test case could not be run, e.g. its arguments no longer match the ability.
+    pub error: Option<String>,
+}
+
+/// List test cases for ability.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_ability_test_cases(
+    ability_id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<AbilityTestCasesList> {
+    let cid = workspace.id();
+    let ability_test_cases =
+        repo::ability_test_cases::list_for_ability(&*pool, cid, ability_id).await?;
+
+    Ok(AbilityTestCasesList { ability_test_cases })
+}
+
+/// Create new test case for ability.
+///
+/// # Errors
+///
+/// Returns error if arguments are not valid for the ability or there was a problem while
+/// inserting new test case.
+#[tauri::command]
+pub async fn create_ability_test_case(
+    request: CreateAbilityTestCase,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<AbilityTestCase> {
+    let cid = workspace.id();
+    let ability = repo::abilities::get(&*pool, cid, request.ability_id).await?;
+    let arguments = parse_arguments(&request.arguments_json)?;
+
+    crate::abilities::validate_arguments(&ability.parameters_json, &arguments)?;
+
+    repo::ability_test_cases::create(
+        &*pool,
+        cid,
+        CreateParams {
+            ability_id: ability.id,
+            name: request.name,
+            arguments,
+        },
+    )
+    .await
+}
+
+/// Update test case by id.
+///
+/// # Errors
+///
+/// Returns error if test case with given id does not exist, arguments are not valid for the
+/// ability or there was a problem while accessing database.
+#[tauri::command]
+pub async fn update_ability_test_case(
+    request: UpdateAbilityTestCase,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<AbilityTestCase> {
+    let cid = workspace.id();
+    let test_case = repo::ability_test_cases::get(&*pool, cid, request.id).await?;
+    let ability = repo::abilities::get(&*pool, cid, test_case.ability_id).await?;
+    let arguments = parse_arguments(&request.arguments_json)?;
+
+    crate::abilities::validate_arguments(&ability.parameters_json, &arguments)?;
+
+    repo::ability_test_cases::update(
+        &*pool,
+        cid,
+        UpdateParams {
+            id: test_case.id,
+            name: request.name,
+            arguments,
+        },
+    )
+    .await
+}
+
+/// Delete test case by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn delete_ability_test_case(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+    repo::ability_test_cases::delete(&*pool, cid, id).await
+}
+
+/// Run saved test case against the current ability code.
+///
+/// # Errors
+///
+/// Returns error if test case with given id does not exist, its arguments are no longer valid
+/// for the ability, or there was a problem while running the ability.
+#[tauri::command]
+pub async fn run_ability_test_case(
+    id: i32,
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Execution> {
+    let cid = workspace.id();
+    let test_case = repo::ability_test_cases::get(&*pool, cid, id).await?;
+    let settings = settings.read().await.clone();
+
+    run(&pool, &test_case, &app_local_data_dir, &settings).await
+}
+
+/// Run all saved test cases of the ability against its current code.
+///
+/// Test cases that fail to run are reported with an error instead of failing the whole batch.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn run_ability_test_cases(
+    ability_id: i32,
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Vec<AbilityTestCaseRun>> {
+    let cid = workspace.id();
+    let test_cases = repo::ability_test_cases::list_for_ability(&*pool, cid, ability_id).await?;
+    let settings = settings.read().await.clone();
+
+    let mut runs = Vec::with_capacity(test_cases.len());
+    for test_case in test_cases {
+        let (run, error) = match run(&pool, &test_case, &app_local_data_dir, &settings).await {
+            Ok(run) => (Some(run), None),
+            Err(err) => (None, Some(format!("{err:#}"))),
+        };
+
+        runs.push(AbilityTestCaseRun {
+            ability_test_case_id: test_case.id,
+            run,
+            error,
+        });
+    }
+
+    Ok(runs)
+}
+
+async fn run(
+    pool: &DbPool,
+    test_case: &AbilityTestCase,
+    workdir_root: &Path,
+    settings: &Settings,
+) -> Result<Execution> {
+    let ability = repo::abilities::get(pool, test_case.company_id, test_case.ability_id).await?;
+
+    crate::abilities::test(
+        &ability,
+        &test_case.arguments,
+        workdir_root,
+        &settings.sandbox,
+        Duration::from_secs(settings.tool_calls.timeout_secs),
+    )
+    .await
+}
+
+fn parse_arguments(arguments_json: &str) -> Result<Value> {
+    Ok(serde_json::from_str(arguments_json).with_context(|| "Failed to parse arguments JSON")?)
+}
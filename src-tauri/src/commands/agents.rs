@@ -12,9 +12,13 @@ use bridge_common::repo::{
 };
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tauri::State;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    events::{AppEvent, Deleted, Events},
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Agent {
@@ -31,6 +35,24 @@ pub struct Agent {
     pub updated_at: DateTime<Utc>,
 }
 
+impl Agent {
+    fn from_row(row: bridge_common::types::agents::Agent, ability_ids: Vec<i32>) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            system_message: row.system_message,
+            ability_ids,
+            is_enabled: row.is_enabled,
+            is_code_interpreter_enabled: row.is_code_interpreter_enabled,
+            is_web_browser_enabled: row.is_web_browser_enabled,
+            execution_steps_limit: row.execution_steps_limit,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AgentsList {
@@ -67,10 +89,14 @@ pub struct UpdateAgent {
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-pub async fn list_agents(pool: State<'_, DbPool>) -> Result<AgentsList> {
-    let rows = repo::agents::list(&*pool, crate::CID).await?;
+pub async fn list_agents(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<AgentsList> {
+    let cid = workspace.id();
+    let rows = repo::agents::list(&*pool, cid).await?;
 
-    let ability_rows = repo::agent_abilities::list(&*pool, crate::CID).await?;
+    let ability_rows = repo::agent_abilities::list(&*pool, cid).await?;
 
     let mut abilities: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
     for row in ability_rows {
@@ -82,18 +108,9 @@ pub async fn list_agents(pool: State<'_, DbPool>) -> Result<AgentsList> {
 
     let agents = rows
         .into_iter()
-        .map(|row| Agent {
-            id: row.id,
-            name: row.name,
-            description: row.description,
-            system_message: row.system_message,
-            ability_ids: abilities.get(&row.id).unwrap_or(&Vec::new()).clone(),
-            is_enabled: row.is_enabled,
-            is_code_interpreter_enabled: row.is_code_interpreter_enabled,
-            is_web_browser_enabled: row.is_web_browser_enabled,
-            execution_steps_limit: row.execution_steps_limit,
-            created_at: row.created_at,
-            updated_at: row.updated_at,
+        .map(|row| {
+            let ability_ids = abilities.remove(&row.id).unwrap_or_default();
+            Agent::from_row(row, ability_ids)
         })
         .collect();
 
@@ -106,7 +123,13 @@ pub async fn list_agents(pool: State<'_, DbPool>) -> Result<AgentsList> {
 ///
 /// Returns error if there was a problem while accessing database.
 #[tauri::command]
-pub async fn create_agent(request: CreateAgent, pool: State<'_, DbPool>) -> Result<Agent> {
+pub async fn create_agent(
+    request: CreateAgent,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Agent> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
@@ -114,7 +137,7 @@ pub async fn create_agent(request: CreateAgent, pool: State<'_, DbPool>) -> Resu
 
     let agent = repo::agents::create(
         &mut *tx,
-        crate::CID,
+        cid,
         CreateParams {
             name: request.name,
             description: request.description,
@@ -126,14 +149,14 @@ pub async fn create_agent(request: CreateAgent, pool: State<'_, DbPool>) -> Resu
     .await?;
 
     for ability_id in &request.ability_ids {
-        repo::agent_abilities::create(&mut *tx, crate::CID, agent.id, *ability_id).await?;
+        repo::agent_abilities::create(&mut *tx, cid, agent.id, *ability_id).await?;
     }
 
     tx.commit()
         .await
         .with_context(|| "Failed to commit transaction")?;
 
-    Ok(Agent {
+    let agent = Agent {
         id: agent.id,
         name: agent.name,
         description: agent.description,
@@ -145,7 +168,10 @@ pub async fn create_agent(request: CreateAgent, pool: State<'_, DbPool>) -> Resu
         execution_steps_limit: request.execution_steps_limit,
         created_at: agent.created_at,
         updated_at: agent.updated_at,
-    })
+    };
+    events.emit_app(cid, AppEvent::AgentCreated(&agent)).await?;
+
+    Ok(agent)
 }
 
 /// Update `is_enabled` field for agent by id.
@@ -158,10 +184,22 @@ pub async fn update_agent_is_enabled(
     id: i32,
     is_enabled: bool,
     pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
-    repo::agents::update_is_enabled(&*pool, crate::CID, id, is_enabled).await?;
+    let cid = workspace.id();
+    repo::agents::update_is_enabled(&*pool, cid, id, is_enabled).await?;
 
-    Ok(())
+    let row = repo::agents::get(&*pool, cid, id).await?;
+    let ability_ids = repo::agent_abilities::list(&*pool, cid)
+        .await?
+        .into_iter()
+        .filter(|row| row.agent_id == id)
+        .map(|row| row.ability_id)
+        .collect();
+    let agent = Agent::from_row(row, ability_ids);
+
+    events.emit_app(cid, AppEvent::AgentUpdated(&agent)).await
 }
 
 /// Update agent by id.
@@ -171,7 +209,13 @@ pub async fn update_agent_is_enabled(
 /// Returns error if agent with given id does not exist or there was an error
 /// while accessing database.
 #[tauri::command]
-pub async fn update_agent(request: UpdateAgent, pool: State<'_, DbPool>) -> Result<Agent> {
+pub async fn update_agent(
+    request: UpdateAgent,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Agent> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
@@ -179,7 +223,7 @@ pub async fn update_agent(request: UpdateAgent, pool: State<'_, DbPool>) -> Resu
 
     let agent = repo::agents::update(
         &mut *tx,
-        crate::CID,
+        cid,
         UpdateParams {
             id: request.id,
             name: request.name,
@@ -192,28 +236,19 @@ pub async fn update_agent(request: UpdateAgent, pool: State<'_, DbPool>) -> Resu
     .await?;
 
     // TODO(ri-nat): Be more clever here
-    repo::agent_abilities::delete_for_agent(&mut *tx, crate::CID, request.id).await?;
+    repo::agent_abilities::delete_for_agent(&mut *tx, cid, request.id).await?;
     for ability_id in &request.ability_ids {
-        repo::agent_abilities::create(&mut *tx, crate::CID, request.id, *ability_id).await?;
+        repo::agent_abilities::create(&mut *tx, cid, request.id, *ability_id).await?;
     }
 
     tx.commit()
         .await
         .with_context(|| "Failed to commit transaction")?;
 
-    Ok(Agent {
-        id: agent.id,
-        name: agent.name,
-        description: agent.description,
-        system_message: agent.system_message,
-        ability_ids: request.ability_ids,
-        is_enabled: agent.is_enabled,
-        is_code_interpreter_enabled: agent.is_code_interpreter_enabled,
-        is_web_browser_enabled: agent.is_web_browser_enabled,
-        execution_steps_limit: agent.execution_steps_limit,
-        created_at: agent.created_at,
-        updated_at: agent.updated_at,
-    })
+    let agent = Agent::from_row(agent, request.ability_ids);
+    events.emit_app(cid, AppEvent::AgentUpdated(&agent)).await?;
+
+    Ok(agent)
 }
 
 /// Delete agent by id.
@@ -223,18 +258,26 @@ pub async fn update_agent(request: UpdateAgent, pool: State<'_, DbPool>) -> Resu
 /// Returns error if agent with given id does not exist.
 /// Returns error if any error occurs during transaction.
 #[tauri::command]
-pub async fn delete_agent(id: i32, pool: State<'_, DbPool>) -> Result<()> {
+pub async fn delete_agent(
+    id: i32,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
         .with_context(|| "Failed to begin transaction")?;
 
-    repo::agent_abilities::delete_for_agent(&mut *tx, crate::CID, id).await?;
-    repo::agents::delete(&mut *tx, crate::CID, id).await?;
+    repo::agent_abilities::delete_for_agent(&mut *tx, cid, id).await?;
+    repo::agents::delete(&mut *tx, cid, id).await?;
 
     tx.commit()
         .await
         .with_context(|| "Failed to commit transaction")?;
 
-    Ok(())
+    events
+        .emit_app(cid, AppEvent::AgentDeleted(Deleted::new(id)))
+        .await
 }
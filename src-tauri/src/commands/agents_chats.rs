@@ -6,9 +6,12 @@
 use std::collections::HashMap;
 
 use bridge_common::repo;
-use tauri::State;
 
-use crate::{types::DbPool, types::Result};
+use crate::{
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 /// List agents for chats.
 ///
@@ -17,6 +20,10 @@ use crate::{types::DbPool, types::Result};
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-pub async fn list_agents_chats(pool: State<'_, DbPool>) -> Result<HashMap<i32, Vec<i32>>> {
-    Ok(repo::agents_chats::list(&*pool, crate::CID).await?)
+pub async fn list_agents_chats(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<HashMap<i32, Vec<i32>>> {
+    let cid = workspace.id();
+    Ok(repo::agents_chats::list(&*pool, cid).await?)
 }
@@ -0,0 +1,51 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    repo,
+    state::State,
+    types::{artifacts::Artifact, DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArtifactsList {
+    pub artifacts: Vec<Artifact>,
+}
+
+/// List artifacts of the chat: full outputs of its tool calls which were over the limit.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_artifacts(
+    chat_id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ArtifactsList> {
+    let cid = workspace.id();
+    let artifacts = repo::artifacts::list_for_chat(&*pool, cid, chat_id).await?;
+
+    Ok(ArtifactsList { artifacts })
+}
+
+/// Get artifact by id.
+///
+/// # Errors
+///
+/// Returns error if artifact with given id does not exist.
+#[tauri::command]
+pub async fn get_artifact(
+    id: i64,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Artifact> {
+    let cid = workspace.id();
+    repo::artifacts::get(&*pool, cid, id).await
+}
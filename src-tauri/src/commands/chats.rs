@@ -6,10 +6,14 @@
 use anyhow::Context;
 use bridge_common::{repo, types::chats::Chat};
 use serde::{Deserialize, Serialize};
-use tauri::State;
 use tracing::error;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    events::{AppEvent, Deleted, Events},
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,8 +33,13 @@ pub struct CreateChat {
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-pub async fn list_chats(pool: State<'_, DbPool>, is_pinned: Option<bool>) -> Result<ChatsList> {
-    let chats = repo::chats::list(&*pool, crate::CID, is_pinned).await?;
+pub async fn list_chats(
+    pool: State<'_, DbPool>,
+    is_pinned: Option<bool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ChatsList> {
+    let cid = workspace.id();
+    let chats = repo::chats::list(&*pool, cid, is_pinned).await?;
 
     Ok(ChatsList { chats })
 }
@@ -41,8 +50,13 @@ pub async fn list_chats(pool: State<'_, DbPool>, is_pinned: Option<bool>) -> Res
 ///
 /// Returns error if chat with given id does not exist.
 #[tauri::command]
-pub async fn get_chat(id: i32, pool: State<'_, DbPool>) -> Result<Chat> {
-    Ok(repo::chats::get(&*pool, crate::CID, id).await?)
+pub async fn get_chat(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Chat> {
+    let cid = workspace.id();
+    Ok(repo::chats::get(&*pool, cid, id).await?)
 }
 
 /// Create new chat with agent.
@@ -51,27 +65,28 @@ pub async fn get_chat(id: i32, pool: State<'_, DbPool>) -> Result<Chat> {
 ///
 /// Returns error if there was a problem while inserting new chat.
 #[tauri::command]
-pub async fn create_chat(request: CreateChat, pool: State<'_, DbPool>) -> Result<Chat> {
+pub async fn create_chat(
+    request: CreateChat,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Chat> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
         .with_context(|| "Failed to begin transaction")?;
 
-    let agent = repo::agents::get(&mut *tx, crate::CID, request.agent_id).await?;
-    let chat = repo::chats::create(
-        &mut *tx,
-        crate::CID,
-        bridge_common::types::chats::Kind::Direct,
-    )
-    .await?;
+    let agent = repo::agents::get(&mut *tx, cid, request.agent_id).await?;
+    let chat =
+        repo::chats::create(&mut *tx, cid, bridge_common::types::chats::Kind::Direct).await?;
 
     // Add agent to chat
-    repo::agents_chats::create(&mut *tx, crate::CID, request.agent_id, chat.id).await?;
+    repo::agents_chats::create(&mut *tx, cid, request.agent_id, chat.id).await?;
 
     // Insert system prompt message to chat
     repo::messages::create(
         &mut *tx,
-        crate::CID,
+        cid,
         repo::messages::CreateParams {
             chat_id: chat.id,
             status: bridge_common::types::messages::Status::Completed,
@@ -95,22 +110,30 @@ pub async fn create_chat(request: CreateChat, pool: State<'_, DbPool>) -> Result
 ///
 /// Returns error if chat with given id does not exist.
 #[tauri::command]
-pub async fn delete_chat(id: i32, pool: State<'_, DbPool>) -> Result<()> {
+pub async fn delete_chat(
+    id: i32,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
         .with_context(|| "Failed to begin transaction")?;
 
-    repo::tasks::delete_for_chat(&mut *tx, crate::CID, id).await?;
-    repo::messages::delete_for_chat(&mut *tx, crate::CID, id).await?;
-    repo::agents_chats::delete_for_chat(&mut *tx, crate::CID, id).await?;
-    repo::chats::delete(&mut *tx, crate::CID, id).await?;
+    repo::tasks::delete_for_chat(&mut *tx, cid, id).await?;
+    repo::messages::delete_for_chat(&mut *tx, cid, id).await?;
+    repo::agents_chats::delete_for_chat(&mut *tx, cid, id).await?;
+    repo::chats::delete(&mut *tx, cid, id).await?;
 
     tx.commit()
         .await
         .with_context(|| "Failed to commit transaction")?;
 
-    Ok(())
+    events
+        .emit_app(cid, AppEvent::ChatDeleted(Deleted::new(id)))
+        .await
 }
 
 /// Update chat title by id.
@@ -119,8 +142,14 @@ pub async fn delete_chat(id: i32, pool: State<'_, DbPool>) -> Result<()> {
 ///
 /// Returns error if there was a problem while updating the chat title or if the chat with the given ID does not exist.
 #[tauri::command]
-pub async fn update_chat_title(id: i32, title: String, pool: State<'_, DbPool>) -> Result<()> {
-    Ok(repo::chats::update_title(&*pool, crate::CID, id, &title).await?)
+pub async fn update_chat_title(
+    id: i32,
+    title: String,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+    Ok(repo::chats::update_title(&*pool, cid, id, &title).await?)
 }
 
 /// Toggle chat is pinned status by id.
@@ -129,8 +158,13 @@ pub async fn update_chat_title(id: i32, title: String, pool: State<'_, DbPool>)
 ///
 /// Returns error if the chat with the given ID does not exist.
 #[tauri::command]
-pub async fn toggle_chat_is_pinned(id: i32, pool: State<'_, DbPool>) -> Result<()> {
-    Ok(repo::chats::toggle_is_pinned(&*pool, crate::CID, id).await?)
+pub async fn toggle_chat_is_pinned(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+    Ok(repo::chats::toggle_is_pinned(&*pool, cid, id).await?)
 }
 
 /// Change chat model full name by id
@@ -143,11 +177,13 @@ pub async fn update_chat_model_full_name(
     id: i32,
     model_full_name: Option<String>,
     pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
+    let cid = workspace.id();
     let maybe_model_id = match model_full_name {
         Some(model_full_name) => {
             if let Some(model) =
-                repo::models::get_by_full_name(&*pool, crate::CID, &model_full_name).await?
+                repo::models::get_by_full_name(&*pool, cid, &model_full_name).await?
             {
                 Some(model.id)
             } else {
@@ -162,5 +198,5 @@ pub async fn update_chat_model_full_name(
         None => None,
     };
 
-    Ok(repo::chats::update_model_id(&*pool, crate::CID, id, maybe_model_id).await?)
+    Ok(repo::chats::update_model_id(&*pool, cid, id, maybe_model_id).await?)
 }
@@ -0,0 +1,42 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    repo,
+    state::State,
+    types::{events::LoggedEvent, DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
+
+/// Most events returned at once. Clients call again, from the last `seq` received, until they get
+/// fewer.
+const EVENTS_LIMIT: i64 = 1000;
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventsList {
+    pub events: Vec<LoggedEvent>,
+}
+
+/// List the events logged after `since_seq`, oldest first, for a client to catch up on the events
+/// it missed, e.g. after reloading. Every event emitted since carries its own `seq`, so a client
+/// can pick up from the last one it received. `0` lists the log from the beginning.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn subscribe_events(
+    since_seq: i64,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<EventsList> {
+    let cid = workspace.id();
+    let events = repo::events::list_since(&*pool, cid, since_seq, EVENTS_LIMIT).await?;
+
+    Ok(EventsList { events })
+}
@@ -0,0 +1,97 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use anyhow::Context;
+use tokio::sync::RwLock;
+
+use crate::{
+    settings::Settings,
+    state::State,
+    task_executor::{self, Executor, ExecutorState},
+    task_queue,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
+
+/// Get the state of the executor: what each worker is up to, and the root tasks waiting for
+/// execution, in the order they are picked, with their estimated wait.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn get_executor_state(
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    executor: State<'_, Executor>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ExecutorState> {
+    let queue = task_executor::schedule(&pool, workspace.id()).await?;
+
+    Ok(executor.state(queue, concurrency(&settings).await))
+}
+
+/// Put the root tasks waiting for execution in the given order. Tasks of higher priority come
+/// first regardless.
+///
+/// # Errors
+///
+/// Returns error if the ids given are not every queued task once each, they put a task before the
+/// ones of higher priority, or there was a problem while accessing database.
+#[tauri::command]
+pub async fn reorder_queue(
+    ids: Vec<i32>,
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    executor: State<'_, Executor>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ExecutorState> {
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let queue = task_queue::reorder(&mut tx, workspace.id(), &ids).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    Ok(executor.state(queue, concurrency(&settings).await))
+}
+
+/// Set the priority of the root task. A queued task is given a new place among the tasks of its
+/// new priority.
+///
+/// # Errors
+///
+/// Returns error if the task does not exist, is a subtask, or there was a problem while accessing
+/// database.
+#[tauri::command]
+pub async fn set_task_priority(
+    id: i32,
+    priority: i32,
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    executor: State<'_, Executor>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ExecutorState> {
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let queue = task_queue::set_priority(&mut tx, workspace.id(), id, priority).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    Ok(executor.state(queue, concurrency(&settings).await))
+}
+
+async fn concurrency(settings: &RwLock<Settings>) -> u16 {
+    settings.read().await.common.tasks.execution_concurrency
+}
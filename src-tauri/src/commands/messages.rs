@@ -5,18 +5,22 @@
 
 use anyhow::{anyhow, Context};
 use bridge_common::channel::{Channel, Event};
-use bridge_common::chats::CreateCompletionParams;
 use bridge_common::repo;
 use bridge_common::repo::messages::{CreateParams, ListParams};
-use bridge_common::settings::Settings;
 use bridge_common::types::messages::{Message, Role, Status};
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, State};
 use tokio::sync::RwLock;
 use tracing::{debug, trace, warn};
 use tracing::{error, instrument};
 
-use crate::types::{DbPool, Result};
+use crate::{
+    conversations,
+    events::{AppEvent, Deleted, Events},
+    settings::Settings,
+    state::{AppLocalDataDir, State},
+    types::{tool_call_policies::Decision, DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[allow(clippy::module_name_repetitions)]
@@ -43,13 +47,18 @@ pub struct CreateMessage {
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-#[instrument(skip(pool))]
-pub async fn list_messages(request: ListMessages, pool: State<'_, DbPool>) -> Result<MessagesList> {
+#[instrument(skip(pool, workspace))]
+pub async fn list_messages(
+    request: ListMessages,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<MessagesList> {
+    let cid = workspace.id();
     debug!("Listing messages for chat");
 
     let messages = repo::messages::list(
         &*pool,
-        crate::CID,
+        cid,
         ListParams {
             chat_id: request.chat_id,
         },
@@ -71,47 +80,53 @@ pub async fn create_message(
     channel: State<'_, Channel>,
     pool: State<'_, DbPool>,
     settings: State<'_, RwLock<Settings>>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
+    let (cid, uid) = workspace.ids();
     debug!("Creating message");
 
     let mut tx = pool.begin().await.context("Failed to begin transaction")?;
 
     // Retrieve the last message for the chat
-    let last_message_id =
-        repo::messages::get_last_message_id(&mut *tx, crate::CID, request.chat_id)
-            .await?
-            .context("Failed to get last message id")?;
-    let mut last_message = repo::messages::get(&mut *tx, crate::CID, last_message_id).await?;
+    let last_message_id = repo::messages::get_last_message_id(&mut *tx, cid, request.chat_id)
+        .await?
+        .context("Failed to get last message id")?;
+    let mut last_message = repo::messages::get(&mut *tx, cid, last_message_id).await?;
 
     // If last message status is waiting for tool call, deny it
     if last_message.status == Status::WaitingForToolCall {
         // Update the message status to ToolCallDenied
-        repo::messages::update_status(
+        repo::messages::update_status(&mut *tx, cid, last_message_id, Status::ToolCallDenied)
+            .await?;
+        // Deny the tool calls which are still pending, some of them may already be answered or
+        // being run
+        let tool_calls = conversations::pending_tool_calls(&mut *tx, &last_message, None).await?;
+        let tool_calls =
+            conversations::claim_tool_calls(&mut *tx, &last_message, tool_calls, Decision::Deny)
+                .await?;
+        let denied_messages = repo::messages::create_multiple(
             &mut *tx,
-            crate::CID,
-            last_message_id,
-            Status::ToolCallDenied,
+            cid,
+            conversations::tool_call_denied_params(&last_message, &tool_calls),
         )
         .await?;
-        // Create a new message indicating the tool call was denied
-        let denied_messages =
-            repo::messages::create_tool_call_denied(&mut *tx, crate::CID, &last_message).await?;
 
         last_message.status = Status::ToolCallDenied;
         channel
-            .emit(crate::UID, Event::MessageUpdated(&last_message))
+            .emit(uid, Event::MessageUpdated(&last_message))
             .await?;
 
         for denied_message in denied_messages {
             channel
-                .emit(crate::UID, Event::MessageCreated(&denied_message))
+                .emit(uid, Event::MessageCreated(&denied_message))
                 .await?;
         }
     }
 
     let message = repo::messages::create(
         &mut *tx,
-        crate::CID,
+        cid,
         CreateParams {
             chat_id: request.chat_id,
             status: Status::Completed,
@@ -125,49 +140,37 @@ pub async fn create_message(
 
     tx.commit().await.context("Failed to commit transaction")?;
 
-    channel
-        .emit(crate::UID, Event::MessageCreated(&message))
-        .await?;
-
-    let sett = settings.read().await.clone();
-
-    let chat = repo::chats::get(&*pool, crate::CID, message.chat_id).await?;
-    let model = bridge_common::models::get_for_chat(&pool, crate::CID, &sett, &chat).await?;
+    channel.emit(uid, Event::MessageCreated(&message)).await?;
 
-    // TODO: refactor error
-    let api_key = sett
-        .api_keys
-        .get(&model.provider)
-        .with_context(|| format!("Failed to get api key for provider: {:?}", model.provider))?;
+    let chat = repo::chats::get(&*pool, cid, message.chat_id).await?;
 
     match chat.kind {
         bridge_common::types::chats::Kind::Direct => {
-            bridge_common::chats::create_completion(
-                &pool,
-                &channel,
-                crate::CID,
-                crate::UID,
+            conversations::complete(
+                &conversations::Context {
+                    company_id: cid,
+                    user_id: uid,
+                    pool: &pool,
+                    channel: &channel,
+                    settings: &settings,
+                    app_local_data_dir: &app_local_data_dir,
+                },
                 chat.id,
-                CreateCompletionParams::default(),
-                &model,
-                api_key,
-                &crate::USER_AGENT,
             )
-            .await
-            .context("Failed to get chat completion")?;
+            .await?;
 
-            generate_chat_title(request.chat_id, channel, pool, settings).await?;
+            generate_chat_title(cid, uid, request.chat_id, channel, pool, settings).await?;
         }
         bridge_common::types::chats::Kind::Execution => {
-            let task = repo::tasks::get_by_execution_chat_id(&*pool, crate::CID, chat.id)
+            let task = repo::tasks::get_by_execution_chat_id(&*pool, cid, chat.id)
                 .await
                 .context("Failed to `get_by_execution_chat_id`")?;
 
             if task.status != bridge_common::types::tasks::Status::InProgress
                 || task.status != bridge_common::types::tasks::Status::ToDo
             {
-                let task = repo::tasks::execute(&*pool, crate::CID, task.id).await?;
-                channel.emit(crate::UID, Event::TaskUpdated(&task)).await?;
+                let task = repo::tasks::execute(&*pool, cid, task.id).await?;
+                channel.emit(uid, Event::TaskUpdated(&task)).await?;
             }
         }
         bridge_common::types::chats::Kind::Control => {
@@ -192,12 +195,14 @@ pub async fn create_message(
 /// Returns error if there was a problem while generating chat title.
 #[instrument(skip_all)]
 async fn generate_chat_title(
+    company_id: i32,
+    user_id: i32,
     chat_id: i32,
     channel: State<'_, Channel>,
     pool: State<'_, DbPool>,
     settings: State<'_, RwLock<Settings>>,
 ) -> Result<()> {
-    let mut chat = repo::chats::get(&*pool, crate::CID, chat_id).await?;
+    let mut chat = repo::chats::get(&*pool, company_id, chat_id).await?;
     trace!("Chat: {:?}", chat);
 
     if !chat.title.is_empty() {
@@ -207,22 +212,16 @@ async fn generate_chat_title(
 
     debug!("Generating chat title");
 
-    let settings_guard = settings.read().await;
+    let sett = settings.read().await.common.clone();
 
-    let messages = repo::messages::list(&*pool, crate::CID, ListParams { chat_id }).await?;
+    let messages = repo::messages::list(&*pool, company_id, ListParams { chat_id }).await?;
 
-    let model =
-        bridge_common::models::get_for_chat(&pool, crate::CID, &settings_guard, &chat).await?;
-
-    let api_key = settings_guard
-        .api_keys
-        .get(&model.provider)
-        .with_context(|| format!("Failed to get api key for provider: {:?}", model.provider))?;
+    let (model, api_key) = conversations::model_for_chat(&pool, &sett, &chat).await?;
 
     let title = match bridge_common::messages::generate_chat_title(
         messages,
         &model,
-        api_key,
+        &api_key,
         &crate::USER_AGENT,
     )
     .await
@@ -245,178 +244,90 @@ async fn generate_chat_title(
         }
     };
 
-    repo::chats::update_title(&*pool, crate::CID, chat_id, &title).await?;
-    chat = repo::chats::get(&*pool, crate::CID, chat_id).await?;
+    repo::chats::update_title(&*pool, company_id, chat_id, &title).await?;
+    chat = repo::chats::get(&*pool, company_id, chat_id).await?;
 
-    channel.emit(crate::UID, Event::ChatUpdated(&chat)).await?;
+    channel.emit(user_id, Event::ChatUpdated(&chat)).await?;
 
     Ok(())
 }
 
 /// Approves tool call, actually runs it and sends result to LLM.
 ///
+/// If `tool_call_id` is not set, every tool call of the message which is still pending is
+/// approved. Approved tool calls run concurrently and their results are sent to LLM once every
+/// tool call of the message has either a result or a denial.
+///
 /// # Errors
 ///
-/// Returns error if there was a problem while performing tool call.
-// TODO(ri-nat): refactor this function.
-#[allow(clippy::too_many_lines)]
+/// Returns error if the tool call is not pending, or there was a problem while performing tool
+/// call.
 #[instrument(skip_all)]
 #[tauri::command]
 pub async fn approve_tool_call(
     message_id: i64,
+    tool_call_id: Option<String>,
     pool: State<'_, DbPool>,
     settings: State<'_, RwLock<Settings>>,
     channel: State<'_, Channel>,
-    app_handle: AppHandle,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
+    let (cid, uid) = workspace.ids();
     debug!("Approving tool call");
 
-    let mut message = repo::messages::get(&*pool, crate::CID, message_id).await?;
-
-    // Check if message is waiting for tool call
-    if message.status != Status::WaitingForToolCall {
-        return Err(anyhow!("Message is not waiting for tool call").into());
-    }
-
-    // Check if message is a last message in chat
-    let last_message_id = repo::messages::get_last_message_id(&*pool, crate::CID, message.chat_id)
-        .await?
-        .context("Failed to get last message id")?;
-
-    // If it's not, mark message as completed and return error
-    if message.id != last_message_id {
-        // Mark message as completed
-        repo::messages::update_status(&*pool, crate::CID, message.id, Status::Completed).await?;
-
-        // Emit event.
-        message.status = Status::Completed;
-        channel
-            .emit(crate::UID, Event::MessageUpdated(&message))
-            .await?;
-
-        return Err(anyhow!("Message is not a last message in chat").into());
-    }
-
-    let app_local_data_dir = app_handle
-        .path_resolver()
-        .app_local_data_dir()
-        .expect("Failed to get app local data dir");
-
-    // Execute abilities
-    bridge_common::abilities::execute_for_message(
-        &pool,
-        &channel,
-        crate::CID,
-        crate::UID,
-        &app_local_data_dir,
-        &message,
-    )
-    .await?;
+    let ctx = conversations::Context {
+        company_id: cid,
+        user_id: uid,
+        pool: &pool,
+        channel: &channel,
+        settings: &settings,
+        app_local_data_dir: &app_local_data_dir,
+    };
 
-    // Emit event
-    message.status = Status::Completed;
-    channel
-        .emit(crate::UID, Event::MessageUpdated(&message))
-        .await?;
+    conversations::approve_tool_call(&ctx, message_id, tool_call_id.as_deref()).await?;
 
-    let sett = settings.read().await.clone();
-
-    let model = bridge_common::models::get_default(&pool, crate::CID, &sett).await?;
-    let api_key = sett
-        .api_keys
-        .get(&model.provider)
-        .with_context(|| format!("Failed to get api key for provider: {:?}", model.provider))?;
-
-    bridge_common::chats::create_completion(
-        &pool,
-        &channel,
-        crate::CID,
-        crate::UID,
-        message.chat_id,
-        CreateCompletionParams::default(),
-        &model,
-        api_key,
-        &crate::USER_AGENT,
-    )
-    .await
-    .context("Failed to get chat completion")?;
-
-    generate_chat_title(message.chat_id, channel, pool, settings).await?;
+    let message = repo::messages::get(&*pool, cid, message_id).await?;
+    generate_chat_title(cid, uid, message.chat_id, channel, pool, settings).await?;
 
     Ok(())
 }
 
 /// Deny tool call
 ///
+/// If `tool_call_id` is not set, every tool call of the message which is still pending is denied.
+/// Other tool calls of the message are not affected.
+///
 /// # Errors
 ///
-/// Returns error if message with given id does not exist.
+/// Returns error if message with given id does not exist or the tool call is not pending.
 #[instrument(skip_all)]
 #[tauri::command]
 pub async fn deny_tool_call(
     message_id: i64,
+    tool_call_id: Option<String>,
     pool: State<'_, DbPool>,
     settings: State<'_, RwLock<Settings>>,
     channel: State<'_, Channel>,
+    app_local_data_dir: State<'_, AppLocalDataDir>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
+    let (cid, uid) = workspace.ids();
     debug!("Denying tool call");
 
-    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
-    let mut message = repo::messages::get(&mut *tx, crate::CID, message_id).await?;
-
-    // Ensure the message is waiting for a tool call
-    if message.status != Status::WaitingForToolCall {
-        return Err(anyhow!("Message is not waiting for tool call").into());
-    }
-
-    // Update the message status to ToolCallDenied
-    repo::messages::update_status(&mut *tx, crate::CID, message.id, Status::ToolCallDenied).await?;
-
-    // Create a new message indicating the tool call was denied
-    let denied_messages =
-        repo::messages::create_tool_call_denied(&mut *tx, crate::CID, &message).await?;
-
-    // Commit the transaction
-    tx.commit().await.context("Failed to commit transaction")?;
-
-    message.status = Status::ToolCallDenied;
-
-    channel
-        .emit(crate::UID, Event::MessageUpdated(&message))
-        .await?;
-
-    for denied_message in denied_messages {
-        channel
-            .emit(crate::UID, Event::MessageCreated(&denied_message))
-            .await?;
-    }
-
-    let chat = repo::chats::get(&*pool, crate::CID, message.chat_id).await?;
+    let ctx = conversations::Context {
+        company_id: cid,
+        user_id: uid,
+        pool: &pool,
+        channel: &channel,
+        settings: &settings,
+        app_local_data_dir: &app_local_data_dir,
+    };
 
-    let sett = settings.read().await.clone();
-    let model = bridge_common::models::get_for_chat(&pool, crate::CID, &sett, &chat)
-        .await
-        .context("Failed to get model for chat")?;
-    let api_key = sett
-        .api_keys
-        .get(&model.provider)
-        .with_context(|| format!("Failed to get api key for provider: {:?}", model.provider))?;
-
-    bridge_common::chats::create_completion(
-        &pool,
-        &channel,
-        crate::CID,
-        crate::UID,
-        message.chat_id,
-        CreateCompletionParams::default(),
-        &model,
-        api_key,
-        &crate::USER_AGENT,
-    )
-    .await
-    .context("Failed to get chat completion")?;
+    conversations::deny_tool_call(&ctx, message_id, tool_call_id.as_deref()).await?;
 
-    generate_chat_title(message.chat_id, channel, pool, settings).await?;
+    let message = repo::messages::get(&*pool, cid, message_id).await?;
+    generate_chat_title(cid, uid, message.chat_id, channel, pool, settings).await?;
 
     Ok(())
 }
@@ -428,12 +339,20 @@ pub async fn deny_tool_call(
 /// Returns error if there was a problem while deleting message.
 #[instrument(skip_all)]
 #[tauri::command]
-pub async fn delete_message(id: i64, pool: State<'_, DbPool>) -> Result<()> {
+pub async fn delete_message(
+    id: i64,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
     debug!("Deleting message");
 
-    repo::messages::delete(&*pool, crate::CID, id).await?;
+    repo::messages::delete(&*pool, cid, id).await?;
 
-    Ok(())
+    events
+        .emit_app(cid, AppEvent::MessageDeleted(Deleted::new(id)))
+        .await
 }
 
 /// Update message content by id.
@@ -447,11 +366,13 @@ pub async fn update_message_content(
     id: i64,
     content: String,
     pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<Message> {
+    let cid = workspace.id();
     debug!("Updating message content");
 
     let mut tx = pool.begin().await.context("Failed to begin transaction")?;
-    let message = repo::messages::get(&mut *tx, crate::CID, id).await?;
+    let message = repo::messages::get(&mut *tx, cid, id).await?;
 
     if message.role != Role::System && message.role != Role::User {
         return Err(anyhow!(
@@ -462,7 +383,7 @@ pub async fn update_message_content(
     }
 
     let updated_message =
-        repo::messages::update_message_content(&mut *tx, crate::CID, id, &content).await?;
+        repo::messages::update_message_content(&mut *tx, cid, id, &content).await?;
 
     tx.commit().await.context("Failed to commit transaction")?;
 
@@ -475,8 +396,13 @@ pub async fn update_message_content(
 ///
 /// Returns error if message with given id does not exist.
 #[tauri::command]
-pub async fn get_raw_message_content(id: i64, pool: State<'_, DbPool>) -> Result<String> {
-    let message = repo::messages::get(&*pool, crate::CID, id)
+pub async fn get_raw_message_content(
+    id: i64,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<String> {
+    let cid = workspace.id();
+    let message = repo::messages::get(&*pool, cid, id)
         .await
         .with_context(|| "Failed to get message")?;
 
@@ -2,12 +2,122 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod abilities;
+pub mod ability_test_cases;
 pub mod agents;
 pub mod agents_chats;
+pub mod artifacts;
 pub mod chats;
+pub mod events;
+pub mod executor;
 pub mod messages;
 pub mod models;
 pub mod pages;
+pub mod plan_proposals;
 pub mod settings;
 pub mod task_results;
+pub mod task_templates;
 pub mod tasks;
+pub mod tool_call_policies;
+pub mod webhooks;
+pub mod workspaces;
+
+/// Invoke `$callback!` with every command, along with the names of its arguments in order, so the
+/// commands the desktop app registers with Tauri and the ones served over HTTP are the same.
+#[macro_export]
+macro_rules! with_commands {
+    ($callback:ident) => {
+        $callback! {
+        abilities::create_ability(request, pool, events, settings, app_local_data_dir, workspace),
+        abilities::delete_ability(id, pool, events, workspace),
+        abilities::list_abilities(pool, workspace),
+        abilities::test_ability(id, arguments_json, pool, settings, app_local_data_dir, workspace),
+        abilities::update_ability(request, pool, events, settings, app_local_data_dir, workspace),
+        ability_test_cases::create_ability_test_case(request, pool, workspace),
+        ability_test_cases::delete_ability_test_case(id, pool, workspace),
+        ability_test_cases::list_ability_test_cases(ability_id, pool, workspace),
+        ability_test_cases::run_ability_test_case(id, pool, settings, app_local_data_dir, workspace),
+        ability_test_cases::run_ability_test_cases(ability_id, pool, settings, app_local_data_dir, workspace),
+        ability_test_cases::update_ability_test_case(request, pool, workspace),
+        agents_chats::list_agents_chats(pool, workspace),
+        agents::create_agent(request, pool, events, workspace),
+        agents::delete_agent(id, pool, events, workspace),
+        agents::list_agents(pool, workspace),
+        agents::update_agent_is_enabled(id, is_enabled, pool, events, workspace),
+        agents::update_agent(request, pool, events, workspace),
+        artifacts::get_artifact(id, pool, workspace),
+        artifacts::list_artifacts(chat_id, pool, workspace),
+        chats::create_chat(request, pool, workspace),
+        chats::delete_chat(id, pool, events, workspace),
+        chats::get_chat(id, pool, workspace),
+        chats::list_chats(pool, is_pinned, workspace),
+        chats::toggle_chat_is_pinned(id, pool, workspace),
+        chats::update_chat_model_full_name(id, model_full_name, pool, workspace),
+        chats::update_chat_title(id, title, pool, workspace),
+        events::subscribe_events(since_seq, pool, workspace),
+        executor::get_executor_state(pool, settings, executor, workspace),
+        executor::reorder_queue(ids, pool, settings, executor, workspace),
+        executor::set_task_priority(id, priority, pool, settings, executor, workspace),
+        messages::approve_tool_call(message_id, tool_call_id, pool, settings, channel, app_local_data_dir, workspace),
+        messages::create_message(request, channel, pool, settings, app_local_data_dir, workspace),
+        messages::delete_message(id, pool, events, workspace),
+        messages::deny_tool_call(message_id, tool_call_id, pool, settings, channel, app_local_data_dir, workspace),
+        messages::get_raw_message_content(id, pool, workspace),
+        messages::list_messages(request, pool, workspace),
+        messages::update_message_content(id, content, pool, workspace),
+        models::list_models(pool, workspace),
+        pages::create_page(request, pool, workspace),
+        pages::delete_page(id, pool, events, workspace),
+        pages::get_page(id, pool, workspace),
+        pages::list_pages(pool, workspace),
+        pages::update_page(request, pool, workspace),
+        plan_proposals::accept_plan_proposal(request, pool, channel, events, workspace),
+        plan_proposals::list_plan_proposals(task_id, pool, workspace),
+        plan_proposals::reject_plan_proposal(id, feedback, pool, settings, events, workspace),
+        settings::export_settings(settings),
+        settings::get_settings(settings),
+        settings::import_settings(settings, pool, cipher, content, workspace),
+        settings::test_provider(provider, api_key, settings, pool, workspace),
+        settings::update_settings(settings, pool, cipher, new_settings, workspace),
+        settings::validate_settings(settings, pool, workspace),
+        task_results::get_task_result_text_data(pool, id, workspace),
+        task_results::list_task_results(pool, task_id, workspace),
+        task_templates::create_task_template(request, pool, workspace),
+        task_templates::delete_task_template(id, pool, workspace),
+        task_templates::get_task_template(id, pool, workspace),
+        task_templates::instantiate_template(request, pool, channel, workspace),
+        task_templates::list_task_templates(pool, workspace),
+        task_templates::update_task_template(request, pool, workspace),
+        tasks::add_subtask(request, pool, channel, workspace),
+        tasks::create_task(request, pool, workspace),
+        tasks::delete_task(id, pool, events, workspace),
+        tasks::duplicate_task(id, execute, copy_results, pool, channel, workspace),
+        tasks::execute_task(id, pool, workspace),
+        tasks::get_task(id, pool, workspace),
+        tasks::get_task_trace(id, pool, workspace),
+        tasks::list_child_tasks(id, pool, workspace),
+        tasks::list_root_tasks_by_status(status, pool, pagination, workspace),
+        tasks::list_root_tasks(pool, pagination, workspace),
+        tasks::move_subtask(request, pool, channel, workspace),
+        tasks::plan_task(pool, channel, events, settings, id, review, workspace),
+        tasks::remove_subtask(id, pool, events, workspace),
+        tasks::reorder_subtasks(parent_id, ids, pool, channel, workspace),
+        tasks::revise_task(id, pool, workspace),
+        tasks::update_task(request, pool, workspace),
+        tool_call_policies::create_tool_call_policy(request, pool, workspace),
+        tool_call_policies::delete_tool_call_policy(id, pool, workspace),
+        tool_call_policies::list_tool_call_decisions(chat_id, pool, workspace),
+        tool_call_policies::list_tool_call_policies(pool, workspace),
+        tool_call_policies::update_tool_call_policy(request, pool, workspace),
+        webhooks::create_webhook(request, pool, cipher, workspace),
+        webhooks::delete_webhook(id, pool, workspace),
+        webhooks::list_webhook_deliveries(webhook_id, pool, workspace),
+        webhooks::list_webhooks(pool, cipher, workspace),
+        webhooks::update_webhook(request, pool, cipher, workspace),
+        workspaces::create_workspace(name, pool),
+        workspaces::delete_workspace(id, pool, workspace),
+        workspaces::get_active_workspace(pool, workspace),
+        workspaces::list_workspaces(pool, workspace),
+        workspaces::switch_workspace(id, pool, workspace, settings, cipher),
+        }
+    };
+}
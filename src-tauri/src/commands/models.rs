@@ -4,10 +4,13 @@
 #![allow(clippy::used_underscore_binding)]
 
 use bridge_common::{repo::models, types::models::Model};
-use tauri::State;
 use tracing::instrument;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 /// List models
 ///
@@ -15,7 +18,11 @@ use crate::types::{DbPool, Result};
 ///
 /// Returns error if there was a problem while accessing database.
 #[tauri::command]
-#[instrument(skip(pool))]
-pub async fn list_models(pool: State<'_, DbPool>) -> Result<Vec<Model>> {
-    Ok(models::list(&*pool, crate::CID).await?)
+#[instrument(skip(pool, workspace))]
+pub async fn list_models(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Vec<Model>> {
+    let cid = workspace.id();
+    Ok(models::list(&*pool, cid).await?)
 }
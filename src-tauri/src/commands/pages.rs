@@ -13,11 +13,15 @@ use chrono::DateTime;
 use chrono::Utc;
 use markdown::to_html;
 use serde::{Deserialize, Serialize};
-use tauri::State;
 use tracing::debug;
 use tracing::instrument;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    events::{AppEvent, Deleted, Events},
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -67,11 +71,15 @@ impl From<Page> for PageResponse {
 ///
 /// Returns error if there was a problem while accessing database.
 #[tauri::command]
-#[instrument(skip(pool))]
-pub async fn list_pages(pool: State<'_, DbPool>) -> Result<Vec<ShortPage>> {
+#[instrument(skip(pool, workspace))]
+pub async fn list_pages(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Vec<ShortPage>> {
+    let cid = workspace.id();
     debug!("Listing pages");
 
-    let pages = repo::pages::list(&*pool, crate::CID).await?;
+    let pages = repo::pages::list(&*pool, cid).await?;
 
     Ok(pages)
 }
@@ -82,8 +90,13 @@ pub async fn list_pages(pool: State<'_, DbPool>) -> Result<Vec<ShortPage>> {
 ///
 /// Returns error if page with given id does not exist.
 #[tauri::command]
-pub async fn get_page(id: i32, pool: State<'_, DbPool>) -> Result<PageResponse> {
-    let page = repo::pages::get(&*pool, crate::CID, id)
+pub async fn get_page(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<PageResponse> {
+    let cid = workspace.id();
+    let page = repo::pages::get(&*pool, cid, id)
         .await
         .with_context(|| "Failed to get page")?;
 
@@ -96,16 +109,18 @@ pub async fn get_page(id: i32, pool: State<'_, DbPool>) -> Result<PageResponse>
 ///
 /// Returns error if there was a problem while creating new page.
 #[tauri::command]
-#[instrument(skip(pool))]
+#[instrument(skip(pool, workspace))]
 pub async fn create_page(
     request: CreatePageRequest,
     pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<PageResponse> {
+    let cid = workspace.id();
     debug!("Creating page");
 
     let page = repo::pages::create(
         &*pool,
-        crate::CID,
+        cid,
         CreateParams {
             title: request.title,
             text: request.text,
@@ -121,17 +136,19 @@ pub async fn create_page(
 /// # Errors
 ///
 /// Returns error if there was a problem while updating page content.
-#[instrument(skip(pool))]
+#[instrument(skip(pool, workspace))]
 #[tauri::command]
 pub async fn update_page(
     request: UpdatePageRequest,
     pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<PageResponse> {
+    let cid = workspace.id();
     debug!("Updating page");
 
     let updated_page = repo::pages::update(
         &*pool,
-        crate::CID,
+        cid,
         request.id,
         UpdateParams {
             title: request.title,
@@ -148,12 +165,20 @@ pub async fn update_page(
 /// # Errors
 ///
 /// Returns error if there was a problem while deleting page.
-#[instrument(skip(pool))]
+#[instrument(skip(pool, events, workspace))]
 #[tauri::command]
-pub async fn delete_page(id: i32, pool: State<'_, DbPool>) -> Result<()> {
+pub async fn delete_page(
+    id: i32,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
     debug!("Deleting page");
 
-    repo::pages::delete(&*pool, crate::CID, id).await?;
+    repo::pages::delete(&*pool, cid, id).await?;
 
-    Ok(())
+    events
+        .emit_app(cid, AppEvent::PageDeleted(Deleted::new(id)))
+        .await
 }
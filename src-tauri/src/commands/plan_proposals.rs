@@ -0,0 +1,121 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use anyhow::Context;
+use bridge_common::{
+    channel::{Channel, Event},
+    repo,
+    types::tasks::Task,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    events::{AppEvent, Events},
+    plan_reviews,
+    settings::Settings,
+    state::State,
+    types::{
+        plan_proposals::{PlanProposal, ProposedSubtask},
+        DbPool, Result,
+    },
+    workspaces::ActiveWorkspace,
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlanProposalsList {
+    pub plan_proposals: Vec<PlanProposal>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AcceptPlanProposal {
+    pub id: i32,
+    /// Subtasks as edited by the user, the proposed ones if not set.
+    pub subtasks: Option<Vec<ProposedSubtask>>,
+}
+
+/// List plan proposals for the task, oldest first.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_plan_proposals(
+    task_id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<PlanProposalsList> {
+    let cid = workspace.id();
+    let plan_proposals = crate::repo::plan_proposals::list_for_task(&*pool, cid, task_id).await?;
+
+    Ok(PlanProposalsList { plan_proposals })
+}
+
+/// Accept plan proposal, possibly edited, saving the subtasks and queueing the task.
+///
+/// # Errors
+///
+/// Returns error if the proposal has been reviewed already, the subtasks are not valid, or there
+/// was a problem while accessing database.
+#[tauri::command]
+pub async fn accept_plan_proposal(
+    request: AcceptPlanProposal,
+    pool: State<'_, DbPool>,
+    channel: State<'_, Channel>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let (cid, uid) = workspace.ids();
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let accepted = plan_reviews::accept(&mut tx, cid, request.id, request.subtasks).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    events
+        .emit_app(cid, AppEvent::PlanProposalUpdated(&accepted.proposal))
+        .await?;
+    for subtask in &accepted.subtasks {
+        channel.emit(uid, Event::TaskCreated(subtask)).await?;
+    }
+    channel
+        .emit(uid, Event::TaskUpdated(&accepted.task))
+        .await?;
+
+    Ok(accepted.task)
+}
+
+/// Reject plan proposal with feedback, and propose a new plan taking the feedback into account.
+///
+/// # Errors
+///
+/// Returns error if the feedback is empty, the proposal has been reviewed already, or there was a
+/// problem while planning the task.
+#[tauri::command]
+pub async fn reject_plan_proposal(
+    id: i32,
+    feedback: String,
+    pool: State<'_, DbPool>,
+    settings: State<'_, RwLock<Settings>>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<PlanProposal> {
+    let cid = workspace.id();
+    let rejected = plan_reviews::reject(&pool, cid, id, &feedback).await?;
+    events
+        .emit_app(cid, AppEvent::PlanProposalUpdated(&rejected))
+        .await?;
+
+    let task = repo::tasks::get(&*pool, cid, rejected.task_id).await?;
+    let settings = settings.read().await.common.clone();
+
+    plan_reviews::propose(&pool, &settings, &events, &task).await
+}
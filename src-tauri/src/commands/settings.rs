@@ -3,11 +3,25 @@
 
 #![allow(clippy::used_underscore_binding)]
 
-use bridge_common::{repo, settings::Settings};
-use tauri::State;
+use anyhow::Context;
+use bridge_common::types::models::Provider;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    providers::{self, ProviderStatus},
+    repo,
+    secrets::{self, Cipher},
+    settings::{self, Settings},
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SettingsValidation {
+    pub providers: Vec<ProviderStatus>,
+}
 
 /// Get the current settings.
 ///
@@ -17,29 +31,167 @@ use crate::types::{DbPool, Result};
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
 pub async fn get_settings(settings: State<'_, RwLock<Settings>>) -> Result<Settings> {
-    Ok(settings.read().await.clone())
+    Ok(masked(&*settings.read().await))
 }
 
 /// Update the settings.
 ///
-/// # Errors
-///
-/// Will return an error if the settings can't be saved to disk.
+/// API keys left masked as returned by `get_settings` keep their current values.
 ///
-/// # Panics
+/// # Errors
 ///
-/// Will panic if the app local data dir can't be resolved.
+/// Will return an error if the settings can't be encrypted or saved to the database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
 pub async fn update_settings(
     settings: State<'_, RwLock<Settings>>,
     pool: State<'_, DbPool>,
-    new_settings: Settings,
+    cipher: State<'_, Cipher>,
+    mut new_settings: Settings,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
     let mut st = settings.write().await;
-    *st = new_settings;
+    // Read under the settings lock, which switching workspaces holds
+    let cid = workspace.id();
+
+    for (provider, api_key) in &mut new_settings.common.api_keys {
+        if let Some(current) = st.common.api_keys.get(provider) {
+            if *api_key == secrets::mask(current) {
+                api_key.clone_from(current);
+            }
+        }
+    }
 
-    repo::settings::update(&*pool, crate::CID, &st).await?;
+    // Keys which can't be decrypted stay as stored, unless the user has entered new ones
+    for (provider, api_key) in &st.unreadable_api_keys {
+        let api_keys = &mut new_settings.common.api_keys;
+        if api_keys.get(provider).map(String::as_str) == Some(secrets::UNREADABLE_MASK) {
+            api_keys.remove(provider);
+            new_settings
+                .unreadable_api_keys
+                .insert(provider.clone(), api_key.clone());
+        }
+    }
+
+    repo::settings::update(&*pool, cid, &new_settings, &cipher).await?;
+    *st = new_settings;
 
     Ok(())
 }
+
+/// Export the settings without API keys, so they can be shared as a baseline.
+///
+/// # Errors
+///
+/// Returns error if the settings can't be serialized.
+#[allow(clippy::module_name_repetitions)]
+#[tauri::command]
+pub async fn export_settings(settings: State<'_, RwLock<Settings>>) -> Result<String> {
+    let value = settings.read().await.export()?;
+
+    Ok(serde_json::to_string_pretty(&value).with_context(|| "Failed to serialize settings")?)
+}
+
+/// Import the settings exported by `export_settings`, possibly by an older version. API keys are
+/// kept as they are.
+///
+/// # Errors
+///
+/// Returns error if the content is not valid settings, is of a newer version, or if the settings
+/// can't be saved to the database.
+#[allow(clippy::module_name_repetitions)]
+#[tauri::command]
+pub async fn import_settings(
+    settings: State<'_, RwLock<Settings>>,
+    pool: State<'_, DbPool>,
+    cipher: State<'_, Cipher>,
+    content: String,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Settings> {
+    let value = serde_json::from_str(&content).map_err(settings::Error::JsonDeserialization)?;
+
+    let mut st = settings.write().await;
+    let cid = workspace.id();
+    let new_settings = st.import(value)?;
+
+    repo::settings::update(&*pool, cid, &new_settings, &cipher).await?;
+    *st = new_settings;
+
+    Ok(masked(&st))
+}
+
+/// Check every configured provider: whether its API key is valid, which of the seeded models are
+/// available and how long the request takes.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database. Problems with providers are
+/// reported in their statuses.
+#[tauri::command]
+pub async fn validate_settings(
+    settings: State<'_, RwLock<Settings>>,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<SettingsValidation> {
+    let cid = workspace.id();
+    let models = bridge_common::repo::models::list(&*pool, cid).await?;
+    let api_keys = settings.read().await.common.api_keys.clone();
+
+    Ok(SettingsValidation {
+        providers: providers::check_all(&models, &api_keys).await,
+    })
+}
+
+/// Check the provider with the given API key, or the saved one if not set or masked, so the key
+/// can be tested before saving it.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn test_provider(
+    provider: Provider,
+    api_key: Option<String>,
+    settings: State<'_, RwLock<Settings>>,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ProviderStatus> {
+    let cid = workspace.id();
+    let models = bridge_common::repo::models::list(&*pool, cid).await?;
+    let models = models
+        .iter()
+        .filter(|model| model.provider == provider && model.api_url.is_none())
+        .collect::<Vec<_>>();
+
+    let saved_api_key = settings
+        .read()
+        .await
+        .common
+        .api_keys
+        .get(&provider)
+        .cloned();
+    let api_key = match (api_key, saved_api_key) {
+        (Some(api_key), Some(saved)) if api_key == secrets::mask(&saved) => Some(saved),
+        (Some(api_key), _) if api_key == secrets::UNREADABLE_MASK => None,
+        (Some(api_key), _) => Some(api_key),
+        (None, saved) => saved,
+    };
+    let api_url = providers::default_api_url(&provider);
+
+    Ok(providers::check(provider, &api_url, api_key.as_deref(), &models).await)
+}
+
+fn masked(settings: &Settings) -> Settings {
+    let mut settings = settings.clone();
+    for api_key in settings.common.api_keys.values_mut() {
+        *api_key = secrets::mask(api_key);
+    }
+    for provider in std::mem::take(&mut settings.unreadable_api_keys).into_keys() {
+        settings
+            .common
+            .api_keys
+            .insert(provider, secrets::UNREADABLE_MASK.to_string());
+    }
+
+    settings
+}
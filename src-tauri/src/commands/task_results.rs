@@ -3,9 +3,12 @@
 
 #![allow(clippy::used_underscore_binding)]
 
-use crate::types::{DbPool, Result};
+use crate::{
+    state::State,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 use bridge_common::{repo, types::task_results::TaskResult};
-use tauri::State;
 use tracing::instrument;
 
 /// List task results by task id
@@ -14,9 +17,14 @@ use tracing::instrument;
 ///
 /// Returns error if there was a problem while accessing database.
 #[tauri::command]
-#[instrument(skip(pool))]
-pub async fn list_task_results(pool: State<'_, DbPool>, task_id: i32) -> Result<Vec<TaskResult>> {
-    Ok(repo::task_results::list(&*pool, crate::CID, task_id).await?)
+#[instrument(skip(pool, workspace))]
+pub async fn list_task_results(
+    pool: State<'_, DbPool>,
+    task_id: i32,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Vec<TaskResult>> {
+    let cid = workspace.id();
+    Ok(repo::task_results::list(&*pool, cid, task_id).await?)
 }
 
 /// Get task text data by task result id
@@ -25,7 +33,12 @@ pub async fn list_task_results(pool: State<'_, DbPool>, task_id: i32) -> Result<
 ///
 /// Returns error if there was a problem while accessing database.
 #[tauri::command]
-#[instrument(skip(pool))]
-pub async fn get_task_result_text_data(pool: State<'_, DbPool>, id: i32) -> Result<String> {
-    Ok(repo::task_results::get_text_data(&*pool, crate::CID, id).await?)
+#[instrument(skip(pool, workspace))]
+pub async fn get_task_result_text_data(
+    pool: State<'_, DbPool>,
+    id: i32,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<String> {
+    let cid = workspace.id();
+    Ok(repo::task_results::get_text_data(&*pool, cid, id).await?)
 }
@@ -0,0 +1,279 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use std::collections::VecDeque;
+
+use anyhow::Context;
+use bridge_common::{
+    channel::{Channel, Event},
+    repo::{self, tasks::CreateParams as CreateTaskParams},
+    types::tasks::{Status, Task},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    repo::task_templates::{CreateParams, UpdateParams},
+    state::State,
+    task_templates::{arguments, render, validate, Error},
+    types::{
+        task_templates::{Parameter, SubtaskTemplate, TaskTemplate},
+        DbPool, Result,
+    },
+    workspaces::ActiveWorkspace,
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaskTemplatesList {
+    pub task_templates: Vec<TaskTemplate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateTaskTemplate {
+    pub name: String,
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    pub agent_id: Option<i32>,
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+    #[serde(default)]
+    pub subtasks: Vec<SubtaskTemplate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateTaskTemplate {
+    pub id: i32,
+    pub name: String,
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    pub agent_id: Option<i32>,
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+    #[serde(default)]
+    pub subtasks: Vec<SubtaskTemplate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InstantiateTemplate {
+    pub template_id: i32,
+    /// Values of the template parameters, by name.
+    #[serde(default)]
+    pub arguments: Map<String, Value>,
+    /// Agent of the task, instead of the default one of the template.
+    pub agent_id: Option<i32>,
+    /// Whether to put the task in the execution queue right away.
+    #[serde(default)]
+    pub execute: bool,
+}
+
+/// List all task templates.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_task_templates(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TaskTemplatesList> {
+    let cid = workspace.id();
+    let task_templates = crate::repo::task_templates::list(&*pool, cid).await?;
+
+    Ok(TaskTemplatesList { task_templates })
+}
+
+/// Get task template by id.
+///
+/// # Errors
+///
+/// Returns error if task template with given id does not exist.
+#[tauri::command]
+pub async fn get_task_template(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TaskTemplate> {
+    let cid = workspace.id();
+
+    crate::repo::task_templates::get(&*pool, cid, id).await
+}
+
+/// Create task template.
+///
+/// # Errors
+///
+/// Returns error if the template is not valid, or there was a problem while accessing database.
+#[tauri::command]
+pub async fn create_task_template(
+    request: CreateTaskTemplate,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TaskTemplate> {
+    let cid = workspace.id();
+    validate(
+        &request.name,
+        &request.title,
+        &request.summary,
+        &request.parameters,
+        &request.subtasks,
+    )?;
+
+    crate::repo::task_templates::create(
+        &*pool,
+        cid,
+        CreateParams {
+            name: request.name,
+            title: request.title,
+            summary: request.summary,
+            agent_id: request.agent_id,
+            parameters: request.parameters,
+            subtasks: request.subtasks,
+        },
+    )
+    .await
+}
+
+/// Update task template.
+///
+/// # Errors
+///
+/// Returns error if task template with given id does not exist, the template is not valid, or
+/// there was a problem while accessing database.
+#[tauri::command]
+pub async fn update_task_template(
+    request: UpdateTaskTemplate,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TaskTemplate> {
+    let cid = workspace.id();
+    validate(
+        &request.name,
+        &request.title,
+        &request.summary,
+        &request.parameters,
+        &request.subtasks,
+    )?;
+
+    crate::repo::task_templates::update(
+        &*pool,
+        cid,
+        UpdateParams {
+            id: request.id,
+            name: request.name,
+            title: request.title,
+            summary: request.summary,
+            agent_id: request.agent_id,
+            parameters: request.parameters,
+            subtasks: request.subtasks,
+        },
+    )
+    .await
+}
+
+/// Delete task template. Tasks created from it are kept.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn delete_task_template(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+
+    crate::repo::task_templates::delete(&*pool, cid, id).await
+}
+
+/// Create task from the template, filling its placeholders with the arguments given, along with
+/// the subtask tree of the template. The task is put in the execution queue if asked to, and is
+/// planned first as usual if the template has no subtasks.
+///
+/// # Errors
+///
+/// Returns error if task template with given id does not exist, the arguments are not valid, no
+/// agent is given while the template has none, or there was a problem while accessing database.
+#[tauri::command]
+pub async fn instantiate_template(
+    request: InstantiateTemplate,
+    pool: State<'_, DbPool>,
+    channel: State<'_, Channel>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let (cid, uid) = workspace.ids();
+    let template = crate::repo::task_templates::get(&*pool, cid, request.template_id).await?;
+    let agent_id = request
+        .agent_id
+        .or(template.agent_id)
+        .ok_or(Error::NoAgent)?;
+    let values = arguments(&template.parameters, &request.arguments)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let title = render(&template.title, &values);
+    let summary = render(&template.summary, &values);
+    let task = repo::tasks::create(
+        &mut *tx,
+        cid,
+        CreateTaskParams {
+            agent_id,
+            origin_chat_id: None,
+            title: &title,
+            summary: Some(&summary),
+            status: if request.execute {
+                Status::ToDo
+            } else {
+                Status::Draft
+            },
+            ancestry: None,
+        },
+    )
+    .await?;
+
+    // Subtasks are created level by level, so that the siblings are executed in the given order
+    let mut tasks = vec![task];
+    let mut pending = VecDeque::from([(0, template.subtasks.as_slice())]);
+    while let Some((parent, subtasks)) = pending.pop_front() {
+        let ancestry = tasks[parent].children_ancestry();
+        let parent_agent_id = tasks[parent].agent_id;
+
+        for subtask in subtasks {
+            let title = render(&subtask.title, &values);
+            let summary = render(&subtask.summary, &values);
+            let task = repo::tasks::create(
+                &mut *tx,
+                cid,
+                CreateTaskParams {
+                    agent_id: subtask.agent_id.unwrap_or(parent_agent_id),
+                    title: &title,
+                    summary: Some(&summary),
+                    ancestry: Some(&ancestry),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+            tasks.push(task);
+            pending.push_back((tasks.len() - 1, subtask.children.as_slice()));
+        }
+    }
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    for task in &tasks {
+        channel.emit(uid, Event::TaskCreated(task)).await?;
+    }
+
+    Ok(tasks.swap_remove(0))
+}
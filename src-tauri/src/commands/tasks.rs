@@ -3,24 +3,36 @@
 
 #![allow(clippy::used_underscore_binding)]
 
+use std::collections::HashMap;
+
 use anyhow::{anyhow, Context};
 use bridge_common::{
-    channel::Channel,
+    channel::{Channel, Event},
     repo::{
         self,
         tasks::{CreateParams, UpdateParams},
     },
-    settings::Settings,
     task_planner::TaskPlanner,
     types::{
         pagination::Pagination,
+        task_results::TaskResult,
         tasks::{Status, Task},
     },
 };
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use sqlx::{Postgres, Transaction};
+use tokio::sync::RwLock;
 
-use crate::types::{DbPool, Result};
+use crate::{
+    events::{AppEvent, Deleted, Events},
+    plan_reviews,
+    settings::Settings,
+    state::State,
+    task_plans::{self, NewSubtask},
+    task_traces::{self, TaskTrace},
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,7 +58,28 @@ pub struct UpdateTask {
     pub agent_id: i32,
 }
 
-/// Plan task by id.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddSubtask {
+    pub parent_id: i32,
+    /// Agent of the subtask, the one of its parent if not set.
+    pub agent_id: Option<i32>,
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    /// Position among the siblings, last if not set.
+    pub position: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MoveSubtask {
+    pub id: i32,
+    pub parent_id: i32,
+    /// Position among the new siblings, last if not set.
+    pub position: Option<usize>,
+}
+
+/// Plan task by id. In review mode, which `review` overrides `planning.review` of the settings
+/// for, the plan is proposed for the user to review instead of being saved.
 ///
 /// # Errors
 ///
@@ -55,12 +88,28 @@ pub struct UpdateTask {
 pub async fn plan_task(
     pool: State<'_, DbPool>,
     channel: State<'_, Channel>,
-    settings: State<'_, Settings>,
+    events: State<'_, Events>,
+    settings: State<'_, RwLock<Settings>>,
     id: i32,
+    review: Option<bool>,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<()> {
-    let mut task = repo::tasks::get(&*pool, crate::CID, id).await?;
+    let (cid, uid) = workspace.ids();
+    let mut task = repo::tasks::get(&*pool, cid, id).await?;
+    let (settings, is_review) = {
+        let settings = settings.read().await;
+        (
+            settings.common.clone(),
+            review.unwrap_or(settings.planning.review),
+        )
+    };
+
+    if is_review {
+        plan_reviews::propose(&pool, &settings, &events, &task).await?;
+        return Ok(());
+    }
 
-    TaskPlanner::new(&pool, &channel, &settings, crate::UID, &crate::USER_AGENT)
+    TaskPlanner::new(&pool, &channel, &settings, uid, &crate::USER_AGENT)
         .plan(&mut task)
         .await?;
 
@@ -73,10 +122,15 @@ pub async fn plan_task(
 ///
 /// Returns error if there was a problem while inserting task into database.
 #[tauri::command]
-pub async fn create_task(request: CreateTask, pool: State<'_, DbPool>) -> Result<Task> {
+pub async fn create_task(
+    request: CreateTask,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let cid = workspace.id();
     Ok(repo::tasks::create(
         &*pool,
-        crate::CID,
+        cid,
         CreateParams {
             agent_id: request.agent_id,
             origin_chat_id: None,
@@ -95,26 +149,34 @@ pub async fn create_task(request: CreateTask, pool: State<'_, DbPool>) -> Result
 ///
 /// Returns error if task with given id does not exist.
 #[tauri::command]
-pub async fn delete_task(id: i32, pool: State<'_, DbPool>) -> Result<()> {
+pub async fn delete_task(
+    id: i32,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
         .with_context(|| "Failed to begin transaction")?;
 
-    let task = repo::tasks::get(&mut *tx, crate::CID, id).await?;
+    let task = repo::tasks::get(&mut *tx, cid, id).await?;
 
     // TODO: delete `execution` and `control` chats; `messages` (for both of them); `task_results`.
     //       As well as the working directory (for the root task).
     //       Also, delete all of these for the children tasks.
 
-    repo::tasks::delete_children(&mut *tx, crate::CID, id, task.ancestry.as_deref()).await?;
-    repo::tasks::delete(&mut *tx, crate::CID, id).await?;
+    repo::tasks::delete_children(&mut *tx, cid, id, task.ancestry.as_deref()).await?;
+    repo::tasks::delete(&mut *tx, cid, id).await?;
 
     tx.commit()
         .await
         .with_context(|| "Failed to commit transaction")?;
 
-    Ok(())
+    events
+        .emit_app(cid, AppEvent::TaskDeleted(Deleted::new(id)))
+        .await
 }
 
 /// Execute task by id.
@@ -123,22 +185,27 @@ pub async fn delete_task(id: i32, pool: State<'_, DbPool>) -> Result<()> {
 ///
 /// Returns error if task with given id does not exist.
 #[tauri::command]
-pub async fn execute_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
-    let task = repo::tasks::get(&*pool, crate::CID, id).await?;
+pub async fn execute_task(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let cid = workspace.id();
+    let task = repo::tasks::get(&*pool, cid, id).await?;
 
     // Delete all the task progress and the results if task is being re-executed
     if task.status == Status::Done {
-        repo::task_results::delete_for_task(&*pool, crate::CID, id).await?;
+        repo::task_results::delete_for_task(&*pool, cid, id).await?;
         repo::messages::delete_for_chat(
             &*pool,
-            crate::CID,
+            cid,
             task.execution_chat_id
                 .context("No execution chat ID for task")?,
         )
         .await?;
     }
 
-    Ok(repo::tasks::execute(&*pool, crate::CID, id).await?)
+    Ok(repo::tasks::execute(&*pool, cid, id).await?)
 }
 
 /// Get task by id.
@@ -147,8 +214,29 @@ pub async fn execute_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
 ///
 /// Returns error if task with given id does not exist.
 #[tauri::command]
-pub async fn get_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
-    Ok(repo::tasks::get(&*pool, crate::CID, id).await?)
+pub async fn get_task(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let cid = workspace.id();
+    Ok(repo::tasks::get(&*pool, cid, id).await?)
+}
+
+/// Get the trace of the execution of the task and its subtasks at every level: every step with
+/// its timing, model, token usage, tool calls, verdict and error, in the order they were started.
+///
+/// # Errors
+///
+/// Returns error if task with given id does not exist or there was a problem while accessing
+/// database.
+#[tauri::command]
+pub async fn get_task_trace(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TaskTrace> {
+    task_traces::trace(&pool, workspace.id(), id).await
 }
 
 /// List child tasks by parent id.
@@ -158,9 +246,15 @@ pub async fn get_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-pub async fn list_child_tasks(id: i32, pool: State<'_, DbPool>) -> Result<TasksList> {
-    let task = repo::tasks::get(&*pool, crate::CID, id).await?;
-    let tasks = repo::tasks::list_direct_children(&*pool, crate::CID, &task).await?;
+pub async fn list_child_tasks(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TasksList> {
+    let cid = workspace.id();
+    let task = repo::tasks::get(&*pool, cid, id).await?;
+    let mut tasks = repo::tasks::list_direct_children(&*pool, cid, &task).await?;
+    task_plans::sort(&*pool, cid, &mut tasks).await?;
 
     Ok(TasksList { tasks, count: None })
 }
@@ -172,8 +266,13 @@ pub async fn list_child_tasks(id: i32, pool: State<'_, DbPool>) -> Result<TasksL
 /// Returns error if there was a problem while accessing database.
 #[allow(clippy::module_name_repetitions)]
 #[tauri::command]
-pub async fn list_root_tasks(pool: State<'_, DbPool>, pagination: Pagination) -> Result<TasksList> {
-    let tasks = repo::tasks::list_roots(&*pool, crate::CID, pagination).await?;
+pub async fn list_root_tasks(
+    pool: State<'_, DbPool>,
+    pagination: Pagination,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TasksList> {
+    let cid = workspace.id();
+    let tasks = repo::tasks::list_roots(&*pool, cid, pagination).await?;
 
     Ok(TasksList { tasks, count: None })
 }
@@ -189,9 +288,11 @@ pub async fn list_root_tasks_by_status(
     status: Status,
     pool: State<'_, DbPool>,
     pagination: Pagination,
+    workspace: State<'_, ActiveWorkspace>,
 ) -> Result<TasksList> {
-    let tasks = repo::tasks::list_roots_by_status(&*pool, crate::CID, status, pagination).await?;
-    let count = repo::tasks::get_total_number_by_status(&*pool, crate::CID, status).await?;
+    let cid = workspace.id();
+    let tasks = repo::tasks::list_roots_by_status(&*pool, cid, status, pagination).await?;
+    let count = repo::tasks::get_total_number_by_status(&*pool, cid, status).await?;
 
     Ok(TasksList {
         tasks,
@@ -205,8 +306,13 @@ pub async fn list_root_tasks_by_status(
 ///
 /// Returns error if task with given id does not exist.
 #[tauri::command]
-pub async fn revise_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
-    Ok(repo::tasks::revise(&*pool, crate::CID, id).await?)
+pub async fn revise_task(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let cid = workspace.id();
+    Ok(repo::tasks::revise(&*pool, cid, id).await?)
 }
 
 /// Update task title or/and summary by id. Title and summary can be optional
@@ -215,7 +321,12 @@ pub async fn revise_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
 ///
 /// Returns error if task with given id does not exist
 #[tauri::command]
-pub async fn update_task(request: UpdateTask, pool: State<'_, DbPool>) -> Result<Task> {
+pub async fn update_task(
+    request: UpdateTask,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let cid = workspace.id();
     let mut tx = pool
         .begin()
         .await
@@ -227,7 +338,7 @@ pub async fn update_task(request: UpdateTask, pool: State<'_, DbPool>) -> Result
 
     let task = repo::tasks::update(
         &mut *tx,
-        crate::CID,
+        cid,
         UpdateParams {
             id: request.id,
             title: &request.title,
@@ -244,26 +355,285 @@ pub async fn update_task(request: UpdateTask, pool: State<'_, DbPool>) -> Result
     Ok(task)
 }
 
-/// Duplicate task by id.
+/// Duplicate task by id, along with its whole subtask tree. The copy is a draft, or is put in the
+/// execution queue if `execute` is set, and its subtasks are drafts as the planned ones are. With
+/// `copy_results` set, the results of every task are appended to the summary of its copy, for the
+/// agent to refer to.
 ///
 /// # Errors
 ///
 /// Returns error if task with given id does not exist.
 #[tauri::command]
-pub async fn duplicate_task(id: i32, pool: State<'_, DbPool>) -> Result<Task> {
-    let task = repo::tasks::get(&*pool, crate::CID, id).await?;
+pub async fn duplicate_task(
+    id: i32,
+    execute: Option<bool>,
+    copy_results: Option<bool>,
+    pool: State<'_, DbPool>,
+    channel: State<'_, Channel>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let (cid, uid) = workspace.ids();
+    let copy_results = copy_results.unwrap_or_default();
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let task = repo::tasks::get(&mut *tx, cid, id).await?;
+    let mut children = repo::tasks::list_all_children(&mut *tx, cid, &task.children_ancestry())
+        .await?
+        .into_iter()
+        .filter(|child| child.company_id == cid)
+        .collect::<Vec<_>>();
+    // Parents are copied before their children, and siblings in their order
+    task_plans::sort(&mut *tx, cid, &mut children).await?;
+    children.sort_by_key(|child| child.ancestry_level);
+
+    let status = if execute.unwrap_or_default() {
+        Status::ToDo
+    } else {
+        Status::Draft
+    };
+    let copy = duplicate(
+        &mut tx,
+        cid,
+        &task,
+        status,
+        task.ancestry.as_deref(),
+        copy_results,
+    )
+    .await?;
+
+    // Copies in the order they're created, and the index of the copy of every task copied
+    let mut copies = vec![copy];
+    let mut indices = HashMap::from([(task.id, 0)]);
+    for child in &children {
+        let parent_id = child
+            .ancestry
+            .as_deref()
+            .and_then(|ancestry| ancestry.rsplit('/').next())
+            .and_then(|id| id.parse::<i32>().ok())
+            .with_context(|| format!("Invalid ancestry of task #{}", child.id))?;
+        let parent = indices
+            .get(&parent_id)
+            .with_context(|| format!("Parent of task #{} is not copied", child.id))?;
+        let ancestry = copies[*parent].children_ancestry();
+
+        let copy = duplicate(
+            &mut tx,
+            cid,
+            child,
+            Status::Draft,
+            Some(&ancestry),
+            copy_results,
+        )
+        .await?;
+        indices.insert(child.id, copies.len());
+        copies.push(copy);
+    }
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    for copy in &copies {
+        channel.emit(uid, Event::TaskCreated(copy)).await?;
+    }
+
+    Ok(copies.swap_remove(0))
+}
+
+/// Create a copy of the task, with the results of the task appended to its summary if asked to.
+async fn duplicate(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    task: &Task,
+    status: Status,
+    ancestry: Option<&str>,
+    copy_results: bool,
+) -> Result<Task> {
+    let mut summary = task.summary.clone();
+    if copy_results {
+        let results = repo::task_results::list(&mut **tx, cid, task.id).await?;
+        append_results(&mut summary, &results);
+    }
 
     Ok(repo::tasks::create(
-        &*pool,
-        crate::CID,
+        &mut **tx,
+        cid,
         CreateParams {
-            status: Status::Draft,
+            status,
             agent_id: task.agent_id,
             origin_chat_id: task.origin_chat_id,
             title: &task.title,
-            summary: Some(&task.summary),
-            ancestry: task.ancestry.as_deref(),
+            summary: Some(&summary),
+            ancestry,
         },
     )
     .await?)
 }
+
+fn append_results(summary: &mut String, results: &[TaskResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    if !summary.is_empty() {
+        summary.push_str("\n\n");
+    }
+    summary.push_str("Results of the previous run, for reference:");
+    for result in results {
+        summary.push_str("\n\n");
+        summary.push_str(&result.data);
+    }
+}
+
+/// Add subtask to the task, e.g. to fix its plan by hand.
+///
+/// # Errors
+///
+/// Returns error if the parent task does not exist, its tree is being executed, or the subtask is
+/// not valid.
+#[tauri::command]
+pub async fn add_subtask(
+    request: AddSubtask,
+    pool: State<'_, DbPool>,
+    channel: State<'_, Channel>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let (cid, uid) = workspace.ids();
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let (task, moved) = task_plans::add(
+        &mut tx,
+        cid,
+        NewSubtask {
+            parent_id: request.parent_id,
+            agent_id: request.agent_id,
+            title: &request.title,
+            summary: &request.summary,
+            position: request.position,
+        },
+    )
+    .await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    channel.emit(uid, Event::TaskCreated(&task)).await?;
+    for task in &moved {
+        channel.emit(uid, Event::TaskUpdated(task)).await?;
+    }
+
+    Ok(task)
+}
+
+/// Remove subtask, along with its own subtasks.
+///
+/// # Errors
+///
+/// Returns error if the task does not exist, is a root task, or its tree is being executed.
+#[tauri::command]
+pub async fn remove_subtask(
+    id: i32,
+    pool: State<'_, DbPool>,
+    events: State<'_, Events>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    task_plans::remove(&mut tx, cid, id).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    events
+        .emit_app(cid, AppEvent::TaskDeleted(Deleted::new(id)))
+        .await
+}
+
+/// Put the subtasks of the task in the given order, which they are executed in.
+///
+/// # Errors
+///
+/// Returns error if the parent task does not exist, its tree is being executed, or the ids given
+/// are not every subtask of the parent once each.
+#[tauri::command]
+pub async fn reorder_subtasks(
+    parent_id: i32,
+    ids: Vec<i32>,
+    pool: State<'_, DbPool>,
+    channel: State<'_, Channel>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<TasksList> {
+    let (cid, uid) = workspace.ids();
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let moved = task_plans::reorder(&mut tx, cid, parent_id, &ids).await?;
+    let parent = repo::tasks::get(&mut *tx, cid, parent_id).await?;
+    let mut tasks = repo::tasks::list_direct_children(&mut *tx, cid, &parent).await?;
+    task_plans::sort(&mut *tx, cid, &mut tasks).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    for task in &moved {
+        channel.emit(uid, Event::TaskUpdated(task)).await?;
+    }
+
+    Ok(TasksList { tasks, count: None })
+}
+
+/// Move subtask under another parent within the same tree, or to another position under the
+/// same one. Its own subtasks are moved along.
+///
+/// # Errors
+///
+/// Returns error if either task does not exist, the task is a root task, the parent is in another
+/// tree or under the task itself, the tree is being executed, or the position is out of range.
+#[tauri::command]
+pub async fn move_subtask(
+    request: MoveSubtask,
+    pool: State<'_, DbPool>,
+    channel: State<'_, Channel>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Task> {
+    let (cid, uid) = workspace.ids();
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let moved = task_plans::move_to(
+        &mut tx,
+        cid,
+        request.id,
+        request.parent_id,
+        request.position,
+    )
+    .await?;
+    let task = repo::tasks::get(&mut *tx, cid, request.id).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    for task in &moved {
+        channel.emit(uid, Event::TaskUpdated(task)).await?;
+    }
+
+    Ok(task)
+}
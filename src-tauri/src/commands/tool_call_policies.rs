@@ -0,0 +1,169 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    repo::{
+        self,
+        tool_call_policies::{CreateParams, UpdateParams},
+    },
+    state::State,
+    types::{
+        tool_call_policies::{Action, ArgumentRule, Target, ToolCallDecision, ToolCallPolicy},
+        DbPool, Result,
+    },
+    workspaces::ActiveWorkspace,
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolCallPoliciesList {
+    pub tool_call_policies: Vec<ToolCallPolicy>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolCallDecisionsList {
+    pub tool_call_decisions: Vec<ToolCallDecision>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateToolCallPolicy {
+    /// Agent the policy applies to, or every agent if not set.
+    pub agent_id: Option<i32>,
+    pub target: Target,
+    pub ability_id: Option<i32>,
+    pub action: Action,
+    #[serde(default)]
+    pub rules: Vec<ArgumentRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateToolCallPolicy {
+    pub id: i32,
+    pub action: Action,
+    #[serde(default)]
+    pub rules: Vec<ArgumentRule>,
+}
+
+/// List all tool call policies.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_tool_call_policies(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ToolCallPoliciesList> {
+    let cid = workspace.id();
+    let tool_call_policies = repo::tool_call_policies::list(&*pool, cid).await?;
+
+    Ok(ToolCallPoliciesList { tool_call_policies })
+}
+
+/// Create tool call policy.
+///
+/// # Errors
+///
+/// Returns error if the policy is not valid, there already is a policy with the same agent and
+/// target, or there was a problem while accessing database.
+#[tauri::command]
+pub async fn create_tool_call_policy(
+    request: CreateToolCallPolicy,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ToolCallPolicy> {
+    let cid = workspace.id();
+    crate::tool_call_policies::validate(
+        request.target,
+        request.ability_id,
+        request.action,
+        &request.rules,
+    )?;
+
+    repo::tool_call_policies::create(
+        &*pool,
+        cid,
+        CreateParams {
+            agent_id: request.agent_id,
+            target: request.target,
+            ability_id: request.ability_id,
+            action: request.action,
+            rules: request.rules,
+        },
+    )
+    .await
+}
+
+/// Update action and rules of tool call policy.
+///
+/// # Errors
+///
+/// Returns error if policy with given id does not exist, the new rules are not valid, or there
+/// was a problem while accessing database.
+#[tauri::command]
+pub async fn update_tool_call_policy(
+    request: UpdateToolCallPolicy,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ToolCallPolicy> {
+    let cid = workspace.id();
+    let policy = repo::tool_call_policies::get(&*pool, cid, request.id).await?;
+
+    crate::tool_call_policies::validate(
+        policy.target,
+        policy.ability_id,
+        request.action,
+        &request.rules,
+    )?;
+
+    repo::tool_call_policies::update(
+        &*pool,
+        cid,
+        UpdateParams {
+            id: request.id,
+            action: request.action,
+            rules: request.rules,
+        },
+    )
+    .await
+}
+
+/// Delete tool call policy by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn delete_tool_call_policy(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+    repo::tool_call_policies::delete(&*pool, cid, id).await
+}
+
+/// List decisions made on tool calls in the chat, by policies or otherwise.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_tool_call_decisions(
+    chat_id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<ToolCallDecisionsList> {
+    let cid = workspace.id();
+    let tool_call_decisions =
+        repo::tool_call_policies::list_decisions_for_chat(&*pool, cid, chat_id).await?;
+
+    Ok(ToolCallDecisionsList {
+        tool_call_decisions,
+    })
+}
@@ -0,0 +1,170 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    repo::{
+        self,
+        webhooks::{CreateParams, UpdateParams},
+    },
+    secrets::Cipher,
+    state::State,
+    types::{
+        webhooks::{Webhook, WebhookDelivery},
+        DbPool, Result,
+    },
+    webhooks::{dedup_events, generate_secret, validate},
+    workspaces::ActiveWorkspace,
+};
+
+/// How many of the latest deliveries are listed.
+const DELIVERIES_LIMIT: i64 = 100;
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhooksList {
+    pub webhooks: Vec<Webhook>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookDeliveriesList {
+    pub webhook_deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateWebhook {
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateWebhook {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+    pub is_enabled: bool,
+}
+
+/// List all webhooks, with their secrets masked.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_webhooks(
+    pool: State<'_, DbPool>,
+    cipher: State<'_, Cipher>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<WebhooksList> {
+    let cid = workspace.id();
+    let mut webhooks = repo::webhooks::list(&*pool, cid).await?;
+
+    for webhook in &mut webhooks {
+        webhook.secret = cipher.mask(&webhook.secret);
+    }
+
+    Ok(WebhooksList { webhooks })
+}
+
+/// Create webhook with a new secret. The secret is returned in full only once, here.
+///
+/// # Errors
+///
+/// Returns error if the webhook is not valid, or there was a problem while accessing database.
+#[tauri::command]
+pub async fn create_webhook(
+    request: CreateWebhook,
+    pool: State<'_, DbPool>,
+    cipher: State<'_, Cipher>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Webhook> {
+    let cid = workspace.id();
+    let events = dedup_events(request.events);
+    validate(&request.url, &events)?;
+
+    let secret = generate_secret();
+    let mut webhook = repo::webhooks::create(
+        &*pool,
+        cid,
+        CreateParams {
+            url: request.url,
+            events,
+            secret: cipher.encrypt(&secret)?,
+        },
+    )
+    .await?;
+    webhook.secret = secret;
+
+    Ok(webhook)
+}
+
+/// Update webhook URL, events and whether it's enabled.
+///
+/// # Errors
+///
+/// Returns error if webhook with given id does not exist, the webhook is not valid, or there was
+/// a problem while accessing database.
+#[tauri::command]
+pub async fn update_webhook(
+    request: UpdateWebhook,
+    pool: State<'_, DbPool>,
+    cipher: State<'_, Cipher>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Webhook> {
+    let cid = workspace.id();
+    let events = dedup_events(request.events);
+    validate(&request.url, &events)?;
+
+    let mut webhook = repo::webhooks::update(
+        &*pool,
+        cid,
+        UpdateParams {
+            id: request.id,
+            url: request.url,
+            events,
+            is_enabled: request.is_enabled,
+        },
+    )
+    .await?;
+    webhook.secret = cipher.mask(&webhook.secret);
+
+    Ok(webhook)
+}
+
+/// Delete webhook along with its deliveries.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn delete_webhook(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    let cid = workspace.id();
+
+    repo::webhooks::delete(&*pool, cid, id).await
+}
+
+/// List the latest deliveries of webhook, newest first.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn list_webhook_deliveries(
+    webhook_id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<WebhookDeliveriesList> {
+    let cid = workspace.id();
+    let webhook_deliveries =
+        repo::webhooks::list_deliveries(&*pool, cid, webhook_id, DELIVERIES_LIMIT).await?;
+
+    Ok(WebhookDeliveriesList { webhook_deliveries })
+}
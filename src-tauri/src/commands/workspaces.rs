@@ -0,0 +1,97 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::used_underscore_binding)]
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    repo,
+    secrets::Cipher,
+    settings::Settings,
+    state::State,
+    types::{workspaces::Workspace, DbPool, Result},
+    workspaces::{self, ActiveWorkspace},
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspacesList {
+    pub workspaces: Vec<Workspace>,
+    pub active_workspace_id: i32,
+}
+
+/// List all workspaces, along with the active one.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[allow(clippy::module_name_repetitions)]
+#[tauri::command]
+pub async fn list_workspaces(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<WorkspacesList> {
+    Ok(WorkspacesList {
+        workspaces: repo::workspaces::list(&*pool).await?,
+        active_workspace_id: workspace.id(),
+    })
+}
+
+/// Get the active workspace.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+#[tauri::command]
+pub async fn get_active_workspace(
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<Workspace> {
+    repo::workspaces::get(&*pool, workspace.id()).await
+}
+
+/// Create workspace with the default models and agent. The active workspace stays the same.
+///
+/// # Errors
+///
+/// Returns error if the name is empty or there was a problem while creating workspace.
+#[allow(clippy::module_name_repetitions)]
+#[tauri::command]
+pub async fn create_workspace(name: String, pool: State<'_, DbPool>) -> Result<Workspace> {
+    workspaces::create(&pool, &name).await
+}
+
+/// Switch to the workspace, loading its settings.
+///
+/// # Errors
+///
+/// Returns error if workspace with given id does not exist or its settings can't be loaded.
+#[allow(clippy::module_name_repetitions)]
+#[tauri::command]
+pub async fn switch_workspace(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+    settings: State<'_, RwLock<Settings>>,
+    cipher: State<'_, Cipher>,
+) -> Result<Workspace> {
+    workspaces::switch(&pool, &workspace, &settings, &cipher, id).await
+}
+
+/// Delete workspace with everything in it.
+///
+/// # Errors
+///
+/// Returns error if the workspace is the active or the default one, or if there was a problem
+/// while deleting workspace.
+#[allow(clippy::module_name_repetitions)]
+#[tauri::command]
+pub async fn delete_workspace(
+    id: i32,
+    pool: State<'_, DbPool>,
+    workspace: State<'_, ActiveWorkspace>,
+) -> Result<()> {
+    workspaces::delete(&pool, &workspace, id).await
+}
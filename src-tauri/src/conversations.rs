@@ -0,0 +1,576 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversation turns in chats.
+//!
+//! A turn starts with a completion from the chat model and goes on while LLM calls tools which
+//! tool call policies allow or deny on their own. It stops when LLM answers without tool calls, or
+//! calls a tool which needs the user, in which case the user approving or denying the tool calls
+//! continues the turn.
+//!
+//! Execution chats are up to the task executor, so there the decision of the user only puts the
+//! task back in the queue.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Context as _};
+use bridge_common::{
+    channel::{Channel, Event},
+    chats::CreateCompletionParams,
+    clients::openai::ToolCall,
+    repo::{self, messages::CreateParams},
+    types::{
+        chats::{Chat, Kind},
+        messages::{Message, Role, Status},
+        models::Model,
+    },
+};
+use sqlx::{Executor, Postgres};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::{
+    code_interpreter,
+    errors::Error,
+    repo::tool_call_policies::CreateDecisionParams,
+    settings::Settings,
+    types::{tool_call_policies::Decision, DbPool, Result},
+};
+
+/// Application state a conversation turn needs.
+pub struct Context<'a> {
+    /// Workspace the conversation belongs to.
+    pub company_id: i32,
+    /// Local user of the workspace, who the events are emitted for.
+    pub user_id: i32,
+    pub pool: &'a DbPool,
+    pub channel: &'a Channel,
+    pub settings: &'a RwLock<Settings>,
+    /// Where tool call workdirs and ability virtualenvs live.
+    pub app_local_data_dir: &'a Path,
+}
+
+/// Get the model of the chat, along with the API key for its provider.
+///
+/// # Errors
+///
+/// Returns error if the model can't be found, or `MissingApiKey` if there is no API key for its
+/// provider.
+pub async fn model_for_chat(
+    pool: &DbPool,
+    settings: &bridge_common::settings::Settings,
+    chat: &Chat,
+) -> Result<(Model, String)> {
+    let model = bridge_common::models::get_for_chat(pool, chat.company_id, settings, chat)
+        .await
+        .context("Failed to get model for chat")?;
+    let api_key = settings
+        .api_keys
+        .get(&model.provider)
+        .ok_or_else(|| Error::MissingApiKey(model.provider.clone()))?
+        .clone();
+
+    Ok((model, api_key))
+}
+
+/// Get completions for the chat, settling tool calls by policies in between.
+///
+/// At most `execution_steps_limit` completions are made this way, so the agent can't get stuck in
+/// a loop.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while getting chat completion or performing tool calls.
+pub async fn complete(ctx: &Context<'_>, chat_id: i32) -> Result<()> {
+    let steps_limit = ctx
+        .settings
+        .read()
+        .await
+        .common
+        .agents
+        .execution_steps_limit;
+
+    for _ in 0..steps_limit {
+        create_completion(ctx, chat_id).await?;
+
+        if !apply_tool_call_policies(ctx, chat_id).await? {
+            return Ok(());
+        }
+    }
+
+    warn!("Execution steps limit reached while applying tool call policies");
+
+    Ok(())
+}
+
+/// Approve tool call of the message, run it and continue the turn once every tool call of the
+/// message has either a result or a denial.
+///
+/// If `tool_call_id` is not set, every tool call of the message which is still pending is
+/// approved. Approved tool calls run concurrently.
+///
+/// # Errors
+///
+/// Returns error if the message is not waiting for tool calls, the tool call is not pending, or
+/// there was a problem while performing tool call.
+pub async fn approve_tool_call(
+    ctx: &Context<'_>,
+    message_id: i64,
+    tool_call_id: Option<&str>,
+) -> Result<()> {
+    let mut message = repo::messages::get(ctx.pool, ctx.company_id, message_id).await?;
+
+    // Check if message is waiting for tool call
+    if message.status != Status::WaitingForToolCall {
+        return Err(anyhow!("Message is not waiting for tool call").into());
+    }
+
+    // Check if the conversation has moved on since the message
+    let has_later_messages = crate::repo::messages::has_later_messages(
+        ctx.pool,
+        ctx.company_id,
+        message.chat_id,
+        message.id,
+    )
+    .await?;
+
+    // If it has, mark message as completed and return error
+    if has_later_messages {
+        repo::messages::update_status(ctx.pool, ctx.company_id, message.id, Status::Completed)
+            .await?;
+
+        message.status = Status::Completed;
+        ctx.channel
+            .emit(ctx.user_id, Event::MessageUpdated(&message))
+            .await?;
+
+        return Err(anyhow!("Message is not a last message in chat").into());
+    }
+
+    let chat = repo::chats::get(ctx.pool, ctx.company_id, message.chat_id).await?;
+    if chat.kind == Kind::Execution {
+        return decide_for_execution(ctx, &message, tool_call_id, Decision::Allow).await;
+    }
+
+    let tool_calls = pending_tool_calls(ctx.pool, &message, tool_call_id).await?;
+    let tool_calls = claim_tool_calls(ctx.pool, &message, tool_calls, Decision::Allow).await?;
+    if let (Some(id), true) = (tool_call_id, tool_calls.is_empty()) {
+        return Err(anyhow!("Tool call `{id}` has already been decided").into());
+    }
+
+    run_tool_calls(ctx, &message, &tool_calls).await?;
+
+    if settle_tool_calls(ctx, &mut message).await? {
+        complete(ctx, message.chat_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Deny tool call of the message and continue the turn once every tool call of the message has
+/// either a result or a denial.
+///
+/// If `tool_call_id` is not set, every tool call of the message which is still pending is denied.
+/// Other tool calls of the message are not affected.
+///
+/// # Errors
+///
+/// Returns error if the message is not waiting for tool calls, the tool call is not pending, or
+/// there was a problem while getting chat completion.
+pub async fn deny_tool_call(
+    ctx: &Context<'_>,
+    message_id: i64,
+    tool_call_id: Option<&str>,
+) -> Result<()> {
+    let mut message = repo::messages::get(ctx.pool, ctx.company_id, message_id).await?;
+
+    // Ensure the message is waiting for a tool call
+    if message.status != Status::WaitingForToolCall {
+        return Err(anyhow!("Message is not waiting for tool call").into());
+    }
+
+    let chat = repo::chats::get(ctx.pool, ctx.company_id, message.chat_id).await?;
+    if chat.kind == Kind::Execution {
+        return decide_for_execution(ctx, &message, tool_call_id, Decision::Deny).await;
+    }
+
+    let tool_calls = pending_tool_calls(ctx.pool, &message, tool_call_id).await?;
+    let tool_calls = claim_tool_calls(ctx.pool, &message, tool_calls, Decision::Deny).await?;
+    if let (Some(id), true) = (tool_call_id, tool_calls.is_empty()) {
+        return Err(anyhow!("Tool call `{id}` has already been decided").into());
+    }
+
+    deny_tool_calls(ctx, &message, &tool_calls).await?;
+
+    if settle_tool_calls(ctx, &mut message).await? {
+        complete(ctx, message.chat_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Record the user decision on the tool calls of a message in an execution chat, and put its root
+/// task back in the queue, for the task executor to act on the decision. A message of an execution
+/// chat without tool calls waits for its code blocks to be interpreted.
+async fn decide_for_execution(
+    ctx: &Context<'_>,
+    message: &Message,
+    tool_call_id: Option<&str>,
+    decision: Decision,
+) -> Result<()> {
+    let tool_call_ids = if message.tool_calls().is_empty() {
+        vec![code_interpreter::FUNCTION_NAME.to_string()]
+    } else {
+        pending_tool_calls(ctx.pool, message, tool_call_id)
+            .await?
+            .into_iter()
+            .map(|tool_call| tool_call.id)
+            .collect()
+    };
+
+    let claimed = crate::repo::tool_call_policies::claim_decisions(
+        ctx.pool,
+        ctx.company_id,
+        message.id,
+        user_decision_params(tool_call_ids, decision),
+    )
+    .await?;
+    if let (Some(id), true) = (tool_call_id, claimed.is_empty()) {
+        return Err(anyhow!("Tool call `{id}` has already been decided").into());
+    }
+
+    let task =
+        repo::tasks::get_by_execution_chat_id(ctx.pool, ctx.company_id, message.chat_id).await?;
+    let root_id = task
+        .parent_ids()?
+        .and_then(|ids| ids.first().copied())
+        .unwrap_or(task.id);
+
+    let root_task = repo::tasks::execute(ctx.pool, ctx.company_id, root_id).await?;
+    ctx.channel
+        .emit(ctx.user_id, Event::TaskUpdated(&root_task))
+        .await?;
+
+    Ok(())
+}
+
+/// Tool calls of the message which have neither a result nor a denial yet: the one with the given
+/// id, or all of them if it's not set.
+///
+/// # Errors
+///
+/// Returns error if the tool call with the given id is not pending, or there was a problem while
+/// accessing database.
+pub async fn pending_tool_calls<'a, E>(
+    executor: E,
+    message: &Message,
+    tool_call_id: Option<&str>,
+) -> Result<Vec<ToolCall>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let tool_calls = message.tool_calls().0;
+    let ids = tool_calls
+        .iter()
+        .map(|tool_call| tool_call.id.clone())
+        .collect::<Vec<_>>();
+
+    let answered_ids = crate::repo::messages::list_answered_tool_call_ids(
+        executor,
+        message.company_id,
+        message.chat_id,
+        &ids,
+    )
+    .await?;
+
+    let mut pending = tool_calls
+        .into_iter()
+        .filter(|tool_call| !answered_ids.contains(&tool_call.id));
+
+    match tool_call_id {
+        Some(id) => {
+            let tool_call = pending
+                .find(|tool_call| tool_call.id == id)
+                .with_context(|| format!("Tool call `{id}` is not pending"))?;
+
+            Ok(vec![tool_call])
+        }
+        None => Ok(pending.collect()),
+    }
+}
+
+/// Messages letting LLM know the tool calls were denied.
+#[must_use]
+pub fn tool_call_denied_params(message: &Message, tool_calls: &[ToolCall]) -> Vec<CreateParams> {
+    tool_calls
+        .iter()
+        .map(|tool_call| CreateParams {
+            chat_id: message.chat_id,
+            status: Status::ToolCallDenied,
+            role: Role::Tool,
+            content: Some("Tool call denied".to_string()),
+            tool_call_id: Some(tool_call.id.clone()),
+
+            ..Default::default()
+        })
+        .collect()
+}
+
+async fn create_completion(ctx: &Context<'_>, chat_id: i32) -> Result<()> {
+    let chat = repo::chats::get(ctx.pool, ctx.company_id, chat_id).await?;
+    let sett = ctx.settings.read().await.common.clone();
+    let (model, api_key) = model_for_chat(ctx.pool, &sett, &chat).await?;
+
+    bridge_common::chats::create_completion(
+        ctx.pool,
+        ctx.channel,
+        ctx.company_id,
+        ctx.user_id,
+        chat_id,
+        CreateCompletionParams::default(),
+        &model,
+        &api_key,
+        &crate::USER_AGENT,
+    )
+    .await
+    .context("Failed to get chat completion")?;
+
+    Ok(())
+}
+
+/// Settle tool calls of the last chat message according to tool call policies.
+///
+/// Allowed tool calls are run and denied ones are reported to LLM. Returns whether every tool
+/// call of the message is answered now, so the turn should go on.
+async fn apply_tool_call_policies(ctx: &Context<'_>, chat_id: i32) -> Result<bool> {
+    let Some(mut message) =
+        repo::messages::get_last_message(ctx.pool, ctx.company_id, chat_id).await?
+    else {
+        return Ok(false);
+    };
+
+    if message.status != Status::WaitingForToolCall {
+        return Ok(false);
+    }
+
+    let mut allowed = Vec::new();
+    let mut denied = Vec::new();
+    for (tool_call, decision) in
+        crate::tool_call_policies::decide_for_message(ctx.pool, &message).await?
+    {
+        match decision {
+            Decision::Allow => allowed.push(tool_call),
+            Decision::Ask => {}
+            Decision::Deny => denied.push(tool_call),
+        }
+    }
+
+    debug!(
+        "Tool calls of message `{}`: {} allowed, {} denied by policy",
+        message.id,
+        allowed.len(),
+        denied.len()
+    );
+
+    if !denied.is_empty() {
+        deny_tool_calls(ctx, &message, &denied).await?;
+    }
+
+    if !allowed.is_empty() {
+        run_tool_calls(ctx, &message, &allowed).await?;
+    }
+
+    settle_tool_calls(ctx, &mut message).await
+}
+
+/// Run the tool calls of the message, sending each result as soon as it's ready. The ones which
+/// failed to run are left for the user to decide again, so they can be retried.
+async fn run_tool_calls(
+    ctx: &Context<'_>,
+    message: &Message,
+    tool_calls: &[ToolCall],
+) -> Result<()> {
+    let settings = ctx.settings.read().await.clone();
+
+    let result = crate::abilities::execute_tool_calls(
+        ctx.pool,
+        ctx.channel,
+        ctx.user_id,
+        ctx.app_local_data_dir,
+        &settings,
+        message,
+        tool_calls,
+    )
+    .await;
+    if result.is_ok() {
+        return Ok(());
+    }
+
+    let ids = tool_calls
+        .iter()
+        .map(|tool_call| tool_call.id.clone())
+        .collect::<Vec<_>>();
+    let answered_ids = crate::repo::messages::list_answered_tool_call_ids(
+        ctx.pool,
+        ctx.company_id,
+        message.chat_id,
+        &ids,
+    )
+    .await?;
+    let failed_ids = ids
+        .into_iter()
+        .filter(|id| !answered_ids.contains(id))
+        .collect::<Vec<_>>();
+    crate::repo::tool_call_policies::release_decisions(
+        ctx.pool,
+        ctx.company_id,
+        message.id,
+        &failed_ids,
+    )
+    .await?;
+
+    result
+}
+
+/// Deny the tool calls of the message, letting LLM know about it.
+async fn deny_tool_calls(
+    ctx: &Context<'_>,
+    message: &Message,
+    tool_calls: &[ToolCall],
+) -> Result<()> {
+    let denied_messages = repo::messages::create_multiple(
+        ctx.pool,
+        ctx.company_id,
+        tool_call_denied_params(message, tool_calls),
+    )
+    .await?;
+
+    for denied_message in denied_messages {
+        ctx.channel
+            .emit(ctx.user_id, Event::MessageCreated(&denied_message))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Mark the message as done with its tool calls, if every one of them is answered.
+///
+/// The message becomes `ToolCallDenied` if all of its tool calls were denied, and `Completed`
+/// otherwise. Returns whether the message has been settled by this call: with tool calls answered
+/// concurrently, only the caller which settled it should continue the conversation.
+async fn settle_tool_calls(ctx: &Context<'_>, message: &mut Message) -> Result<bool> {
+    let ids = message
+        .tool_calls()
+        .iter()
+        .map(|tool_call| tool_call.id.clone())
+        .collect::<Vec<_>>();
+
+    let answered_ids = crate::repo::messages::list_answered_tool_call_ids(
+        ctx.pool,
+        ctx.company_id,
+        message.chat_id,
+        &ids,
+    )
+    .await?;
+
+    if answered_ids.len() < ids.len() {
+        return Ok(false);
+    }
+
+    let decisions = crate::repo::tool_call_policies::list_decisions_for_message(
+        ctx.pool,
+        ctx.company_id,
+        message.id,
+    )
+    .await?;
+    let is_denied = ids.iter().all(|id| {
+        decisions
+            .iter()
+            .any(|decision| &decision.tool_call_id == id && decision.decision == Decision::Deny)
+    });
+    let status = if is_denied {
+        Status::ToolCallDenied
+    } else {
+        Status::Completed
+    };
+
+    if !crate::repo::messages::update_status_if(
+        ctx.pool,
+        ctx.company_id,
+        message.id,
+        Status::WaitingForToolCall,
+        status,
+    )
+    .await?
+    {
+        return Ok(false);
+    }
+
+    message.status = status;
+    ctx.channel
+        .emit(ctx.user_id, Event::MessageUpdated(message))
+        .await?;
+
+    Ok(true)
+}
+
+/// Record the user decision on the tool calls of the message, overriding the policy decisions to
+/// ask. Returns the tool calls claimed this way: the others have been decided in the meantime, by
+/// a concurrent call or a policy, and are up to whoever decided them.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn claim_tool_calls<'a, E>(
+    executor: E,
+    message: &Message,
+    tool_calls: Vec<ToolCall>,
+    decision: Decision,
+) -> Result<Vec<ToolCall>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let ids = tool_calls
+        .iter()
+        .map(|tool_call| tool_call.id.clone())
+        .collect();
+
+    let claimed = crate::repo::tool_call_policies::claim_decisions(
+        executor,
+        message.company_id,
+        message.id,
+        user_decision_params(ids, decision),
+    )
+    .await?;
+
+    Ok(tool_calls
+        .into_iter()
+        .filter(|tool_call| {
+            claimed
+                .iter()
+                .any(|decision| decision.tool_call_id == tool_call.id)
+        })
+        .collect())
+}
+
+fn user_decision_params(
+    tool_call_ids: Vec<String>,
+    decision: Decision,
+) -> Vec<CreateDecisionParams> {
+    let reason = match decision {
+        Decision::Allow => "approved by user",
+        Decision::Ask => "left for user",
+        Decision::Deny => "denied by user",
+    };
+
+    tool_call_ids
+        .into_iter()
+        .map(|tool_call_id| CreateDecisionParams {
+            tool_call_id,
+            decision,
+            policy_id: None,
+            reason: reason.to_string(),
+        })
+        .collect()
+}
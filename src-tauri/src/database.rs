@@ -6,6 +6,20 @@ use tracing::debug;
 
 use crate::types::Result;
 
+/// Create or update the tables owned by the application.
+///
+/// # Errors
+///
+/// This function will return an error if any of the queries fail to execute.
+pub async fn migrate(pool: &Pool<Postgres>) -> Result<()> {
+    debug!("Migrating application tables");
+    for query in split_queries(include_str!("../db/schema.sql")) {
+        sqlx::query(query).execute(pool).await?;
+    }
+
+    Ok(())
+}
+
 /// Seed the database with initial data
 ///
 /// # Errors
@@ -13,16 +27,15 @@ use crate::types::Result;
 /// This function will return an error if any of the queries fail to execute.
 pub async fn seed(pool: &Pool<Postgres>) -> Result<()> {
     debug!("Seeding the database");
-    for query in seed_queries() {
+    for query in split_queries(include_str!("../db/seeds.sql")) {
         sqlx::query(query).execute(pool).await?;
     }
 
     Ok(())
 }
 
-fn seed_queries() -> Vec<&'static str> {
-    include_str!("../db/seeds.sql")
-        .split(';')
+fn split_queries(sql: &'static str) -> Vec<&'static str> {
+    sql.split(';')
         .map(str::trim)
         .filter(|q| !q.is_empty())
         .collect()
@@ -0,0 +1,321 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use bollard::{
+    container::{
+        Config, KillContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions,
+        WaitContainerOptions,
+    },
+    image::CreateImageOptions,
+    secret::{HostConfig, ResourcesUlimits},
+    Docker,
+};
+use futures_util::{StreamExt, TryStreamExt};
+use tokio::time::{self, Instant};
+use tracing::trace;
+
+use crate::{settings::Sandbox, types::Result};
+
+const CONTAINER_WORKDIR: &str = "/bridge";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Bollard(#[from] bollard::errors::Error),
+}
+
+/// Host directory mounted into a container in addition to the workdir.
+#[derive(Debug, Clone)]
+pub struct Volume {
+    pub host_path: PathBuf,
+    pub container_path: String,
+    pub is_read_only: bool,
+}
+
+impl Volume {
+    fn bind(&self) -> String {
+        let mut bind = format!(
+            "{}:{}",
+            self.host_path.to_string_lossy(),
+            self.container_path
+        );
+        if self.is_read_only {
+            bind.push_str(":ro");
+        }
+
+        bind
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RunParams<'a> {
+    pub image: &'a str,
+    /// Host directory mounted as the working directory, the only writable place besides `/tmp`.
+    pub workdir: Option<&'a Path>,
+    pub volumes: &'a [Volume],
+    /// Environment variables in `NAME=value` form.
+    pub env: Vec<String>,
+    pub cmd: Vec<String>,
+    /// Wall time after which the container is killed.
+    pub timeout: Option<Duration>,
+}
+
+/// Output of a command run in a container.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i64,
+    /// Whether the command was stopped for exceeding the output limit.
+    pub is_truncated: bool,
+    /// Whether the command was stopped for exceeding the timeout.
+    pub is_timed_out: bool,
+}
+
+/// Run a command in a fresh container, within the limits of the `sandbox`.
+///
+/// Root filesystem of the container is read-only, so the command can only write to the workdir,
+/// volumes mounted as writable and `/tmp`. Network is only available if the sandbox allows it.
+/// The container is killed once the command exceeds the output limit or the timeout, if set.
+/// If the sandbox is disabled, only the timeout applies.
+///
+/// Unlike `bridge_common::docker`, the container is run without a TTY, so `stdout` and `stderr`
+/// are kept apart.
+///
+/// # Errors
+///
+/// Will return an error if there was a problem while pulling the image or running the container.
+pub async fn run(params: RunParams<'_>, sandbox: &Sandbox) -> Result<Output> {
+    let docker = Docker::connect_with_local_defaults().map_err(Error::Bollard)?;
+
+    pull(&docker, params.image).await?;
+
+    let mut binds = params
+        .workdir
+        .map(|workdir| format!("{}:{CONTAINER_WORKDIR}", workdir.to_string_lossy()))
+        .into_iter()
+        .collect::<Vec<_>>();
+    binds.extend(params.volumes.iter().map(Volume::bind));
+
+    let config = Config {
+        image: Some(params.image.to_string()),
+        cmd: Some(params.cmd),
+        env: Some(params.env),
+        working_dir: params.workdir.map(|_| CONTAINER_WORKDIR.to_string()),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        network_disabled: Some(sandbox.enabled && !sandbox.is_network_enabled),
+        host_config: Some(if sandbox.enabled {
+            host_config(binds, sandbox)
+        } else {
+            HostConfig {
+                binds: Some(binds),
+                ..Default::default()
+            }
+        }),
+        ..Default::default()
+    };
+
+    let id = docker
+        .create_container::<String, String>(None, config)
+        .await
+        .map_err(Error::Bollard)?
+        .id;
+
+    let output_limit_bytes = if sandbox.enabled {
+        sandbox.output_limit_bytes
+    } else {
+        usize::MAX
+    };
+    let output = collect_output(&docker, &id, output_limit_bytes, params.timeout).await;
+
+    docker
+        .remove_container(
+            &id,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .map_err(Error::Bollard)?;
+
+    trace!("Container output: {:?}", output);
+
+    output
+}
+
+/// Get the ID of the image, pulling it if needed.
+///
+/// # Errors
+///
+/// Will return an error if there was a problem while pulling or inspecting the image.
+pub async fn image_id(image: &str) -> Result<String> {
+    let docker = Docker::connect_with_local_defaults().map_err(Error::Bollard)?;
+
+    pull(&docker, image).await?;
+
+    let inspect = docker.inspect_image(image).await.map_err(Error::Bollard)?;
+
+    Ok(inspect
+        .id
+        .with_context(|| format!("Image `{image}` has no ID"))?)
+}
+
+async fn pull(docker: &Docker, image: &str) -> Result<()> {
+    docker
+        .create_image(
+            Some(CreateImageOptions {
+                from_image: image,
+                ..Default::default()
+            }),
+            None,
+            None,
+        )
+        .try_collect::<Vec<_>>()
+        .await
+        .with_context(|| format!("Failed to create image `{image}`"))?;
+
+    Ok(())
+}
+
+fn host_config(binds: Vec<String>, sandbox: &Sandbox) -> HostConfig {
+    let memory =
+        i64::try_from(sandbox.memory_limit_mb.saturating_mul(1024 * 1024)).unwrap_or(i64::MAX);
+    let cpu_time = i64::try_from(sandbox.cpu_time_limit_secs).unwrap_or(i64::MAX);
+
+    HostConfig {
+        binds: Some(binds),
+        network_mode: (!sandbox.is_network_enabled).then(|| "none".to_string()),
+        readonly_rootfs: Some(true),
+        tmpfs: Some(HashMap::from([(
+            "/tmp".to_string(),
+            "rw,nosuid,nodev".to_string(),
+        )])),
+        security_opt: Some(vec!["no-new-privileges".to_string()]),
+        memory: Some(memory),
+        // No swap, so the memory limit is the actual limit
+        memory_swap: Some(memory),
+        nano_cpus: Some(nano_cpus(sandbox.cpus)),
+        pids_limit: Some(i64::try_from(sandbox.processes_limit).unwrap_or(i64::MAX)),
+        ulimits: Some(vec![ResourcesUlimits {
+            name: Some("cpu".to_string()),
+            soft: Some(cpu_time),
+            hard: Some(cpu_time),
+        }]),
+        ..Default::default()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn nano_cpus(cpus: f64) -> i64 {
+    (cpus.max(0.01) * 1e9).round() as i64
+}
+
+async fn collect_output(
+    docker: &Docker,
+    id: &str,
+    output_limit_bytes: usize,
+    timeout: Option<Duration>,
+) -> Result<Output> {
+    docker
+        .start_container::<String>(id, None)
+        .await
+        .map_err(Error::Bollard)?;
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let mut output = Output::default();
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+
+    let mut logs = docker.logs(
+        id,
+        Some(LogsOptions::<String> {
+            follow: true,
+            stdout: true,
+            stderr: true,
+            ..Default::default()
+        }),
+    );
+    loop {
+        let log = match deadline {
+            Some(deadline) => {
+                let Ok(log) = time::timeout_at(deadline, logs.next()).await else {
+                    output.is_timed_out = true;
+
+                    break;
+                };
+
+                log
+            }
+            None => logs.next().await,
+        };
+        let Some(log) = log else {
+            break;
+        };
+
+        let available = output_limit_bytes.saturating_sub(stdout.len() + stderr.len());
+        let (buffer, message) = match log.map_err(Error::Bollard)? {
+            LogOutput::StdOut { message } => (&mut stdout, message),
+            LogOutput::StdErr { message } => (&mut stderr, message),
+            LogOutput::StdIn { .. } | LogOutput::Console { .. } => continue,
+        };
+
+        if message.len() > available {
+            buffer.extend_from_slice(&message[..available]);
+            output.is_truncated = true;
+
+            break;
+        }
+
+        buffer.extend_from_slice(&message);
+    }
+    drop(logs);
+
+    output.stdout = String::from_utf8_lossy(&stdout).into_owned();
+    output.stderr = String::from_utf8_lossy(&stderr).into_owned();
+
+    // There's no point in waiting for the command which nobody listens to anymore
+    if output.is_truncated || output.is_timed_out {
+        kill(docker, id).await?;
+    }
+
+    let mut wait = docker.wait_container(
+        id,
+        Some(WaitContainerOptions {
+            condition: "not-running",
+        }),
+    );
+    while let Some(response) = wait.next().await {
+        output.exit_code = match response {
+            Ok(response) => response.status_code,
+            // Non-zero exit codes are reported as errors by `bollard`
+            Err(bollard::errors::Error::DockerContainerWaitError { code, .. }) => code,
+            Err(err) => return Err(Error::Bollard(err).into()),
+        };
+    }
+
+    Ok(output)
+}
+
+async fn kill(docker: &Docker, id: &str) -> Result<()> {
+    match docker
+        .kill_container(id, Some(KillContainerOptions { signal: "SIGKILL" }))
+        .await
+    {
+        // The container may have exited on its own in the meantime
+        Ok(())
+        | Err(bollard::errors::Error::DockerResponseServerError {
+            status_code: 409, ..
+        }) => Ok(()),
+        Err(err) => Err(Error::Bollard(err).into()),
+    }
+}
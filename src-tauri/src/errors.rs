@@ -1,6 +1,16 @@
 // Copyright 2024 StarfleetAI
 // SPDX-License-Identifier: Apache-2.0
 
+//! Errors returned by the commands.
+//!
+//! The frontend receives every error as `{code, message, details}`. `code` is stable and tells
+//! errors apart, `message` is meant for humans, and `details` carries whatever the frontend may
+//! need to offer a fix, e.g. the provider which has no API key.
+
+use bridge_common::types::models::Provider;
+use serde::ser::SerializeStruct;
+use serde_json::{json, Value};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -10,9 +20,88 @@ pub enum Error {
     #[error(transparent)]
     TokioJoin(#[from] tokio::task::JoinError),
     #[error(transparent)]
+    Abilities(#[from] crate::abilities::Error),
+    #[error(transparent)]
     Common(#[from] bridge_common::errors::Error),
     #[error(transparent)]
+    Docker(#[from] crate::docker::Error),
+    #[error("no API key is set for provider `{0:?}`")]
+    MissingApiKey(Provider),
+    #[error(transparent)]
+    PlanReviews(#[from] crate::plan_reviews::Error),
+    #[error(transparent)]
+    Secrets(#[from] crate::secrets::Error),
+    #[error(transparent)]
+    Settings(#[from] crate::settings::Error),
+    #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
+    #[error(transparent)]
+    TaskExecutor(#[from] crate::task_executor::Error),
+    #[error(transparent)]
+    TaskPlans(#[from] crate::task_plans::Error),
+    #[error(transparent)]
+    TaskQueue(#[from] crate::task_queue::Error),
+    #[error(transparent)]
+    TaskTemplates(#[from] crate::task_templates::Error),
+    #[error(transparent)]
+    ToolCallPolicies(#[from] crate::tool_call_policies::Error),
+    #[error(transparent)]
+    Webhooks(#[from] crate::webhooks::Error),
+    #[error(transparent)]
+    Workspaces(#[from] crate::workspaces::Error),
+}
+
+impl Error {
+    /// Stable code the frontend can tell the error by.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        self.describe().0
+    }
+
+    /// Data the frontend may need to handle the error, `null` if there is none.
+    #[must_use]
+    pub fn details(&self) -> Value {
+        self.describe().1
+    }
+
+    fn describe(&self) -> (&'static str, Value) {
+        match self {
+            Error::Internal(err) => describe_chain(err),
+            Error::Tauri(_)
+            | Error::TokioJoin(_)
+            | Error::TaskExecutor(crate::task_executor::Error::NotAnExecutionChat(_)) => {
+                ("internal", Value::Null)
+            }
+            Error::Abilities(err) => describe_abilities(err),
+            Error::Common(err) => describe_common(err),
+            Error::Docker(_) => ("docker", Value::Null),
+            Error::MissingApiKey(provider) => ("missing_api_key", json!({ "provider": provider })),
+            Error::PlanReviews(err) => (describe_plan_reviews(err), Value::Null),
+            Error::Secrets(err) => (describe_secrets(err), Value::Null),
+            Error::Settings(crate::settings::Error::UnsupportedVersion(version)) => (
+                "unsupported_settings_version",
+                json!({ "version": version, "supported_version": crate::settings::VERSION }),
+            ),
+            Error::Settings(_) => ("invalid_settings", Value::Null),
+            Error::Sqlx(err) => (describe_sqlx(err), Value::Null),
+            Error::TaskExecutor(crate::task_executor::Error::NoRootTasks) => {
+                ("no_root_tasks", Value::Null)
+            }
+            Error::TaskPlans(err) => describe_task_plans(err),
+            Error::TaskQueue(crate::task_queue::Error::NotRootTask(task_id)) => {
+                ("not_root_task", json!({ "task_id": task_id }))
+            }
+            Error::TaskQueue(_) => ("invalid_queue_order", Value::Null),
+            Error::TaskTemplates(err) => describe_task_templates(err),
+            Error::ToolCallPolicies(err) => describe_tool_call_policies(err),
+            Error::Webhooks(
+                crate::webhooks::Error::UnknownEvent(event)
+                | crate::webhooks::Error::StreamedEvent(event),
+            ) => ("invalid_webhook", json!({ "event": event })),
+            Error::Webhooks(_) => ("invalid_webhook", Value::Null),
+            Error::Workspaces(err) => (describe_workspaces(err), Value::Null),
+        }
+    }
 }
 
 impl serde::Serialize for Error {
@@ -20,6 +109,196 @@ impl serde::Serialize for Error {
     where
         S: serde::ser::Serializer,
     {
-        serializer.serialize_str(format!("{self:#}").as_str())
+        let (code, details) = self.describe();
+
+        let mut state = serializer.serialize_struct("Error", 3)?;
+        state.serialize_field("code", code)?;
+        state.serialize_field("message", &format!("{self:#}"))?;
+        state.serialize_field("details", &details)?;
+        state.end()
+    }
+}
+
+/// Errors wrapped with `anyhow` context are described by the first known error in the chain.
+fn describe_chain(err: &anyhow::Error) -> (&'static str, Value) {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<Error>() {
+            return err.describe();
+        }
+        if let Some(err) = cause.downcast_ref::<bridge_common::errors::Error>() {
+            return describe_common(err);
+        }
+        if let Some(err) = cause.downcast_ref::<crate::abilities::Error>() {
+            return describe_abilities(err);
+        }
+        if let Some(err) = cause.downcast_ref::<sqlx::Error>() {
+            return (describe_sqlx(err), Value::Null);
+        }
+    }
+
+    ("internal", Value::Null)
+}
+
+fn describe_common(err: &bridge_common::errors::Error) -> (&'static str, Value) {
+    use bridge_common::{abilities, errors::Error as Common, messages, models, task_executor};
+
+    match err {
+        Common::Application(err) => describe_chain(err),
+        Common::Sqlx(err) => (describe_sqlx(err), Value::Null),
+        Common::Abilities(abilities::Error::IsUsedByAgents) => {
+            ("ability_is_used_by_agents", Value::Null)
+        }
+        Common::Messages(messages::Error::TooFewMessages(count)) => {
+            ("too_few_messages", json!({ "count": count }))
+        }
+        Common::Messages(messages::Error::NoSuitableMessages) => {
+            ("no_suitable_messages", Value::Null)
+        }
+        Common::Messages(messages::Error::LastMessageNotFromAssistant) => {
+            ("last_message_not_from_assistant", Value::Null)
+        }
+        Common::Messages(messages::Error::FailedToCreateChatCompletion(_)) => {
+            ("completion_failed", Value::Null)
+        }
+        Common::Messages(
+            messages::Error::EmptyLLMResponseReceived | messages::Error::UnexpectedResponse,
+        ) => ("unexpected_llm_response", Value::Null),
+        Common::Messages(messages::Error::NoToolCallsFound) => ("no_tool_calls", Value::Null),
+        Common::Models(models::Error::DefaultModelNotFound(_, model)) => {
+            ("default_model_not_found", json!({ "model": model }))
+        }
+        Common::Executor(task_executor::Error::NoRootTasks) => ("no_root_tasks", Value::Null),
+        Common::Planner(_) => ("planning_failed", Value::Null),
+        Common::Browser(_) | Common::WebBrowsing(_) => ("web_browsing", Value::Null),
+        Common::Docker(_) => ("docker", Value::Null),
+        Common::Pages(_) => ("invalid_page", Value::Null),
+        Common::Settings(_) => ("invalid_settings", Value::Null),
+        _ => ("internal", Value::Null),
+    }
+}
+
+fn describe_abilities(err: &crate::abilities::Error) -> (&'static str, Value) {
+    use crate::abilities::Error as Abilities;
+
+    match err {
+        Abilities::InvalidArguments(errors) => ("invalid_arguments", json!({ "errors": errors })),
+        Abilities::NoResult { exit_code } => {
+            ("ability_no_result", json!({ "exit_code": exit_code }))
+        }
+        Abilities::NoFunctionFound => ("no_function_found", Value::Null),
+        Abilities::UnsupportedParameter(parameter) => {
+            ("unsupported_parameter", json!({ "parameter": parameter }))
+        }
+        Abilities::UnsupportedLanguage(language) => {
+            ("unsupported_language", json!({ "language": language }))
+        }
+        Abilities::RequirementsNotSupported(language) => (
+            "requirements_not_supported",
+            json!({ "language": language }),
+        ),
+        Abilities::FunctionDefinition(_) => ("function_definition_failed", Value::Null),
+        Abilities::InvalidRequirement(requirement) => {
+            ("invalid_requirement", json!({ "requirement": requirement }))
+        }
+        Abilities::RequirementNotPinned(requirement) => (
+            "requirement_not_pinned",
+            json!({ "requirement": requirement }),
+        ),
+        Abilities::RequirementsInstall(_) => ("requirements_install_failed", Value::Null),
+    }
+}
+
+fn describe_plan_reviews(err: &crate::plan_reviews::Error) -> &'static str {
+    use crate::plan_reviews::Error as PlanReviews;
+
+    match err {
+        PlanReviews::PlanningUnavailable(_) => "planning_unavailable",
+        PlanReviews::NoPlan => "planning_failed",
+        PlanReviews::EmptyPlan | PlanReviews::EmptySubtaskTitle => "invalid_plan",
+        PlanReviews::NotPending => "plan_proposal_reviewed",
+        PlanReviews::NoFeedback => "no_plan_feedback",
+    }
+}
+
+fn describe_secrets(err: &crate::secrets::Error) -> &'static str {
+    use crate::secrets::Error as Secrets;
+
+    match err {
+        Secrets::NoKeyStorage => "no_secrets_key_storage",
+        Secrets::WrongPassphrase => "wrong_secrets_passphrase",
+        Secrets::Encrypt | Secrets::Decrypt | Secrets::CorruptedKey => "secrets",
+    }
+}
+
+fn describe_sqlx(err: &sqlx::Error) -> &'static str {
+    match err {
+        sqlx::Error::RowNotFound => "not_found",
+        _ => "database",
+    }
+}
+
+fn describe_task_plans(err: &crate::task_plans::Error) -> (&'static str, Value) {
+    use crate::task_plans::Error as TaskPlans;
+
+    match err {
+        TaskPlans::InProgress => ("task_in_progress", Value::Null),
+        TaskPlans::NotSubtask(task_id) => ("invalid_subtask", json!({ "task_id": task_id })),
+        TaskPlans::InvalidPosition { position, count } => (
+            "invalid_subtask",
+            json!({ "position": position, "count": count }),
+        ),
+        TaskPlans::EmptyTitle
+        | TaskPlans::Cycle
+        | TaskPlans::OtherTree
+        | TaskPlans::OrderMismatch => ("invalid_subtask", Value::Null),
+    }
+}
+
+fn describe_task_templates(err: &crate::task_templates::Error) -> (&'static str, Value) {
+    use crate::task_templates::Error as TaskTemplates;
+
+    match err {
+        TaskTemplates::EmptyName | TaskTemplates::EmptyTitle | TaskTemplates::EmptySubtaskTitle => {
+            ("invalid_task_template", Value::Null)
+        }
+        TaskTemplates::InvalidParameterName(parameter)
+        | TaskTemplates::DuplicateParameter(parameter)
+        | TaskTemplates::InvalidDefault(parameter) => {
+            ("invalid_task_template", json!({ "parameter": parameter }))
+        }
+        TaskTemplates::UnknownPlaceholder(placeholder) => (
+            "invalid_task_template",
+            json!({ "placeholder": placeholder }),
+        ),
+        TaskTemplates::NoAgent => ("no_template_agent", Value::Null),
+        TaskTemplates::MissingArgument(argument)
+        | TaskTemplates::UnknownArgument(argument)
+        | TaskTemplates::InvalidArgument { name: argument, .. } => (
+            "invalid_template_arguments",
+            json!({ "argument": argument }),
+        ),
+    }
+}
+
+fn describe_tool_call_policies(err: &crate::tool_call_policies::Error) -> (&'static str, Value) {
+    use crate::tool_call_policies::Error as ToolCallPolicies;
+
+    match err {
+        ToolCallPolicies::AbilityMismatch | ToolCallPolicies::NoRules => {
+            ("invalid_tool_call_policy", Value::Null)
+        }
+        ToolCallPolicies::InvalidRule { argument, .. } => {
+            ("invalid_tool_call_policy", json!({ "argument": argument }))
+        }
+    }
+}
+
+fn describe_workspaces(err: &crate::workspaces::Error) -> &'static str {
+    use crate::workspaces::Error as Workspaces;
+
+    match err {
+        Workspaces::IsActive => "workspace_is_active",
+        Workspaces::IsDefault => "default_workspace",
+        Workspaces::EmptyName => "empty_workspace_name",
     }
 }
@@ -0,0 +1,330 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Events the clients are kept up to date with.
+//!
+//! `bridge_common` emits its events, about chats, messages, tasks and their results, through the
+//! `Channel` it's given. The application has events of its own on top of these: deletions, agent
+//! and ability changes, plan proposals, streamed message deltas and executor worker status. Both
+//! kinds go through `Events`: every event is sent to the `Sink` of the app, and, unless it's one of
+//! the per-chunk updates of a message being streamed, appended to the event log and queued for the
+//! webhooks.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use bridge_common::{
+    channel::{Emitter, Event},
+    types::messages::{Message, Role, Status},
+};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{debug, error, instrument, trace};
+
+use crate::{
+    channel::{Emitted, Sink},
+    commands::agents::Agent,
+    repo::{self, events::CreateParams},
+    task_executor::WorkerState,
+    types::{abilities::Ability, plan_proposals::PlanProposal, DbPool, Result},
+    webhooks,
+};
+
+/// Names of every event, as emitted.
+pub const EVENT_NAMES: &[&str] = &[
+    "abilities:created",
+    "abilities:deleted",
+    "abilities:updated",
+    "agents:created",
+    "agents:deleted",
+    "agents:updated",
+    "chats:deleted",
+    "chats:updated",
+    "executor:updated",
+    "messages:created",
+    "messages:deleted",
+    "messages:delta",
+    "messages:updated",
+    "pages:deleted",
+    "plan_proposals:created",
+    "plan_proposals:updated",
+    "task_results:created",
+    "tasks:created",
+    "tasks:deleted",
+    "tasks:updated",
+];
+
+/// Events of the application, emitted along with those of `bridge_common`, the same way.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+pub enum AppEvent<'a> {
+    AbilityCreated(&'a Ability),
+    AbilityUpdated(&'a Ability),
+    AbilityDeleted(Deleted),
+    AgentCreated(&'a Agent),
+    AgentUpdated(&'a Agent),
+    AgentDeleted(Deleted),
+    ChatDeleted(Deleted),
+    ExecutorUpdated(&'a WorkerState),
+    MessageDeleted(Deleted),
+    MessageDelta(&'a MessageDelta),
+    PageDeleted(Deleted),
+    PlanProposalCreated(&'a PlanProposal),
+    PlanProposalUpdated(&'a PlanProposal),
+    /// Emitted for the task deleted only, not for its children deleted along.
+    TaskDeleted(Deleted),
+}
+
+impl AppEvent<'_> {
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::AbilityCreated(_) => "abilities:created",
+            AppEvent::AbilityUpdated(_) => "abilities:updated",
+            AppEvent::AbilityDeleted(_) => "abilities:deleted",
+            AppEvent::AgentCreated(_) => "agents:created",
+            AppEvent::AgentUpdated(_) => "agents:updated",
+            AppEvent::AgentDeleted(_) => "agents:deleted",
+            AppEvent::ChatDeleted(_) => "chats:deleted",
+            AppEvent::ExecutorUpdated(_) => "executor:updated",
+            AppEvent::MessageDeleted(_) => "messages:deleted",
+            AppEvent::MessageDelta(_) => "messages:delta",
+            AppEvent::PageDeleted(_) => "pages:deleted",
+            AppEvent::PlanProposalCreated(_) => "plan_proposals:created",
+            AppEvent::PlanProposalUpdated(_) => "plan_proposals:updated",
+            AppEvent::TaskDeleted(_) => "tasks:deleted",
+        }
+    }
+}
+
+/// Subject of a deletion event.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Deleted {
+    pub id: i64,
+}
+
+impl Deleted {
+    #[must_use]
+    pub fn new(id: impl Into<i64>) -> Self {
+        Self { id: id.into() }
+    }
+}
+
+/// Content an assistant message got since the previous delta, while it's streamed.
+#[derive(Serialize, Debug, Clone)]
+pub struct MessageDelta {
+    pub id: i64,
+    pub chat_id: i32,
+    pub delta: String,
+    /// Whether the message is written completely, the delta being the last one.
+    pub is_done: bool,
+}
+
+/// Name the event of `bridge_common` is emitted under.
+#[must_use]
+pub fn event_name(event: &Event) -> &'static str {
+    match event {
+        Event::ChatUpdated(_) => "chats:updated",
+        Event::TaskCreated(_) => "tasks:created",
+        Event::TaskUpdated(_) => "tasks:updated",
+        Event::MessageCreated(_) => "messages:created",
+        Event::MessageUpdated(_) => "messages:updated",
+        Event::TaskResultCreated(_) => "task_results:created",
+    }
+}
+
+/// Company the subject of the event of `bridge_common` belongs to.
+#[must_use]
+pub fn company_id(event: &Event) -> i32 {
+    match event {
+        Event::ChatUpdated(chat) => chat.company_id,
+        Event::MessageCreated(message) | Event::MessageUpdated(message) => message.company_id,
+        Event::TaskCreated(task) | Event::TaskUpdated(task) => task.company_id,
+        Event::TaskResultCreated(task_result) => task_result.company_id,
+    }
+}
+
+/// Emits the events of both the application and `bridge_common`, which is given it as `Channel`.
+#[derive(Clone)]
+pub struct Events(Arc<Inner>);
+
+struct Inner {
+    sink: Box<dyn Sink>,
+    log: EventLog,
+    webhooks: webhooks::Sender,
+    /// Bytes sent as deltas so far of each assistant message being written.
+    deltas: Mutex<HashMap<i64, usize>>,
+}
+
+#[async_trait]
+impl Emitter for Events {
+    #[instrument(skip(self, event))]
+    async fn emit<'a>(&self, _user_id: i32, event: Event<'a>) -> bridge_common::types::Result<()> {
+        debug!("Emitting event");
+        trace!("Event: {:?}", event);
+
+        // Updates of the messages being written come one per streamed chunk, each superseding the
+        // previous one, so there is no point in logging them or sending them to the webhooks
+        let is_logged = !matches!(
+            &event,
+            Event::MessageUpdated(message) if message.status == Status::Writing
+        );
+        self.send(company_id(&event), event_name(&event), &event, is_logged)
+            .await?;
+
+        let delta = match &event {
+            Event::MessageCreated(message) => self.delta(message, true)?,
+            Event::MessageUpdated(message) => self.delta(message, false)?,
+            _ => None,
+        };
+        if let Some(delta) = delta {
+            self.send(
+                company_id(&event),
+                "messages:delta",
+                &AppEvent::MessageDelta(&delta),
+                false,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Events {
+    /// Create events sent to the sink, returning the queue to start the webhooks with.
+    #[must_use]
+    pub fn new(sink: Box<dyn Sink>, pool: DbPool) -> (Self, webhooks::Queue) {
+        let (webhooks, queue) = webhooks::queue();
+        let inner = Inner {
+            sink,
+            log: EventLog(pool),
+            webhooks,
+            deltas: Mutex::default(),
+        };
+
+        (Self(Arc::new(inner)), queue)
+    }
+
+    /// Emit the event of the application.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the event can't be sent to the sink.
+    #[instrument(skip(self, event))]
+    pub async fn emit_app(&self, company_id: i32, event: AppEvent<'_>) -> Result<()> {
+        debug!("Emitting event");
+        trace!("Event: {:?}", event);
+
+        let is_logged = !matches!(event, AppEvent::MessageDelta(_));
+        self.send(company_id, event.name(), &event, is_logged)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send<E: Serialize + Sync>(
+        &self,
+        company_id: i32,
+        name: &'static str,
+        event: &E,
+        is_logged: bool,
+    ) -> anyhow::Result<()> {
+        let payload = serde_json::to_value(event).context("Failed to serialize event")?;
+        let seq = if is_logged {
+            self.0.log.append(company_id, name, &payload).await
+        } else {
+            None
+        };
+
+        let emitted = Emitted { name, seq, payload };
+        self.0.sink.send(&emitted)?;
+        if is_logged {
+            self.0.webhooks.push(company_id, name, emitted.payload);
+        }
+
+        Ok(())
+    }
+
+    /// Delta of the assistant message, if it's still to be sent.
+    ///
+    /// Messages are tracked only while they're written: the last delta is sent once the message
+    /// is no longer `Writing`, and the later updates of the message are not written ones. Messages
+    /// created written completely get their content as a single delta.
+    fn delta(&self, message: &Message, is_created: bool) -> anyhow::Result<Option<MessageDelta>> {
+        if message.role != Role::Assistant {
+            return Ok(None);
+        }
+
+        let is_done = message.status != Status::Writing;
+        let mut deltas = self
+            .0
+            .deltas
+            .lock()
+            .map_err(|_| anyhow!("Message deltas lock is poisoned"))?;
+        let len = match (deltas.get(&message.id).copied(), is_done) {
+            (Some(len), false) => len,
+            (Some(len), true) => {
+                deltas.remove(&message.id);
+                len
+            }
+            (None, false) => 0,
+            (None, true) if is_created => 0,
+            (None, true) => return Ok(None),
+        };
+
+        let content = message.content.as_deref().unwrap_or_default();
+        let delta = content.get(len..).unwrap_or_default().to_string();
+        if !is_done {
+            deltas.insert(message.id, content.len().max(len));
+        }
+
+        if delta.is_empty() && !is_done {
+            return Ok(None);
+        }
+
+        Ok(Some(MessageDelta {
+            id: message.id,
+            chat_id: message.chat_id,
+            delta,
+            is_done,
+        }))
+    }
+}
+
+/// Append-only log of the events, for the clients which missed them to catch up.
+struct EventLog(DbPool);
+
+impl EventLog {
+    /// Append the event to the log, returning its sequence number. Events failed to be logged
+    /// must not stop them from being emitted, so `None` is returned for those.
+    async fn append(&self, company_id: i32, name: &str, payload: &Value) -> Option<i64> {
+        let params = CreateParams {
+            name: name.to_string(),
+            payload: payload.clone(),
+        };
+
+        match self.create(company_id, params).await {
+            Ok(seq) => Some(seq),
+            Err(err) => {
+                error!("Failed to log event: {:?}", err);
+                None
+            }
+        }
+    }
+
+    async fn create(&self, company_id: i32, params: CreateParams) -> Result<i64> {
+        let mut tx = self.0.begin().await?;
+
+        repo::events::lock_for_append(&mut *tx, company_id).await?;
+        let seq = repo::events::create(&mut *tx, company_id, params).await?;
+
+        tx.commit().await?;
+
+        Ok(seq)
+    }
+}
@@ -3,18 +3,34 @@
 
 use lazy_static::lazy_static;
 
+pub mod abilities;
 pub mod channel;
+pub mod cli;
+pub mod code_interpreter;
 pub mod commands;
+pub mod conversations;
 pub mod database;
+pub mod docker;
 pub mod errors;
+pub mod events;
 pub mod messages;
+pub mod notifications;
+pub mod plan_reviews;
+pub mod providers;
+pub mod repo;
+pub mod secrets;
+pub mod server;
+pub mod settings;
+pub mod state;
 pub mod task_executor;
+pub mod task_plans;
+pub mod task_queue;
+pub mod task_templates;
+pub mod task_traces;
+pub mod tool_call_policies;
 pub mod types;
-
-/// Virtual company ID for local database.
-pub const CID: i32 = 0;
-/// Virtual user ID for local database.
-pub const UID: i32 = 0;
+pub mod webhooks;
+pub mod workspaces;
 
 lazy_static! {
     static ref USER_AGENT: String = format!("StarfleetAI-Bridge/{}", env!("CARGO_PKG_VERSION"));
@@ -4,77 +4,69 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
 use anyhow::Context;
-use bridge_common::{channel::Channel, repo};
+use bridge_common::channel::Channel;
 use dotenvy::dotenv;
-use tauri::{async_runtime::block_on, generate_handler, App, LogicalSize, Manager};
+use tauri::{async_runtime::block_on, generate_handler, App, LogicalSize, Manager, WindowEvent};
 use tokio::sync::RwLock;
 use tracing::info;
 use tracing_subscriber::{fmt, EnvFilter};
 
-use bridge::{channel::TauriChannel, commands, database, task_executor, types::Result};
+use bridge::{
+    channel::TauriChannel,
+    cli, commands,
+    events::Events,
+    notifications::Notifier,
+    state::{self, AppLocalDataDir},
+    task_executor::{self, Executor},
+    types::Result,
+    webhooks, with_commands,
+};
+
+/// Register the commands listed by `with_commands!` with Tauri.
+macro_rules! tauri_handler {
+    ($($module:ident::$command:ident($($arg:ident),*),)*) => {
+        generate_handler![$(commands::$module::$command),*]
+    };
+}
 
 fn main() -> Result<()> {
     let _ = fix_path_env::fix();
     dotenv().ok();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let is_cli = cli::is_command(&args);
+
     let format = fmt::format();
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
-        .event_format(format)
-        .init();
+        .event_format(format);
+    // Keep stdout clean for the CLI output
+    if is_cli {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
+    if is_cli {
+        if let Err(err) = block_on(cli::run(&args)) {
+            eprintln!("Error: {err:#}");
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
 
     // tauri_plugin_deep_link::prepare("com.starfleetai.bridge");
 
     info!("Starting Bridge...");
     tauri::Builder::default()
-        .invoke_handler(generate_handler![
-            commands::abilities::create_ability,
-            commands::abilities::delete_ability,
-            commands::abilities::list_abilities,
-            commands::abilities::update_ability,
-            commands::agents_chats::list_agents_chats,
-            commands::agents::create_agent,
-            commands::agents::delete_agent,
-            commands::agents::list_agents,
-            commands::agents::update_agent_is_enabled,
-            commands::agents::update_agent,
-            commands::chats::create_chat,
-            commands::chats::delete_chat,
-            commands::chats::get_chat,
-            commands::chats::list_chats,
-            commands::chats::toggle_chat_is_pinned,
-            commands::chats::update_chat_model_full_name,
-            commands::chats::update_chat_title,
-            commands::messages::approve_tool_call,
-            commands::messages::create_message,
-            commands::messages::delete_message,
-            commands::messages::deny_tool_call,
-            commands::messages::get_raw_message_content,
-            commands::messages::list_messages,
-            commands::messages::update_message_content,
-            commands::models::list_models,
-            commands::pages::create_page,
-            commands::pages::delete_page,
-            commands::pages::get_page,
-            commands::pages::list_pages,
-            commands::pages::update_page,
-            commands::settings::get_settings,
-            commands::settings::update_settings,
-            commands::task_results::get_task_result_text_data,
-            commands::task_results::list_task_results,
-            commands::tasks::create_task,
-            commands::tasks::delete_task,
-            commands::tasks::duplicate_task,
-            commands::tasks::execute_task,
-            commands::tasks::get_task,
-            commands::tasks::list_child_tasks,
-            commands::tasks::list_root_tasks_by_status,
-            commands::tasks::list_root_tasks,
-            commands::tasks::plan_task,
-            commands::tasks::revise_task,
-            commands::tasks::update_task,
-        ])
+        .invoke_handler(with_commands!(tauri_handler))
         .setup(setup_handler)
         .run(tauri::generate_context!())
         .with_context(|| "Failed to run tauri application")?;
@@ -99,20 +91,35 @@ fn setup_handler(app: &mut App) -> std::result::Result<(), Box<dyn std::error::E
     std::fs::create_dir_all(&app_local_data_dir)
         .with_context(|| format!("Failed to create app local data dir: {app_local_data_dir}"))?;
 
-    let channel: Channel = Box::new(TauriChannel::new(app_handle.clone()));
-    app_handle.manage(channel);
-
     set_main_window_min_size(app)?;
 
-    let pool = block_on(async { bridge_common::database::new_pool().await })?;
-    block_on(async { database::seed(&pool).await })?;
+    let loaded = block_on(async { state::load(Path::new(&app_local_data_dir)).await })?;
+
+    let notifier = Notifier::new(app_handle.clone());
+    watch_main_window_focus(app, notifier.clone())?;
+
+    let (events, queue) = Events::new(
+        Box::new(TauriChannel::new(app_handle.clone(), notifier)),
+        loaded.pool.clone(),
+    );
+    let channel: Channel = Box::new(events.clone());
+    app_handle.manage(channel);
+    app_handle.manage(events);
 
-    let settings = block_on(async { repo::settings::get(&pool, bridge::CID).await })?;
-    app_handle.manage(RwLock::new(settings));
+    app_handle.manage(RwLock::new(loaded.settings));
+    app_handle.manage(loaded.cipher);
+    app_handle.manage(loaded.workspace);
+    app_handle.manage(Executor::default());
+    app_handle.manage(loaded.pool);
+    app_handle.manage(AppLocalDataDir(PathBuf::from(&app_local_data_dir)));
 
-    app_handle.manage(pool);
+    webhooks::start(&app_handle, queue);
+    block_on(async {
+        webhooks::resume(&app_handle).await?;
+        task_executor::start_loop(&app_handle).await;
 
-    block_on(async { task_executor::start_loop(&app_handle).await });
+        Ok::<_, bridge::errors::Error>(())
+    })?;
 
     info!("Startup sequence completed!");
     info!("Launching Bridge! 🚀");
@@ -120,6 +127,20 @@ fn setup_handler(app: &mut App) -> std::result::Result<(), Box<dyn std::error::E
     Ok(())
 }
 
+fn watch_main_window_focus(app: &App, notifier: Arc<Notifier>) -> Result<()> {
+    let main_window = app
+        .get_window("main")
+        .with_context(|| "Failed to get main window")?;
+
+    main_window.on_window_event(move |event| {
+        if let WindowEvent::Focused(is_focused) = event {
+            notifier.set_focused(*is_focused);
+        }
+    });
+
+    Ok(())
+}
+
 fn set_main_window_min_size(app: &App) -> Result<()> {
     let main_window = app
         .get_window("main")
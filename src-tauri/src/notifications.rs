@@ -0,0 +1,231 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Native notifications about the tasks needing attention, shown by the desktop app while its
+//! window is not focused: a tool call waiting for approval, a question to the user, a root task
+//! done or failed. Which of these are shown is up to `Settings::notifications`.
+//!
+//! Tauri doesn't tell when a notification is clicked, but clicking one brings the app to the
+//! front. So when the window gets focused after a notification, the frontend is asked to open the
+//! task of the last one with the `tasks:open` event.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use bridge_common::{
+    repo,
+    types::{
+        messages::{Message, Status as MessageStatus},
+        tasks::{Status, Task},
+    },
+};
+use serde_json::json;
+use tauri::{api::notification::Notification, async_runtime::spawn, AppHandle, Manager};
+use tokio::sync::RwLock;
+use tracing::{debug, error};
+
+use crate::{
+    channel::Emitted,
+    settings::Settings,
+    types::{DbPool, Result},
+};
+
+#[derive(Debug, Clone, Copy)]
+enum Reason {
+    ToolCallApproval,
+    WaitingForUser,
+    Done,
+    Failed,
+}
+
+impl Reason {
+    fn body(self) -> &'static str {
+        match self {
+            Reason::ToolCallApproval => "A tool call is waiting for your approval",
+            Reason::WaitingForUser => "The task has a question for you",
+            Reason::Done => "The task is done",
+            Reason::Failed => "The task has failed",
+        }
+    }
+
+    fn is_enabled(self, settings: &Settings) -> bool {
+        let notifications = &settings.notifications;
+
+        match self {
+            Reason::ToolCallApproval => notifications.tool_call_approval,
+            Reason::WaitingForUser => notifications.waiting_for_user,
+            Reason::Done => notifications.done,
+            Reason::Failed => notifications.failed,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Seen {
+    is_focused: bool,
+    /// Last known status of every task, so that only the changes are notified about.
+    statuses: HashMap<i32, Status>,
+    /// Messages with tool calls already notified about.
+    tool_call_messages: HashSet<i64>,
+    /// Root task of the last notification, to open when the window gets focused.
+    pending_task_id: Option<i32>,
+}
+
+pub struct Notifier {
+    app_handle: AppHandle,
+    seen: Mutex<Seen>,
+}
+
+impl Notifier {
+    #[must_use]
+    pub fn new(app_handle: AppHandle) -> Arc<Self> {
+        Arc::new(Self {
+            app_handle,
+            seen: Mutex::new(Seen {
+                is_focused: true,
+                ..Seen::default()
+            }),
+        })
+    }
+
+    /// Notify about the event if it tells that a task needs attention.
+    pub fn observe(self: &Arc<Self>, event: &Emitted) {
+        let data = &event.payload["data"];
+
+        match event.name {
+            "tasks:updated" => {
+                let Ok(task) = serde_json::from_value::<Task>(data.clone()) else {
+                    return;
+                };
+                if let Some(reason) = self.status_change(&task) {
+                    let notifier = self.clone();
+                    spawn(async move { notifier.notify(reason, &task).await });
+                }
+            }
+            "messages:created" | "messages:updated" => {
+                let Ok(message) = serde_json::from_value::<Message>(data.clone()) else {
+                    return;
+                };
+                if self.is_new_tool_call(&message) {
+                    let notifier = self.clone();
+                    spawn(async move { notifier.notify_tool_call(&message).await });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep track of whether the window is focused, opening the task of the last notification
+    /// when it gets focused.
+    pub fn set_focused(&self, is_focused: bool) {
+        let pending_task_id = {
+            let Ok(mut seen) = self.seen.lock() else {
+                return;
+            };
+            seen.is_focused = is_focused;
+
+            if !is_focused {
+                return;
+            }
+            seen.pending_task_id.take()
+        };
+
+        let Some(task_id) = pending_task_id else {
+            return;
+        };
+        let Some(window) = self.app_handle.get_window("main") else {
+            return;
+        };
+        if let Err(err) = window.emit("tasks:open", json!({ "id": task_id })) {
+            error!("Failed to open task #{}: {:?}", task_id, err);
+        }
+    }
+
+    fn status_change(&self, task: &Task) -> Option<Reason> {
+        let previous = self.seen.lock().ok()?.statuses.insert(task.id, task.status);
+
+        // The executor moves tasks in progress before anything else, so a task first seen with a
+        // notable status has just been edited, and has been notified about already if at all
+        if previous.is_none() || previous == Some(task.status) {
+            return None;
+        }
+
+        match task.status {
+            Status::WaitingForUser => Some(Reason::WaitingForUser),
+            Status::Done if task.ancestry.is_none() => Some(Reason::Done),
+            Status::Failed if task.ancestry.is_none() => Some(Reason::Failed),
+            _ => None,
+        }
+    }
+
+    fn is_new_tool_call(&self, message: &Message) -> bool {
+        message.status == MessageStatus::WaitingForToolCall
+            && self
+                .seen
+                .lock()
+                .is_ok_and(|mut seen| seen.tool_call_messages.insert(message.id))
+    }
+
+    async fn notify_tool_call(&self, message: &Message) {
+        let pool = self.app_handle.state::<DbPool>();
+        // Direct chats are not about tasks, and have the user looking at them anyway
+        let Ok(task) =
+            repo::tasks::get_by_execution_chat_id(&*pool, message.company_id, message.chat_id)
+                .await
+        else {
+            return;
+        };
+
+        self.notify(Reason::ToolCallApproval, &task).await;
+    }
+
+    async fn notify(&self, reason: Reason, task: &Task) {
+        let is_focused = self.seen.lock().map_or(true, |seen| seen.is_focused);
+        if is_focused {
+            return;
+        }
+
+        let is_enabled = {
+            let settings = self.app_handle.state::<RwLock<Settings>>();
+            let settings = settings.read().await;
+            reason.is_enabled(&settings)
+        };
+        if !is_enabled {
+            return;
+        }
+
+        debug!("Notifying about task #{}: {:?}", task.id, reason);
+        if let Err(err) = self.show(reason, task) {
+            error!("Failed to notify about task #{}: {:?}", task.id, err);
+            return;
+        }
+
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.pending_task_id = Some(root_task_id(task));
+        }
+    }
+
+    fn show(&self, reason: Reason, task: &Task) -> Result<()> {
+        let identifier = self.app_handle.config().tauri.bundle.identifier.clone();
+
+        Notification::new(identifier)
+            .title(&task.title)
+            .body(reason.body())
+            .show()
+            .context("Failed to show notification")?;
+
+        Ok(())
+    }
+}
+
+/// Root of the task tree the task belongs to, which is what the frontend opens.
+fn root_task_id(task: &Task) -> i32 {
+    task.ancestry
+        .as_deref()
+        .and_then(|ancestry| ancestry.split('/').next())
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(task.id)
+}
@@ -0,0 +1,352 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Review of task plans before they're saved.
+//!
+//! `TaskPlanner` of `bridge_common` saves the subtasks it plans straight away. In review mode the
+//! plan is proposed instead, along with the rationale behind it and the execution steps each
+//! subtask is estimated to take. The user accepts the proposal, possibly edited, which saves the
+//! subtasks and queues the task, or rejects it with feedback the task is planned anew with.
+//! Proposals are a single level of subtasks deep.
+
+use anyhow::{anyhow, Context};
+use bridge_common::{
+    chats::construct_tools,
+    clients::openai::{Client, CreateChatCompletionRequest, Message},
+    repo::{self, tasks::CreateParams as CreateTaskParams},
+    settings::Settings,
+    types::{
+        abilities::Ability,
+        tasks::{Status, Task},
+    },
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Postgres, Transaction};
+use tracing::info;
+
+use crate::{
+    events::{AppEvent, Events},
+    repo::plan_proposals::CreateParams,
+    types::{
+        plan_proposals::{PlanProposal, ProposalStatus, ProposedSubtask},
+        DbPool, Result,
+    },
+};
+
+const PROMPT: &str = r"You are a project manager with the objective of orchestrating task execution using your team effectively.
+
+Split the task into discrete, manageable sub-tasks, each assigned to a single agent and executed sequentially. Sub-tasks see the outcomes of their siblings, and the task sees the outcomes of its sub-tasks. Keep the number of sub-tasks to a minimum, and don't plan tasks which need no planning, like simple scripts or direct questions: propose a single sub-task, the task itself, assigned to the most suitable agent instead. Leave out review steps and steps for delivering results.
+
+The user reviews the plan before it's executed, so explain briefly why the task is split this way, and estimate how many execution steps each sub-task will take. If the user rejected earlier plans, address their feedback.";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("planning is not available for tasks with status `{0:?}`")]
+    PlanningUnavailable(Status),
+    #[error("no plan received from LLM")]
+    NoPlan,
+    #[error("plan must have at least one subtask")]
+    EmptyPlan,
+    #[error("subtask title cannot be empty")]
+    EmptySubtaskTitle,
+    #[error("plan proposal has been reviewed already")]
+    NotPending,
+    #[error("feedback is required to reject a plan proposal")]
+    NoFeedback,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProposedPlan {
+    #[serde(default)]
+    rationale: String,
+    #[serde(default)]
+    tasks: Vec<ProposedSubtask>,
+}
+
+/// Proposal accepted, along with the task it's for and the subtasks created.
+pub struct Accepted {
+    pub proposal: PlanProposal,
+    pub task: Task,
+    pub subtasks: Vec<Task>,
+}
+
+/// Propose a plan for the task, superseding the pending proposals for it.
+///
+/// # Errors
+///
+/// Returns error if the task can't be planned, or there was a problem while getting the plan from
+/// LLM or accessing database.
+pub async fn propose(
+    pool: &DbPool,
+    settings: &Settings,
+    events: &Events,
+    task: &Task,
+) -> Result<PlanProposal> {
+    check_plannable(task)?;
+    info!("Proposing plan for task #{}", task.id);
+
+    let model = repo::models::get_by_full_name(pool, task.company_id, &settings.default_model)
+        .await
+        .context("Failed to get model")?
+        .ok_or_else(|| anyhow!("Model `{}` not found", settings.default_model))?;
+    let api_key = settings
+        .api_keys
+        .get(&model.provider)
+        .ok_or_else(|| crate::errors::Error::MissingApiKey(model.provider.clone()))?;
+
+    let client = Client::new(api_key, model.api_url_or_default(), &crate::USER_AGENT);
+    let response = client
+        .create_chat_completion(CreateChatCompletionRequest {
+            model: &model.name,
+            messages: messages(pool, task).await?,
+            stream: false,
+            tools: construct_tools(vec![propose_plan_ability()]).await?,
+        })
+        .await
+        .context("Failed to create chat completion")?;
+
+    let plan = response
+        .choices
+        .first()
+        .and_then(|choice| {
+            choice
+                .message
+                .tool_calls()
+                .iter()
+                .find(|tool_call| tool_call.function.name == "sfai_propose_plan")
+                .map(|tool_call| {
+                    serde_json::from_str::<ProposedPlan>(&tool_call.function.arguments)
+                })
+        })
+        .ok_or(Error::NoPlan)?
+        .context("Failed to parse plan")?;
+    validate(&plan.tasks)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let superseded =
+        crate::repo::plan_proposals::supersede_pending(&mut *tx, task.company_id, task.id).await?;
+    let proposal = crate::repo::plan_proposals::create(
+        &mut *tx,
+        task.company_id,
+        CreateParams {
+            task_id: task.id,
+            rationale: plan.rationale,
+            subtasks: plan.tasks,
+        },
+    )
+    .await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    for superseded in &superseded {
+        events
+            .emit_app(task.company_id, AppEvent::PlanProposalUpdated(superseded))
+            .await?;
+    }
+    events
+        .emit_app(task.company_id, AppEvent::PlanProposalCreated(&proposal))
+        .await?;
+
+    Ok(proposal)
+}
+
+/// Accept the proposal, with the subtasks edited by the user if given. The subtasks are created,
+/// or the task is assigned to the agent of the only one, and the task is queued for execution if
+/// it's a root one.
+///
+/// # Errors
+///
+/// Returns error if the proposal has been reviewed already, the subtasks are not valid, the task
+/// can't be planned, or there was a problem while accessing database.
+pub async fn accept(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    id: i32,
+    subtasks: Option<Vec<ProposedSubtask>>,
+) -> Result<Accepted> {
+    let proposal = crate::repo::plan_proposals::get(&mut **tx, cid, id).await?;
+    let subtasks = subtasks.unwrap_or_else(|| proposal.subtasks.0.clone());
+    validate(&subtasks)?;
+
+    let mut task = repo::tasks::get(&mut **tx, cid, proposal.task_id).await?;
+    check_plannable(&task)?;
+
+    let proposal = crate::repo::plan_proposals::accept(&mut **tx, cid, id, subtasks)
+        .await?
+        .ok_or(Error::NotPending)?;
+
+    let mut created = Vec::new();
+    if let [subtask] = proposal.subtasks.as_slice() {
+        repo::tasks::assign(&mut **tx, cid, task.id, subtask.agent_id).await?;
+        task.agent_id = subtask.agent_id;
+    } else {
+        let ancestry = task.children_ancestry();
+        for subtask in proposal.subtasks.iter() {
+            let subtask = repo::tasks::create(
+                &mut **tx,
+                cid,
+                CreateTaskParams {
+                    agent_id: subtask.agent_id,
+                    title: &subtask.title,
+                    summary: Some(&subtask.summary),
+                    ancestry: Some(&ancestry),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            created.push(subtask);
+        }
+    }
+
+    if task.ancestry.is_none() {
+        task = repo::tasks::execute(&mut **tx, cid, task.id).await?;
+    }
+
+    Ok(Accepted {
+        proposal,
+        task,
+        subtasks: created,
+    })
+}
+
+/// Reject the proposal with the feedback given.
+///
+/// # Errors
+///
+/// Returns error if the feedback is empty, the proposal has been reviewed already, or there was a
+/// problem while accessing database.
+pub async fn reject(pool: &DbPool, cid: i32, id: i32, feedback: &str) -> Result<PlanProposal> {
+    if feedback.trim().is_empty() {
+        return Err(Error::NoFeedback.into());
+    }
+
+    Ok(
+        crate::repo::plan_proposals::reject(pool, cid, id, feedback.trim())
+            .await?
+            .ok_or(Error::NotPending)?,
+    )
+}
+
+fn check_plannable(task: &Task) -> Result<()> {
+    match task.status {
+        Status::ToDo | Status::InProgress => Err(Error::PlanningUnavailable(task.status).into()),
+        _ => Ok(()),
+    }
+}
+
+fn validate(subtasks: &[ProposedSubtask]) -> Result<()> {
+    if subtasks.is_empty() {
+        return Err(Error::EmptyPlan.into());
+    }
+    if subtasks
+        .iter()
+        .any(|subtask| subtask.title.trim().is_empty())
+    {
+        return Err(Error::EmptySubtaskTitle.into());
+    }
+
+    Ok(())
+}
+
+async fn messages(pool: &DbPool, task: &Task) -> Result<Vec<Message>> {
+    let agents = repo::agents::list_enabled(pool, task.company_id)
+        .await
+        .context("Failed to list agents")?
+        .into_iter()
+        .map(|agent| format!("- ID: {}. {}: {}", agent.id, agent.name, agent.description))
+        .collect::<Vec<_>>();
+    let agents = if agents.is_empty() {
+        "No agents available".to_string()
+    } else {
+        agents.join("\n")
+    };
+
+    let mut sections = vec![
+        format!("## Available Agents\n\n{agents}"),
+        format!("## Task: {}", task.title),
+    ];
+    if !task.summary.is_empty() {
+        sections.push(task.summary.clone());
+    }
+
+    let rejected = crate::repo::plan_proposals::list_for_task(pool, task.company_id, task.id)
+        .await?
+        .into_iter()
+        .filter(|proposal| proposal.status == ProposalStatus::Rejected);
+    for (index, proposal) in rejected.enumerate() {
+        let subtasks = proposal
+            .subtasks
+            .iter()
+            .map(|subtask| format!("- {} (agent {})", subtask.title, subtask.agent_id))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        sections.push(format!(
+            "## Rejected Plan {}\n\n{subtasks}\n\nFeedback: {}",
+            index + 1,
+            proposal.feedback.as_deref().unwrap_or_default(),
+        ));
+    }
+
+    Ok(vec![
+        Message::System {
+            content: PROMPT.to_string(),
+            name: None,
+        },
+        Message::User {
+            content: sections.join("\n\n"),
+            name: None,
+        },
+    ])
+}
+
+fn propose_plan_ability() -> Ability {
+    Ability::for_fn(
+        "Propose task execution plan for the user to review",
+        &json!({
+            "name": "sfai_propose_plan",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "rationale": {
+                        "type": "string",
+                        "description": "Why the task is split this way"
+                    },
+                    "tasks": {
+                        "type": "array",
+                        "description": "List of planned sub-tasks",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "title": {
+                                    "type": "string",
+                                    "description": "Task title"
+                                },
+                                "summary": {
+                                    "type": "string",
+                                    "description": "Task summary"
+                                },
+                                "agent_id": {
+                                    "type": "integer",
+                                    "description": "ID of the agent to assign the task to"
+                                },
+                                "estimated_steps": {
+                                    "type": "integer",
+                                    "description": "Estimated number of execution steps"
+                                }
+                            }
+                        }
+                    }
+                },
+                "required": ["rationale", "tasks"]
+            }
+        }),
+    )
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checking model providers against the configured API keys.
+//!
+//! A provider is checked by listing its models, which is free and proves both that the key is
+//! valid and which of the seeded models the key has access to.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use bridge_common::types::models::{Model, Provider};
+use futures_util::future;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+const OPENAI_API_URL: &str = "https://api.openai.com/v1/";
+const GROQ_API_URL: &str = "https://api.groq.com/openai/v1/";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Result of checking a provider API.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderStatus {
+    pub provider: Provider,
+    pub api_url: String,
+    pub is_api_key_set: bool,
+    /// Whether the provider accepted the API key.
+    pub is_api_key_valid: bool,
+    /// Seeded models the API key has access to.
+    pub available_models: Vec<String>,
+    /// Seeded models the provider didn't list.
+    pub unavailable_models: Vec<String>,
+    /// Round-trip time of the request, if it got a response.
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelsList {
+    data: Vec<ListedModel>,
+}
+
+#[derive(Deserialize)]
+struct ListedModel {
+    id: String,
+}
+
+/// Check every provider API the seeded models use, plus the providers with an API key but without
+/// models.
+///
+/// Models with a custom `api_url`, e.g. a local mock, are checked against that URL.
+pub async fn check_all(
+    models: &[Model],
+    api_keys: &BTreeMap<Provider, String>,
+) -> Vec<ProviderStatus> {
+    let mut apis: BTreeMap<(Provider, String), Vec<&Model>> = BTreeMap::new();
+    for model in models {
+        apis.entry((
+            model.provider.clone(),
+            model.api_url_or_default().to_string(),
+        ))
+        .or_default()
+        .push(model);
+    }
+
+    for provider in api_keys.keys() {
+        if !apis
+            .keys()
+            .any(|(api_provider, _)| api_provider == provider)
+        {
+            let api_url = default_api_url(provider);
+            apis.insert((provider.clone(), api_url), vec![]);
+        }
+    }
+
+    future::join_all(apis.into_iter().map(|((provider, api_url), models)| {
+        let api_key = api_keys.get(&provider).map(String::as_str);
+
+        async move { check(provider, &api_url, api_key, &models).await }
+    }))
+    .await
+}
+
+/// Check the provider API with the API key, reporting which of `models` are available.
+pub async fn check(
+    provider: Provider,
+    api_url: &str,
+    api_key: Option<&str>,
+    models: &[&Model],
+) -> ProviderStatus {
+    let mut status = ProviderStatus {
+        provider,
+        api_url: api_url.to_string(),
+        is_api_key_set: api_key.is_some_and(|api_key| !api_key.is_empty()),
+        is_api_key_valid: false,
+        available_models: vec![],
+        unavailable_models: models.iter().map(|model| model.name.clone()).collect(),
+        latency_ms: None,
+        error: None,
+    };
+
+    let Some(api_key) = api_key.filter(|api_key| !api_key.is_empty()) else {
+        status.error = Some("API key is not set".to_string());
+        return status;
+    };
+
+    match list_models(api_url, api_key).await {
+        Ok((listed, latency)) => {
+            status.latency_ms = Some(u64::try_from(latency.as_millis()).unwrap_or(u64::MAX));
+            status.is_api_key_valid = true;
+            (status.available_models, status.unavailable_models) = status
+                .unavailable_models
+                .into_iter()
+                .partition(|name| listed.contains(name));
+        }
+        Err(Failure { latency, error }) => {
+            status.latency_ms =
+                latency.map(|latency| u64::try_from(latency.as_millis()).unwrap_or(u64::MAX));
+            status.error = Some(error);
+        }
+    }
+
+    debug!("Provider status: {:?}", status);
+
+    status
+}
+
+/// API URL of the provider when the model doesn't set its own, as in `bridge_common`.
+#[must_use]
+pub fn default_api_url(provider: &Provider) -> String {
+    match provider {
+        Provider::OpenAI => OPENAI_API_URL,
+        Provider::Groq => GROQ_API_URL,
+    }
+    .to_string()
+}
+
+struct Failure {
+    latency: Option<Duration>,
+    error: String,
+}
+
+async fn list_models(
+    api_url: &str,
+    api_key: &str,
+) -> std::result::Result<(Vec<String>, Duration), Failure> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(crate::USER_AGENT.as_str())
+        .build()
+        .map_err(|err| Failure {
+            latency: None,
+            error: err.to_string(),
+        })?;
+
+    let url = format!("{}/models", api_url.trim_end_matches('/'));
+    let started_at = Instant::now();
+    let response = client
+        .get(&url)
+        .bearer_auth(api_key)
+        .send()
+        .await
+        .map_err(|err| Failure {
+            latency: None,
+            error: format!("request to `{url}` failed: {err}"),
+        })?;
+    let latency = started_at.elapsed();
+
+    let failure = |error: String| Failure {
+        latency: Some(latency),
+        error,
+    };
+
+    match response.status() {
+        status if status.is_success() => {}
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            return Err(failure("API key is rejected by the provider".to_string()))
+        }
+        status => return Err(failure(format!("provider responded with `{status}`"))),
+    }
+
+    let models = response
+        .json::<ModelsList>()
+        .await
+        .map_err(|err| failure(format!("failed to parse models list: {err}")))?;
+
+    Ok((
+        models.data.into_iter().map(|model| model.id).collect(),
+        latency,
+    ))
+}
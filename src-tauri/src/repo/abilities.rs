@@ -0,0 +1,187 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{query_as, Executor, Postgres};
+
+use crate::types::{
+    abilities::{Ability, Language},
+    Result,
+};
+
+pub struct CreateParams {
+    pub name: String,
+    pub description: String,
+    pub code: String,
+    pub parameters_json: Value,
+    pub language: Language,
+    pub requirements: Vec<String>,
+    pub timeout_secs: Option<i32>,
+}
+
+pub struct UpdateParams {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub code: String,
+    pub parameters_json: Value,
+    pub language: Language,
+    pub requirements: Vec<String>,
+    pub timeout_secs: Option<i32>,
+}
+
+/// Abilities along with their runtime. Abilities created by `bridge_common` may have none yet.
+const SELECT: &str = r"
+    SELECT
+        abilities.id, abilities.company_id, abilities.name, abilities.description, abilities.code,
+        abilities.parameters_json,
+        COALESCE(ability_runtimes.language, 'Python') AS language,
+        COALESCE(ability_runtimes.requirements, '{}') AS requirements,
+        ability_runtimes.timeout_secs,
+        abilities.created_at, abilities.updated_at
+    FROM abilities
+    LEFT JOIN ability_runtimes ON ability_runtimes.ability_id = abilities.id
+";
+
+/// List abilities for agent.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_for_agent<'a, E>(
+    executor: E,
+    company_id: i32,
+    agent_id: i32,
+) -> Result<Vec<Ability>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(&format!(
+        r"
+        {SELECT}
+        INNER JOIN agent_abilities ON abilities.id = agent_abilities.ability_id
+        WHERE abilities.company_id = $1 AND agent_abilities.agent_id = $2
+        "
+    ))
+    .bind(company_id)
+    .bind(agent_id)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// List all abilities.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list<'a, E>(executor: E, company_id: i32) -> Result<Vec<Ability>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(&format!(
+        "{SELECT} WHERE abilities.company_id = $1 ORDER BY abilities.id DESC"
+    ))
+    .bind(company_id)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Get ability by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i32) -> Result<Ability>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(&format!(
+        "{SELECT} WHERE abilities.company_id = $1 AND abilities.id = $2"
+    ))
+    .bind(company_id)
+    .bind(id)
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Create ability along with its runtime.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating ability.
+pub async fn create<'a, E>(executor: E, company_id: i32, params: CreateParams) -> Result<Ability>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        WITH ability AS (
+            INSERT INTO abilities (
+                company_id, name, description, code, parameters_json, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5::json, $9, $9)
+            RETURNING id, company_id, name, description, code, parameters_json, created_at,
+                updated_at
+        ), runtime AS (
+            INSERT INTO ability_runtimes (ability_id, company_id, language, requirements, timeout_secs)
+            SELECT id, company_id, $6, $7, $8 FROM ability
+            RETURNING language, requirements, timeout_secs
+        )
+        SELECT ability.*, runtime.* FROM ability, runtime
+        ",
+    )
+    .bind(company_id)
+    .bind(params.name)
+    .bind(params.description)
+    .bind(params.code)
+    .bind(params.parameters_json)
+    .bind(params.language.to_string())
+    .bind(params.requirements)
+    .bind(params.timeout_secs)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Update ability along with its runtime.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update<'a, E>(executor: E, company_id: i32, params: UpdateParams) -> Result<Ability>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        WITH ability AS (
+            UPDATE abilities
+            SET name = $3, description = $4, code = $5, parameters_json = $6::json, updated_at = $10
+            WHERE company_id = $1 AND id = $2
+            RETURNING id, company_id, name, description, code, parameters_json, created_at,
+                updated_at
+        ), runtime AS (
+            INSERT INTO ability_runtimes (ability_id, company_id, language, requirements, timeout_secs)
+            SELECT id, company_id, $7, $8, $9 FROM ability
+            ON CONFLICT (ability_id) DO UPDATE
+            SET language = EXCLUDED.language, requirements = EXCLUDED.requirements,
+                timeout_secs = EXCLUDED.timeout_secs
+            RETURNING language, requirements, timeout_secs
+        )
+        SELECT ability.*, runtime.* FROM ability, runtime
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.name)
+    .bind(params.description)
+    .bind(params.code)
+    .bind(params.parameters_json)
+    .bind(params.language.to_string())
+    .bind(params.requirements)
+    .bind(params.timeout_secs)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
@@ -0,0 +1,139 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{query, query_as, Executor, Postgres};
+
+use crate::types::{ability_test_cases::AbilityTestCase, Result};
+
+pub struct CreateParams {
+    pub ability_id: i32,
+    pub name: String,
+    pub arguments: Value,
+}
+
+pub struct UpdateParams {
+    pub id: i32,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// List test cases for ability.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_for_ability<'a, E>(
+    executor: E,
+    company_id: i32,
+    ability_id: i32,
+) -> Result<Vec<AbilityTestCase>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        "SELECT * FROM ability_test_cases WHERE company_id = $1 AND ability_id = $2 ORDER BY id",
+    )
+    .bind(company_id)
+    .bind(ability_id)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Get test case by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i32) -> Result<AbilityTestCase>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM ability_test_cases WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Create test case.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating test case.
+pub async fn create<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: CreateParams,
+) -> Result<AbilityTestCase>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO ability_test_cases (company_id, ability_id, name, arguments, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.ability_id)
+    .bind(params.name)
+    .bind(params.arguments)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Update test case.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: UpdateParams,
+) -> Result<AbilityTestCase>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE ability_test_cases
+        SET name = $3, arguments = $4, updated_at = $5
+        WHERE company_id = $1 AND id = $2
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.name)
+    .bind(params.arguments)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Delete test case.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while deleting test case.
+pub async fn delete<'a, E>(executor: E, company_id: i32, id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query("DELETE FROM ability_test_cases WHERE company_id = $1 AND id = $2")
+        .bind(company_id)
+        .bind(id)
+        .execute(executor)
+        .await
+        .with_context(|| "Failed to delete ability test case")?;
+
+    Ok(())
+}
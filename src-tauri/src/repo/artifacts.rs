@@ -0,0 +1,82 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::Utc;
+use sqlx::{query_as, Executor, Postgres};
+
+use crate::types::{artifacts::Artifact, Result};
+
+pub struct CreateParams {
+    pub chat_id: i32,
+    pub message_id: i64,
+    pub tool_call_id: String,
+    pub content: String,
+}
+
+/// List artifacts of the chat.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_for_chat<'a, E>(
+    executor: E,
+    company_id: i32,
+    chat_id: i32,
+) -> Result<Vec<Artifact>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM artifacts WHERE company_id = $1 AND chat_id = $2 ORDER BY id")
+            .bind(company_id)
+            .bind(chat_id)
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// Get artifact by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i64) -> Result<Artifact>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM artifacts WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Create artifact.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating artifact.
+pub async fn create<'a, E>(executor: E, company_id: i32, params: CreateParams) -> Result<Artifact>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO artifacts (
+            company_id, chat_id, message_id, tool_call_id, content, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $6)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.chat_id)
+    .bind(params.message_id)
+    .bind(params.tool_call_id)
+    .bind(params.content)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
@@ -0,0 +1,92 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, types::Json, Executor, Postgres};
+
+use crate::types::{events::LoggedEvent, Result};
+
+/// Namespace of the advisory locks the events of a company are appended under.
+const APPEND_LOCK: i32 = 0x6576_6e74;
+
+pub struct CreateParams {
+    pub name: String,
+    pub payload: Value,
+}
+
+/// List events logged after the given sequence number, oldest first.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_since<'a, E>(
+    executor: E,
+    company_id: i32,
+    since_seq: i64,
+    limit: i64,
+) -> Result<Vec<LoggedEvent>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        SELECT * FROM events
+        WHERE company_id = $1 AND seq > $2
+        ORDER BY seq
+        LIMIT $3
+        ",
+    )
+    .bind(company_id)
+    .bind(since_seq)
+    .bind(limit)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Lock the log of the company for appending until the end of the transaction.
+///
+/// `seq` is taken when the event is inserted, not when it's committed, so events appended
+/// concurrently could otherwise become visible out of order, and a client listing them meanwhile
+/// would skip the ones committed late. Appending under the lock makes the events of a company
+/// visible in the order of their `seq`.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn lock_for_append<'a, E>(executor: E, company_id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query("SELECT pg_advisory_xact_lock($1, $2)")
+        .bind(APPEND_LOCK)
+        .bind(company_id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+/// Append event to the log. Must be called within a transaction holding [`lock_for_append`].
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating event.
+pub async fn create<'a, E>(executor: E, company_id: i32, params: CreateParams) -> Result<i64>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_scalar(
+        r"
+        INSERT INTO events (company_id, name, payload, created_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING seq
+        ",
+    )
+    .bind(company_id)
+    .bind(params.name)
+    .bind(Json(params.payload))
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
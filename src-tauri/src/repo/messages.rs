@@ -0,0 +1,102 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use bridge_common::types::messages::{Role, Status};
+use sqlx::{query, query_scalar, Executor, Postgres};
+
+use crate::types::Result;
+
+/// List ids of the given tool calls which already have a result (or a denial) in the chat.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_answered_tool_call_ids<'a, E>(
+    executor: E,
+    company_id: i32,
+    chat_id: i32,
+    tool_call_ids: &[String],
+) -> Result<Vec<String>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_scalar(
+        r"
+        SELECT DISTINCT tool_call_id
+        FROM messages
+        WHERE company_id = $1 AND chat_id = $2 AND role = $3 AND tool_call_id = ANY($4)
+        ",
+    )
+    .bind(company_id)
+    .bind(chat_id)
+    .bind(Role::Tool.to_string())
+    .bind(tool_call_ids)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Update message status, but only if it's still `from`.
+///
+/// Returns whether the status has been updated, so out of several concurrent callers exactly one
+/// gets to act on the change.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_status_if<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i64,
+    from: Status,
+    to: Status,
+) -> Result<bool>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let result = query(
+        r"
+        UPDATE messages
+        SET status = $4
+        WHERE company_id = $1 AND id = $2 AND status = $3
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(from.to_string())
+    .bind(to.to_string())
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Check whether there are messages in the chat after the given one, other than tool call
+/// results.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn has_later_messages<'a, E>(
+    executor: E,
+    company_id: i32,
+    chat_id: i32,
+    id: i64,
+) -> Result<bool>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_scalar(
+        r"
+        SELECT EXISTS (
+            SELECT 1 FROM messages
+            WHERE company_id = $1 AND chat_id = $2 AND id > $3 AND role <> $4
+        )
+        ",
+    )
+    .bind(company_id)
+    .bind(chat_id)
+    .bind(id)
+    .bind(Role::Tool.to_string())
+    .fetch_one(executor)
+    .await?)
+}
@@ -0,0 +1,21 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Queries for the tables owned by the application (see `db/schema.sql`), and for the data the
+//! application keeps in `bridge_common` tables or needs from them in a way `bridge_common::repo`
+//! doesn't provide. Everything else lives in `bridge_common::repo`.
+
+pub mod abilities;
+pub mod ability_test_cases;
+pub mod artifacts;
+pub mod events;
+pub mod messages;
+pub mod plan_proposals;
+pub mod secrets;
+pub mod settings;
+pub mod task_steps;
+pub mod task_templates;
+pub mod tasks;
+pub mod tool_call_policies;
+pub mod webhooks;
+pub mod workspaces;
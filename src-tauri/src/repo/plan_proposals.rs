@@ -0,0 +1,184 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::Utc;
+use sqlx::{query_as, types::Json, Executor, Postgres};
+
+use crate::types::{
+    plan_proposals::{PlanProposal, ProposalStatus, ProposedSubtask},
+    Result,
+};
+
+pub struct CreateParams {
+    pub task_id: i32,
+    pub rationale: String,
+    pub subtasks: Vec<ProposedSubtask>,
+}
+
+/// List proposals for the task, oldest first.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_for_task<'a, E>(
+    executor: E,
+    company_id: i32,
+    task_id: i32,
+) -> Result<Vec<PlanProposal>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM plan_proposals WHERE company_id = $1 AND task_id = $2 ORDER BY id")
+            .bind(company_id)
+            .bind(task_id)
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// Get proposal by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i32) -> Result<PlanProposal>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM plan_proposals WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Create pending proposal.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating proposal.
+pub async fn create<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: CreateParams,
+) -> Result<PlanProposal>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO plan_proposals (company_id, task_id, rationale, subtasks, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.task_id)
+    .bind(params.rationale)
+    .bind(Json(params.subtasks))
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Mark the pending proposals for the task as superseded, returning them.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn supersede_pending<'a, E>(
+    executor: E,
+    company_id: i32,
+    task_id: i32,
+) -> Result<Vec<PlanProposal>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE plan_proposals
+        SET status = $4, updated_at = $5
+        WHERE company_id = $1 AND task_id = $2 AND status = $3
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(task_id)
+    .bind(ProposalStatus::Pending.to_string())
+    .bind(ProposalStatus::Superseded.to_string())
+    .bind(Utc::now())
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Accept the proposal with the subtasks given, but only if it's still pending.
+///
+/// Returns `None` if the proposal is not pending, so out of several concurrent reviews exactly
+/// one gets to act on it.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn accept<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i32,
+    subtasks: Vec<ProposedSubtask>,
+) -> Result<Option<PlanProposal>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE plan_proposals
+        SET status = $4, subtasks = $5, updated_at = $6
+        WHERE company_id = $1 AND id = $2 AND status = $3
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(ProposalStatus::Pending.to_string())
+    .bind(ProposalStatus::Accepted.to_string())
+    .bind(Json(subtasks))
+    .bind(Utc::now())
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// Reject the proposal with the feedback given, but only if it's still pending.
+///
+/// Returns `None` if the proposal is not pending.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn reject<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i32,
+    feedback: &str,
+) -> Result<Option<PlanProposal>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE plan_proposals
+        SET status = $4, feedback = $5, updated_at = $6
+        WHERE company_id = $1 AND id = $2 AND status = $3
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(ProposalStatus::Pending.to_string())
+    .bind(ProposalStatus::Rejected.to_string())
+    .bind(feedback)
+    .bind(Utc::now())
+    .fetch_optional(executor)
+    .await?)
+}
@@ -0,0 +1,49 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::Utc;
+use sqlx::{query, query_as, Executor, Postgres};
+
+use crate::types::Result;
+
+/// Get the salt and check value of the key derived from the passphrase, if there is one.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get_passphrase<'a, E>(executor: E) -> Result<Option<(String, String)>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT salt, check_value FROM secrets_passphrase LIMIT 1")
+            .fetch_optional(executor)
+            .await?,
+    )
+}
+
+/// Save the salt and check value of the key derived from the passphrase, unless another install
+/// has saved its own already.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn create_passphrase<'a, E>(executor: E, salt: &str, check_value: &str) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        INSERT INTO secrets_passphrase (salt, check_value, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO NOTHING
+        ",
+    )
+    .bind(salt)
+    .bind(check_value)
+    .bind(Utc::now())
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{query, query_scalar, Executor, Postgres};
+use tracing::{info, warn};
+
+use crate::{
+    secrets::{self, Cipher},
+    settings::{self, Settings},
+    types::Result,
+};
+
+/// Get settings for a company, including the application-specific sections.
+///
+/// Settings of an older schema version are migrated and saved. API keys are returned decrypted.
+/// Keys stored in plain text before encryption was introduced are encrypted in place. Keys which
+/// can't be decrypted, e.g. encrypted by an install with another key, are moved to
+/// `unreadable_api_keys` as stored.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while fetching settings.
+pub async fn get<'a, E>(executor: E, company_id: i32, cipher: &Cipher) -> Result<Settings>
+where
+    E: Executor<'a, Database = Postgres> + std::marker::Copy,
+{
+    let value: Option<Value> =
+        query_scalar("SELECT value FROM settings WHERE company_id = $1 LIMIT 1")
+            .bind(company_id)
+            .fetch_optional(executor)
+            .await?;
+
+    let Some(value) = value else {
+        let settings = Settings::default();
+        bridge_common::repo::settings::insert(executor, company_id, &settings.common).await?;
+        update(executor, company_id, &settings, cipher).await?;
+
+        return Ok(settings);
+    };
+
+    let is_outdated = settings::version_of(&value) < settings::VERSION;
+    if is_outdated {
+        info!(
+            "Migrating settings from version {} to {}",
+            settings::version_of(&value),
+            settings::VERSION
+        );
+    }
+
+    let mut settings = Settings::try_from(value)?;
+    let has_plain_keys = settings
+        .common
+        .api_keys
+        .values()
+        .any(|api_key| !secrets::is_encrypted(api_key));
+
+    for (provider, api_key) in std::mem::take(&mut settings.common.api_keys) {
+        match cipher.decrypt(&api_key) {
+            Ok(decrypted) => {
+                settings.common.api_keys.insert(provider, decrypted);
+            }
+            Err(err) => {
+                warn!("Keeping API key for {provider:?} as stored: {err}");
+                settings.unreadable_api_keys.insert(provider, api_key);
+            }
+        }
+    }
+
+    if has_plain_keys {
+        info!("Encrypting API keys stored in plain text");
+    }
+
+    if is_outdated || has_plain_keys {
+        update(executor, company_id, &settings, cipher).await?;
+    }
+
+    Ok(settings)
+}
+
+/// Update settings for a company.
+///
+/// Unlike `bridge_common::repo::settings::update`, keeps the application-specific sections and
+/// encrypts API keys. Keys which can't be decrypted are written back as stored, unless replaced.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while encrypting API keys or updating settings.
+pub async fn update<'a, E>(
+    executor: E,
+    company_id: i32,
+    settings: &Settings,
+    cipher: &Cipher,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let mut settings = settings.clone();
+    for api_key in settings.common.api_keys.values_mut() {
+        *api_key = cipher.encrypt(api_key)?;
+    }
+    for (provider, api_key) in std::mem::take(&mut settings.unreadable_api_keys) {
+        settings.common.api_keys.entry(provider).or_insert(api_key);
+    }
+
+    let value = serde_json::to_value(settings).with_context(|| "Failed to serialize settings")?;
+
+    query("UPDATE settings SET value = $1::json, updated_at = $2 WHERE company_id = $3")
+        .bind(value)
+        .bind(Utc::now())
+        .bind(company_id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{query, query_as, types::Json, Executor, Postgres};
+
+use crate::types::{
+    task_steps::{StepToolCall, TaskStep, Verdict},
+    Result,
+};
+
+pub struct CreateParams<'a> {
+    pub task_id: i32,
+    pub message_id: i64,
+    pub is_self_reflection: bool,
+    pub model: &'a str,
+    pub started_at: DateTime<Utc>,
+}
+
+pub struct UpdateCompletionParams {
+    pub id: i64,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub tool_calls: Vec<StepToolCall>,
+    pub verdict: Verdict,
+}
+
+/// List the steps of the tasks, in the order they were started.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_for_tasks<'a, E>(
+    executor: E,
+    company_id: i32,
+    task_ids: &[i32],
+) -> Result<Vec<TaskStep>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        SELECT * FROM task_steps
+        WHERE company_id = $1 AND task_id = ANY($2)
+        ORDER BY started_at, id
+        ",
+    )
+    .bind(company_id)
+    .bind(task_ids)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Create step, started and not finished yet.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating step.
+pub async fn create<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: CreateParams<'_>,
+) -> Result<TaskStep>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO task_steps (
+            company_id, task_id, message_id, is_self_reflection, model, started_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.task_id)
+    .bind(params.message_id)
+    .bind(params.is_self_reflection)
+    .bind(params.model)
+    .bind(params.started_at)
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Record the completion of the step: its token usage, the tool calls made, and the verdict.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_completion<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: UpdateCompletionParams,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        UPDATE task_steps
+        SET prompt_tokens = $3, completion_tokens = $4, tool_calls = $5, verdict = $6
+        WHERE company_id = $1 AND id = $2
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.prompt_tokens)
+    .bind(params.completion_tokens)
+    .bind(Json(params.tool_calls))
+    .bind(params.verdict.to_string())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update task step")?;
+
+    Ok(())
+}
+
+/// Mark the step finished, with the error it failed with if any. A step finished already keeps
+/// its finish time, but gets the error.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn finish<'a, E>(executor: E, company_id: i32, id: i64, error: Option<&str>) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        UPDATE task_steps
+        SET finished_at = COALESCE(finished_at, $3), error = COALESCE($4, error)
+        WHERE company_id = $1 AND id = $2
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(Utc::now())
+    .bind(error)
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to finish task step")?;
+
+    Ok(())
+}
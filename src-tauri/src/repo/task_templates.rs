@@ -0,0 +1,154 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{query, query_as, types::Json, Executor, Postgres};
+
+use crate::types::{
+    task_templates::{Parameter, SubtaskTemplate, TaskTemplate},
+    Result,
+};
+
+pub struct CreateParams {
+    pub name: String,
+    pub title: String,
+    pub summary: String,
+    pub agent_id: Option<i32>,
+    pub parameters: Vec<Parameter>,
+    pub subtasks: Vec<SubtaskTemplate>,
+}
+
+pub struct UpdateParams {
+    pub id: i32,
+    pub name: String,
+    pub title: String,
+    pub summary: String,
+    pub agent_id: Option<i32>,
+    pub parameters: Vec<Parameter>,
+    pub subtasks: Vec<SubtaskTemplate>,
+}
+
+/// List all task templates.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list<'a, E>(executor: E, company_id: i32) -> Result<Vec<TaskTemplate>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM task_templates WHERE company_id = $1 ORDER BY name, id")
+            .bind(company_id)
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// Get task template by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i32) -> Result<TaskTemplate>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM task_templates WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Create task template.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating task template.
+pub async fn create<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: CreateParams,
+) -> Result<TaskTemplate>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO task_templates (
+            company_id, name, title, summary, agent_id, parameters, subtasks, created_at,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.name)
+    .bind(params.title)
+    .bind(params.summary)
+    .bind(params.agent_id)
+    .bind(Json(params.parameters))
+    .bind(Json(params.subtasks))
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Update task template.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: UpdateParams,
+) -> Result<TaskTemplate>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE task_templates
+        SET name = $3, title = $4, summary = $5, agent_id = $6, parameters = $7, subtasks = $8,
+            updated_at = $9
+        WHERE company_id = $1 AND id = $2
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.name)
+    .bind(params.title)
+    .bind(params.summary)
+    .bind(params.agent_id)
+    .bind(Json(params.parameters))
+    .bind(Json(params.subtasks))
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Delete task template. Tasks created from it are kept.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while deleting task template.
+pub async fn delete<'a, E>(executor: E, company_id: i32, id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query("DELETE FROM task_templates WHERE company_id = $1 AND id = $2")
+        .bind(company_id)
+        .bind(id)
+        .execute(executor)
+        .await
+        .with_context(|| "Failed to delete task template")?;
+
+    Ok(())
+}
@@ -0,0 +1,319 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use bridge_common::types::tasks::Status;
+use chrono::{DateTime, Utc};
+use sqlx::{query, query_as, query_scalar, Executor, Postgres};
+
+use crate::types::{task_queue::QueuedTask, Result};
+
+/// Put the sibling tasks in the given order, which they are listed and executed in.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_positions<'a, E>(executor: E, company_id: i32, ids: &[i32]) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        INSERT INTO task_positions (task_id, company_id, position)
+        SELECT positions.task_id, $1, positions.position::INTEGER
+        FROM UNNEST($2::INTEGER[]) WITH ORDINALITY AS positions(task_id, position)
+        ON CONFLICT (task_id) DO UPDATE SET position = EXCLUDED.position
+        ",
+    )
+    .bind(company_id)
+    .bind(ids)
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update task positions")?;
+
+    Ok(())
+}
+
+/// List the positions of the given tasks among their siblings, for the ones put in order by hand.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_positions<'a, E>(
+    executor: E,
+    company_id: i32,
+    ids: &[i32],
+) -> Result<HashMap<i32, i32>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let positions: Vec<(i32, i32)> = query_as(
+        r"
+        SELECT task_id, position
+        FROM task_positions
+        WHERE company_id = $1 AND task_id = ANY($2)
+        ",
+    )
+    .bind(company_id)
+    .bind(ids)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(positions.into_iter().collect())
+}
+
+/// Move the tasks with the ancestry starting with `from` under `to`, replacing that part of their
+/// ancestry and adjusting their level by `level_delta`.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn replace_ancestry<'a, E>(
+    executor: E,
+    company_id: i32,
+    from: &str,
+    to: &str,
+    level_delta: i32,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        UPDATE tasks
+        SET ancestry = $3 || substr(ancestry, length($2) + 1),
+            ancestry_level = ancestry_level + $4,
+            updated_at = $5
+        WHERE company_id = $1 AND (ancestry = $2 OR ancestry LIKE $2 || '/%')
+        ",
+    )
+    .bind(company_id)
+    .bind(from)
+    .bind(to)
+    .bind(level_delta)
+    .bind(Utc::now())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update tasks ancestry")?;
+
+    Ok(())
+}
+
+/// Set the ancestry of the task, along with its level.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_ancestry<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i32,
+    ancestry: &str,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let ancestry_level =
+        i32::try_from(ancestry.split('/').count()).context("Too many ancestors")?;
+
+    query(
+        r"
+        UPDATE tasks
+        SET ancestry = $3, ancestry_level = $4, updated_at = $5
+        WHERE company_id = $1 AND id = $2
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(ancestry)
+    .bind(ancestry_level)
+    .bind(Utc::now())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update task ancestry")?;
+
+    Ok(())
+}
+
+/// Delete the task along with all of its children, at every level.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while deleting tasks.
+pub async fn delete_subtree<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i32,
+    children_ancestry: &str,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        DELETE FROM tasks
+        WHERE company_id = $1 AND (id = $2 OR ancestry = $3 OR ancestry LIKE $3 || '/%')
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(children_ancestry)
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to delete tasks")?;
+
+    Ok(())
+}
+
+/// List the root tasks to execute, in the order they are queued in, locking them until the end of
+/// the transaction. The tasks which are yet to be given their place come last, in the order they
+/// were created.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_queued<'a, E>(executor: E, company_id: i32) -> Result<Vec<QueuedTask>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        SELECT
+            tasks.id, tasks.agent_id, tasks.title,
+            COALESCE(task_schedules.priority, 0) AS priority,
+            task_schedules.queued_at, tasks.created_at
+        FROM tasks
+        LEFT JOIN task_schedules ON task_schedules.task_id = tasks.id
+        WHERE tasks.company_id = $1 AND tasks.ancestry IS NULL
+        AND tasks.status = $2
+        ORDER BY priority DESC, task_schedules.queued_at, tasks.created_at, tasks.id
+        FOR UPDATE OF tasks
+        ",
+    )
+    .bind(company_id)
+    .bind(Status::ToDo.to_string())
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Lock the root task next in line for execution, returning its id. The tasks other workers are
+/// locking at the same time are skipped, for each task to be locked by a single worker. Only the
+/// tasks given their place in the queue are considered.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn lock_next_queued<'a, E>(executor: E, company_id: i32) -> Result<Option<i32>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_scalar(
+        r"
+        SELECT tasks.id
+        FROM tasks
+        JOIN task_schedules ON task_schedules.task_id = tasks.id
+        WHERE tasks.company_id = $1 AND tasks.ancestry IS NULL AND tasks.status = $2
+        AND task_schedules.queued_at IS NOT NULL
+        ORDER BY task_schedules.priority DESC, task_schedules.queued_at, tasks.id
+        LIMIT 1
+        FOR UPDATE OF tasks, task_schedules SKIP LOCKED
+        ",
+    )
+    .bind(company_id)
+    .bind(Status::ToDo.to_string())
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// Clear the queue time of the tasks which are not in the queue anymore, for them to be given a
+/// new place once queued again.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn clear_stale_queued_at<'a, E>(executor: E, company_id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        UPDATE task_schedules
+        SET queued_at = NULL
+        FROM tasks
+        WHERE tasks.id = task_schedules.task_id
+        AND task_schedules.company_id = $1 AND task_schedules.queued_at IS NOT NULL
+        AND (tasks.ancestry IS NOT NULL OR tasks.status != $2)
+        ",
+    )
+    .bind(company_id)
+    .bind(Status::ToDo.to_string())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update tasks queue time")?;
+
+    Ok(())
+}
+
+/// Set the time the task took its place in the queue, or clear it.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_queued_at<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i32,
+    queued_at: Option<DateTime<Utc>>,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        INSERT INTO task_schedules (task_id, company_id, queued_at)
+        VALUES ($2, $1, $3)
+        ON CONFLICT (task_id) DO UPDATE SET queued_at = EXCLUDED.queued_at
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(queued_at)
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update task queue time")?;
+
+    Ok(())
+}
+
+/// Set the priority of the task.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_priority<'a, E>(
+    executor: E,
+    company_id: i32,
+    id: i32,
+    priority: i32,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        INSERT INTO task_schedules (task_id, company_id, priority)
+        VALUES ($2, $1, $3)
+        ON CONFLICT (task_id) DO UPDATE SET priority = EXCLUDED.priority
+        ",
+    )
+    .bind(company_id)
+    .bind(id)
+    .bind(priority)
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update task priority")?;
+
+    Ok(())
+}
@@ -0,0 +1,322 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{query, query_as, types::Json, Executor, Postgres};
+
+use crate::types::{
+    tool_call_policies::{
+        Action, ArgumentRule, Decision, Target, ToolCallDecision, ToolCallPolicy,
+    },
+    Result,
+};
+
+pub struct CreateParams {
+    pub agent_id: Option<i32>,
+    pub target: Target,
+    pub ability_id: Option<i32>,
+    pub action: Action,
+    pub rules: Vec<ArgumentRule>,
+}
+
+pub struct UpdateParams {
+    pub id: i32,
+    pub action: Action,
+    pub rules: Vec<ArgumentRule>,
+}
+
+pub struct CreateDecisionParams {
+    pub tool_call_id: String,
+    pub decision: Decision,
+    pub policy_id: Option<i32>,
+    pub reason: String,
+}
+
+/// List all policies.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list<'a, E>(executor: E, company_id: i32) -> Result<Vec<ToolCallPolicy>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM tool_call_policies WHERE company_id = $1 ORDER BY id")
+            .bind(company_id)
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// List policies applicable to the agent: its own and the ones for every agent.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_for_agent<'a, E>(
+    executor: E,
+    company_id: i32,
+    agent_id: i32,
+) -> Result<Vec<ToolCallPolicy>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        SELECT * FROM tool_call_policies
+        WHERE company_id = $1 AND (agent_id IS NULL OR agent_id = $2)
+        ORDER BY id
+        ",
+    )
+    .bind(company_id)
+    .bind(agent_id)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Get policy by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i32) -> Result<ToolCallPolicy>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM tool_call_policies WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Create policy.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating policy, e.g. there already is a policy
+/// with the same agent and target.
+pub async fn create<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: CreateParams,
+) -> Result<ToolCallPolicy>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO tool_call_policies (
+            company_id, agent_id, target, ability_id, action, rules, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $7)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.agent_id)
+    .bind(params.target.to_string())
+    .bind(params.ability_id)
+    .bind(params.action.to_string())
+    .bind(Json(params.rules))
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Update policy action and rules.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: UpdateParams,
+) -> Result<ToolCallPolicy>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE tool_call_policies
+        SET action = $3, rules = $4, updated_at = $5
+        WHERE company_id = $1 AND id = $2
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.action.to_string())
+    .bind(Json(params.rules))
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Delete policy.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while deleting policy.
+pub async fn delete<'a, E>(executor: E, company_id: i32, id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query("DELETE FROM tool_call_policies WHERE company_id = $1 AND id = $2")
+        .bind(company_id)
+        .bind(id)
+        .execute(executor)
+        .await
+        .with_context(|| "Failed to delete tool call policy")?;
+
+    Ok(())
+}
+
+/// Record decisions on the tool calls of the message, except for the ones already allowed or
+/// denied. Only a tool call left for the user to decide may be decided again.
+///
+/// Returns the decisions recorded, so out of several concurrent callers exactly one gets to act on
+/// each tool call.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn claim_decisions<'a, E>(
+    executor: E,
+    company_id: i32,
+    message_id: i64,
+    params: Vec<CreateDecisionParams>,
+) -> Result<Vec<ToolCallDecision>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let mut tool_call_ids = Vec::with_capacity(params.len());
+    let mut decisions = Vec::with_capacity(params.len());
+    let mut policy_ids = Vec::with_capacity(params.len());
+    let mut reasons = Vec::with_capacity(params.len());
+    for params in params {
+        tool_call_ids.push(params.tool_call_id);
+        decisions.push(params.decision.to_string());
+        policy_ids.push(params.policy_id);
+        reasons.push(params.reason);
+    }
+
+    Ok(query_as(
+        r"
+        INSERT INTO tool_call_decisions (
+            company_id, message_id, tool_call_id, decision, policy_id, reason, created_at, updated_at
+        )
+        SELECT $1, $2, decisions.*, $7, $7
+        FROM UNNEST($3::TEXT[], $4::TEXT[], $5::INTEGER[], $6::TEXT[]) AS decisions
+        ON CONFLICT (message_id, tool_call_id) DO UPDATE
+        SET
+            decision = EXCLUDED.decision,
+            policy_id = EXCLUDED.policy_id,
+            reason = EXCLUDED.reason,
+            updated_at = EXCLUDED.updated_at
+        WHERE tool_call_decisions.decision = $8
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(message_id)
+    .bind(tool_call_ids)
+    .bind(decisions)
+    .bind(policy_ids)
+    .bind(reasons)
+    .bind(Utc::now())
+    .bind(Decision::Ask.to_string())
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Leave the allowed tool calls of the message for the user to decide again, e.g. when they
+/// failed to run, for them to be retried.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn release_decisions<'a, E>(
+    executor: E,
+    company_id: i32,
+    message_id: i64,
+    tool_call_ids: &[String],
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        UPDATE tool_call_decisions
+        SET decision = $4, policy_id = NULL, reason = $5, updated_at = $6
+        WHERE company_id = $1 AND message_id = $2 AND tool_call_id = ANY($3) AND decision = $7
+        ",
+    )
+    .bind(company_id)
+    .bind(message_id)
+    .bind(tool_call_ids)
+    .bind(Decision::Ask.to_string())
+    .bind("failed to run, left for user")
+    .bind(Utc::now())
+    .bind(Decision::Allow.to_string())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to release tool call decisions")?;
+
+    Ok(())
+}
+
+/// List decisions made on tool calls of the message.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_decisions_for_message<'a, E>(
+    executor: E,
+    company_id: i32,
+    message_id: i64,
+) -> Result<Vec<ToolCallDecision>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        "SELECT * FROM tool_call_decisions WHERE company_id = $1 AND message_id = $2 ORDER BY id",
+    )
+    .bind(company_id)
+    .bind(message_id)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// List decisions made on tool calls of the chat messages.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_decisions_for_chat<'a, E>(
+    executor: E,
+    company_id: i32,
+    chat_id: i32,
+) -> Result<Vec<ToolCallDecision>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        SELECT tool_call_decisions.*
+        FROM tool_call_decisions
+        INNER JOIN messages ON messages.id = tool_call_decisions.message_id
+        WHERE tool_call_decisions.company_id = $1 AND messages.chat_id = $2
+        ORDER BY tool_call_decisions.id
+        ",
+    )
+    .bind(company_id)
+    .bind(chat_id)
+    .fetch_all(executor)
+    .await?)
+}
@@ -0,0 +1,292 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{query, query_as, types::Json, Executor, Postgres};
+
+use crate::types::{
+    webhooks::{DeliveryStatus, Webhook, WebhookDelivery},
+    Result,
+};
+
+pub struct CreateParams {
+    pub url: String,
+    pub events: Vec<String>,
+    /// Encrypted secret.
+    pub secret: String,
+}
+
+pub struct UpdateParams {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+    pub is_enabled: bool,
+}
+
+pub struct CreateDeliveryParams {
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: Value,
+}
+
+pub struct UpdateDeliveryParams {
+    pub id: i64,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// List all webhooks.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list<'a, E>(executor: E, company_id: i32) -> Result<Vec<Webhook>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM webhooks WHERE company_id = $1 ORDER BY id")
+            .bind(company_id)
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// List enabled webhooks of every company.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_enabled<'a, E>(executor: E) -> Result<Vec<Webhook>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM webhooks WHERE is_enabled ORDER BY id")
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// Get webhook by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, company_id: i32, id: i32) -> Result<Webhook>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM webhooks WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Find webhook by id, if it still exists.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn find<'a, E>(executor: E, company_id: i32, id: i32) -> Result<Option<Webhook>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM webhooks WHERE company_id = $1 AND id = $2")
+            .bind(company_id)
+            .bind(id)
+            .fetch_optional(executor)
+            .await?,
+    )
+}
+
+/// Create webhook.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating webhook.
+pub async fn create<'a, E>(executor: E, company_id: i32, params: CreateParams) -> Result<Webhook>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO webhooks (company_id, url, events, secret, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.url)
+    .bind(params.events)
+    .bind(params.secret)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Update webhook URL, events and whether it's enabled.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update<'a, E>(executor: E, company_id: i32, params: UpdateParams) -> Result<Webhook>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        UPDATE webhooks
+        SET url = $3, events = $4, is_enabled = $5, updated_at = $6
+        WHERE company_id = $1 AND id = $2
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.url)
+    .bind(params.events)
+    .bind(params.is_enabled)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Delete webhook along with its deliveries.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while deleting webhook.
+pub async fn delete<'a, E>(executor: E, company_id: i32, id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query("DELETE FROM webhooks WHERE company_id = $1 AND id = $2")
+        .bind(company_id)
+        .bind(id)
+        .execute(executor)
+        .await
+        .with_context(|| "Failed to delete webhook")?;
+
+    Ok(())
+}
+
+/// List the latest deliveries of the webhook, newest first.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_deliveries<'a, E>(
+    executor: E,
+    company_id: i32,
+    webhook_id: i32,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        SELECT * FROM webhook_deliveries
+        WHERE company_id = $1 AND webhook_id = $2
+        ORDER BY id DESC
+        LIMIT $3
+        ",
+    )
+    .bind(company_id)
+    .bind(webhook_id)
+    .bind(limit)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// List pending deliveries of every company, oldest first.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list_pending_deliveries<'a, E>(executor: E) -> Result<Vec<WebhookDelivery>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_as("SELECT * FROM webhook_deliveries WHERE status = $1 ORDER BY id")
+            .bind(DeliveryStatus::Pending.to_string())
+            .fetch_all(executor)
+            .await?,
+    )
+}
+
+/// Create pending delivery.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating delivery.
+pub async fn create_delivery<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: CreateDeliveryParams,
+) -> Result<WebhookDelivery>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO webhook_deliveries (
+            company_id, webhook_id, event, payload, status, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $6)
+        RETURNING *
+        ",
+    )
+    .bind(company_id)
+    .bind(params.webhook_id)
+    .bind(params.event)
+    .bind(Json(params.payload))
+    .bind(DeliveryStatus::Pending.to_string())
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Record the outcome of a delivery attempt.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn update_delivery<'a, E>(
+    executor: E,
+    company_id: i32,
+    params: UpdateDeliveryParams,
+) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        UPDATE webhook_deliveries
+        SET status = $3, attempts = $4, response_status = $5, error = $6, updated_at = $7
+        WHERE company_id = $1 AND id = $2
+        ",
+    )
+    .bind(company_id)
+    .bind(params.id)
+    .bind(params.status.to_string())
+    .bind(params.attempts)
+    .bind(params.response_status)
+    .bind(params.error)
+    .bind(Utc::now())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to update webhook delivery")?;
+
+    Ok(())
+}
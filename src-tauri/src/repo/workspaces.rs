@@ -0,0 +1,228 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{query, query_as, query_scalar, Executor, PgConnection, Postgres};
+
+use crate::types::{workspaces::Workspace, Result};
+
+/// Tables of `bridge_common` scoped by company, in the order they can be cleared in without
+/// breaking foreign keys. The tables of the application go along with the company instead.
+const COMMON_TABLES: [&str; 12] = [
+    "task_results",
+    "tasks",
+    "messages",
+    "agents_chats",
+    "chats",
+    "pages",
+    "agent_abilities",
+    "abilities",
+    "agents",
+    "models",
+    "settings",
+    "users",
+];
+
+/// List all workspaces.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn list<'a, E>(executor: E) -> Result<Vec<Workspace>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as("SELECT * FROM companies ORDER BY id")
+        .fetch_all(executor)
+        .await?)
+}
+
+/// Get workspace by id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get<'a, E>(executor: E, id: i32) -> Result<Workspace>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as("SELECT * FROM companies WHERE id = $1")
+        .bind(id)
+        .fetch_one(executor)
+        .await?)
+}
+
+/// Whether there is a workspace with the slug.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn is_slug_taken<'a, E>(executor: E, slug: &str) -> Result<bool>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_scalar("SELECT EXISTS (SELECT 1 FROM companies WHERE slug = $1)")
+            .bind(slug)
+            .fetch_one(executor)
+            .await?,
+    )
+}
+
+/// Create workspace.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while creating workspace, e.g. the slug is taken.
+pub async fn create<'a, E>(executor: E, name: &str, slug: &str) -> Result<Workspace>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as(
+        r"
+        INSERT INTO companies (auth_id, name, slug, created_at, updated_at)
+        VALUES ($2, $1, $2, $3, $3)
+        RETURNING *
+        ",
+    )
+    .bind(name)
+    .bind(slug)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Copy models from one workspace to another.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn copy_models<'a, E>(executor: E, from_id: i32, to_id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        INSERT INTO models (
+            company_id, provider, name, context_length, max_tokens,
+            text_in, text_out, image_in, image_out, audio_in, audio_out, function_calling,
+            api_url, api_key, created_at, updated_at
+        )
+        SELECT
+            $2, provider, name, context_length, max_tokens,
+            text_in, text_out, image_in, image_out, audio_in, audio_out, function_calling,
+            api_url, api_key, $3, $3
+        FROM models
+        WHERE company_id = $1
+        ",
+    )
+    .bind(from_id)
+    .bind(to_id)
+    .bind(Utc::now())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to copy models")?;
+
+    Ok(())
+}
+
+/// Copy agent, without its abilities, from one workspace to another.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn copy_agent<'a, E>(executor: E, from_id: i32, agent_id: i32, to_id: i32) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    query(
+        r"
+        INSERT INTO agents (
+            company_id, name, description, system_message, is_enabled,
+            is_code_interpreter_enabled, is_web_browser_enabled, execution_steps_limit,
+            created_at, updated_at
+        )
+        SELECT
+            $3, name, description, system_message, is_enabled,
+            is_code_interpreter_enabled, is_web_browser_enabled, execution_steps_limit,
+            $4, $4
+        FROM agents
+        WHERE company_id = $1 AND id = $2
+        ",
+    )
+    .bind(from_id)
+    .bind(agent_id)
+    .bind(to_id)
+    .bind(Utc::now())
+    .execute(executor)
+    .await
+    .with_context(|| "Failed to copy agent")?;
+
+    Ok(())
+}
+
+/// Get the id of the local user of the workspace, the first one if there are several.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn get_user_id<'a, E>(executor: E, id: i32) -> Result<Option<i32>>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(
+        query_scalar("SELECT id FROM users WHERE company_id = $1 ORDER BY id LIMIT 1")
+            .bind(id)
+            .fetch_optional(executor)
+            .await?,
+    )
+}
+
+/// Create the local user of the workspace, returning its id.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn create_user<'a, E>(executor: E, id: i32) -> Result<i32>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    Ok(query_scalar(
+        r"
+        INSERT INTO users (company_id, first_name, last_name, created_at, updated_at)
+        VALUES ($1, 'User', 'Bridge', $2, $2)
+        RETURNING id
+        ",
+    )
+    .bind(id)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await
+    .with_context(|| "Failed to create user")?)
+}
+
+/// Delete workspace with everything in it.
+///
+/// Should be run in a transaction, so the workspace is not left half-deleted.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn delete(conn: &mut PgConnection, id: i32) -> Result<()> {
+    for table in COMMON_TABLES {
+        query(&format!("DELETE FROM {table} WHERE company_id = $1"))
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to delete workspace {table}"))?;
+    }
+
+    query("DELETE FROM companies WHERE id = $1")
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .with_context(|| "Failed to delete workspace")?;
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encryption of the secrets kept in the database, such as provider API keys.
+//!
+//! Secrets are encrypted with AES-256-GCM. The key is derived from the `BRIDGE_SECRETS_PASSPHRASE`
+//! environment variable with Argon2 and a salt stored in the database, so every install sharing the
+//! database and the passphrase reads the same secrets. Without the passphrase, the key is kept in
+//! the OS keyring, which suits a single install. Either way, the database alone is not enough to
+//! read the secrets.
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::{info, warn};
+
+use crate::{
+    repo,
+    types::{DbPool, Result},
+};
+
+const KEYRING_SERVICE: &str = "ai.starfleet.bridge";
+const KEYRING_USER: &str = "secrets-encryption-key";
+const PASSPHRASE_ENV: &str = "BRIDGE_SECRETS_PASSPHRASE";
+/// Prefix of the encrypted values, so they can be told apart from the plain ones stored before.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// Known text encrypted with the passphrase key, to tell a wrong passphrase right away.
+const CHECK_TEXT: &str = "bridge";
+const NONCE_LEN: usize = 12;
+const VISIBLE_CHARS: usize = 4;
+/// Shown in place of the secrets which can't be decrypted.
+pub const UNREADABLE_MASK: &str = "[encrypted with another key]";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("no OS keyring is available, set `{PASSPHRASE_ENV}` to encrypt secrets with a passphrase instead")]
+    NoKeyStorage,
+    #[error("wrong passphrase in `{PASSPHRASE_ENV}`")]
+    WrongPassphrase,
+    #[error("failed to encrypt secret")]
+    Encrypt,
+    #[error("failed to decrypt secret, it may have been encrypted with another key")]
+    Decrypt,
+    #[error("secrets encryption key is corrupted")]
+    CorruptedKey,
+}
+
+pub struct Cipher {
+    cipher: Aes256Gcm,
+}
+
+impl Cipher {
+    /// Derive the encryption key from the passphrase, if it's set, or load it from the OS keyring,
+    /// creating it on the first run.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if there is neither passphrase nor keyring, if the passphrase is wrong, or if
+    /// the key can't be read or saved.
+    pub async fn load(pool: &DbPool) -> Result<Self> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            return load_from_passphrase(pool, &passphrase).await;
+        }
+
+        match load_from_keyring() {
+            Ok(key) => Ok(Self::new(&key)),
+            Err(err) => {
+                warn!("OS keyring is not available: {err}");
+
+                Err(Error::NoKeyStorage.into())
+            }
+        }
+    }
+
+    fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// Encrypt the secret, which is then safe to store.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the secret can't be encrypted.
+    pub fn encrypt(&self, secret: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| Error::Encrypt)?;
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&ciphertext);
+
+        Ok(format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(bytes)))
+    }
+
+    /// Decrypt the secret. Secrets stored before encryption was introduced are returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the secret was encrypted with another key or is corrupted.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let bytes = STANDARD.decode(encoded).map_err(|_| Error::Decrypt)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(Error::Decrypt.into());
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::Decrypt)?;
+
+        Ok(String::from_utf8(secret).map_err(|_| Error::Decrypt)?)
+    }
+
+    /// Decrypt and mask the secret for the user to recognize it. Secrets which can't be decrypted,
+    /// e.g. encrypted by an install with another key, are shown as such.
+    #[must_use]
+    pub fn mask(&self, value: &str) -> String {
+        match self.decrypt(value) {
+            Ok(secret) => mask(&secret),
+            Err(_) => UNREADABLE_MASK.to_string(),
+        }
+    }
+}
+
+/// Whether the value is encrypted by `Cipher`.
+#[must_use]
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Hide the secret, leaving only its last characters visible for the user to recognize it.
+#[must_use]
+pub fn mask(secret: &str) -> String {
+    let chars = secret.chars().count();
+    if chars <= VISIBLE_CHARS * 2 {
+        return "*".repeat(chars);
+    }
+
+    let visible = secret
+        .chars()
+        .skip(chars - VISIBLE_CHARS)
+        .collect::<String>();
+
+    format!("{}{visible}", "*".repeat(chars - VISIBLE_CHARS))
+}
+
+fn load_from_keyring() -> std::result::Result<[u8; 32], keyring::Error> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?;
+
+    match entry.get_password() {
+        Ok(encoded) => {
+            decode_key(&encoded).map_err(|err| keyring::Error::PlatformFailure(err.into()))
+        }
+        Err(keyring::Error::NoEntry) => {
+            info!("Creating secrets encryption key in the OS keyring");
+
+            let key: [u8; 32] = Aes256Gcm::generate_key(&mut OsRng).into();
+            entry.set_password(&STANDARD.encode(key))?;
+
+            Ok(key)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+async fn load_from_passphrase(pool: &DbPool, passphrase: &str) -> Result<Cipher> {
+    if repo::secrets::get_passphrase(pool).await?.is_none() {
+        info!("Creating secrets passphrase salt in the database");
+
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Cipher::new(&derive_key(passphrase, &salt)?);
+
+        // Another install may have got there first, in which case its salt is the one to use
+        repo::secrets::create_passphrase(
+            pool,
+            &STANDARD.encode(salt),
+            &cipher.encrypt(CHECK_TEXT)?,
+        )
+        .await?;
+    }
+
+    let (salt, check) = repo::secrets::get_passphrase(pool)
+        .await?
+        .ok_or(Error::CorruptedKey)?;
+
+    let salt = STANDARD.decode(salt).map_err(|_| Error::CorruptedKey)?;
+    let cipher = Cipher::new(&derive_key(passphrase, &salt)?);
+
+    if cipher.decrypt(&check).ok().as_deref() != Some(CHECK_TEXT) {
+        return Err(Error::WrongPassphrase.into());
+    }
+
+    Ok(cipher)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| Error::CorruptedKey)?;
+
+    Ok(key)
+}
+
+fn decode_key(encoded: &str) -> std::result::Result<[u8; 32], Error> {
+    STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(Error::CorruptedKey)
+}
@@ -0,0 +1,99 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Commands served over HTTP, the same the desktop app registers with Tauri.
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use super::Error;
+use crate::{
+    commands,
+    state::{Owned, State},
+};
+
+/// Arguments of a command call, keyed in camelCase like Tauri expects them.
+struct Args(Map<String, Value>);
+
+impl Args {
+    /// Take the argument out, a missing one being `null`, as Tauri does.
+    fn take<T: DeserializeOwned>(&mut self, name: &str) -> Result<T, Error> {
+        let name = camel_case(name);
+        let value = self.0.remove(&name).unwrap_or(Value::Null);
+
+        serde_json::from_value(value).map_err(|source| Error::InvalidArgument { name, source })
+    }
+}
+
+/// Resolve a command argument: the state the server owns, or the one passed by the client.
+macro_rules! arg {
+    ($state:ident, $args:ident, pool) => {
+        State::new(&$state.pool)
+    };
+    ($state:ident, $args:ident, channel) => {
+        State::new(&$state.channel)
+    };
+    ($state:ident, $args:ident, events) => {
+        State::new(&$state.events)
+    };
+    ($state:ident, $args:ident, settings) => {
+        State::new(&$state.settings)
+    };
+    ($state:ident, $args:ident, cipher) => {
+        State::new(&$state.cipher)
+    };
+    ($state:ident, $args:ident, workspace) => {
+        State::new(&$state.workspace)
+    };
+    ($state:ident, $args:ident, executor) => {
+        State::new(&$state.executor)
+    };
+    ($state:ident, $args:ident, app_local_data_dir) => {
+        State::new(&$state.app_local_data_dir)
+    };
+    ($state:ident, $args:ident, $name:ident) => {
+        $args.take(stringify!($name))?
+    };
+}
+
+macro_rules! commands {
+    ($($module:ident::$command:ident($($arg:ident),*),)*) => {
+        /// Call the command by its name, returning what it returns as JSON.
+        pub(super) async fn call(
+            owned: &Owned,
+            name: &str,
+            args: Map<String, Value>,
+        ) -> Result<Value, Error> {
+            let mut args = Args(args);
+
+            match name {
+                $(stringify!($command) => {
+                    let result = commands::$module::$command($(arg!(owned, args, $arg)),*).await?;
+
+                    Ok(serde_json::to_value(result).map_err(anyhow::Error::from)?)
+                })*
+                _ => Err(Error::UnknownCommand(name.to_string())),
+            }
+        }
+    };
+}
+
+crate::with_commands!(commands);
+
+fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut is_upper = false;
+
+    for c in name.chars() {
+        if c == '_' {
+            is_upper = true;
+        } else if is_upper {
+            camel.extend(c.to_uppercase());
+            is_upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+
+    camel
+}
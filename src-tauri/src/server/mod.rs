@@ -0,0 +1,256 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Headless server, serving the commands as a JSON HTTP API and streaming events over WebSocket.
+//!
+//! A command is called with `POST /commands/{name}`, its arguments being a JSON object keyed the
+//! same way the frontend passes them to `invoke`, e.g. `{"chatId": 1}`. The response is the JSON
+//! the command returns, or the `{code, message, details}` error. Events are streamed to every
+//! client connected to `GET /events` as `{"name": ..., "payload": ...}` text messages.
+//!
+//! When a token is set, every request but `GET /health` has to carry it as
+//! `Authorization: Bearer {token}`, or as the percent-encoded `token` query parameter, since
+//! browsers can't set headers on WebSocket requests.
+
+use std::{borrow::Cow, convert::Infallible, net::SocketAddr, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{
+    body::Incoming, header, server::conn::http1, service::service_fn, Method, Request, Response,
+    StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Map, Value};
+use subtle::ConstantTimeEq;
+use tokio::{net::TcpListener, spawn, sync::broadcast};
+use tracing::{debug, error, info, instrument};
+
+use crate::{
+    channel::BroadcastChannel,
+    events::Events,
+    state::{Loaded, Owned},
+    webhooks,
+};
+
+mod commands;
+mod websocket;
+
+/// How many events a WebSocket client may lag behind before it starts missing them.
+const EVENTS_CAPACITY: usize = 1024;
+/// Largest request body accepted, in bytes.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Command(#[from] crate::errors::Error),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+    #[error("invalid argument `{name}`: {source}")]
+    InvalidArgument {
+        name: String,
+        source: serde_json::Error,
+    },
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("not found")]
+    NotFound,
+    #[error("missing or wrong token")]
+    Unauthorized,
+    #[error("unknown command `{0}`")]
+    UnknownCommand(String),
+}
+
+impl Error {
+    fn into_response(self) -> Response<Full<Bytes>> {
+        let (status, body) = match &self {
+            Error::Command(err) => {
+                let status = match err.code() {
+                    "not_found" => StatusCode::NOT_FOUND,
+                    "internal" | "database" => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::UNPROCESSABLE_ENTITY,
+                };
+
+                (status, json!(err))
+            }
+            Error::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.describe("internal")),
+            Error::InvalidArgument { name, .. } => (
+                StatusCode::BAD_REQUEST,
+                json!({ "code": "invalid_argument", "message": self.to_string(), "details": { "argument": name } }),
+            ),
+            Error::InvalidRequest(_) => (StatusCode::BAD_REQUEST, self.describe("invalid_request")),
+            Error::NotFound => (StatusCode::NOT_FOUND, self.describe("not_found")),
+            Error::Unauthorized => (StatusCode::UNAUTHORIZED, self.describe("unauthorized")),
+            Error::UnknownCommand(_) => (StatusCode::NOT_FOUND, self.describe("unknown_command")),
+        };
+
+        json_response(status, &body)
+    }
+
+    fn describe(&self, code: &str) -> Value {
+        json!({ "code": code, "message": self.to_string(), "details": Value::Null })
+    }
+}
+
+#[derive(Clone)]
+pub struct Server {
+    state: Arc<Owned>,
+    events: broadcast::Sender<String>,
+    token: Option<Arc<str>>,
+}
+
+impl Server {
+    #[must_use]
+    pub fn new(loaded: Loaded, app_local_data_dir: PathBuf, token: Option<String>) -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        let (app_events, queue) = Events::new(
+            Box::new(BroadcastChannel::new(events.clone())),
+            loaded.pool.clone(),
+        );
+        let state = Owned::new(loaded, app_events, app_local_data_dir);
+        webhooks::start(&state, queue);
+
+        Self {
+            state,
+            events,
+            token: token.map(Arc::from),
+        }
+    }
+
+    /// State the commands are served with, to run the task executor with.
+    #[must_use]
+    pub fn state(&self) -> &Arc<Owned> {
+        &self.state
+    }
+
+    /// Accept connections until the process is stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the address can't be bound.
+    pub async fn serve(self, addr: SocketAddr) -> crate::types::Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind to {addr}"))?;
+
+        info!("Listening on {}", addr);
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    error!("Failed to accept connection: {:?}", err);
+                    continue;
+                }
+            };
+
+            let server = self.clone();
+            spawn(async move {
+                let service = service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(request).await) }
+                });
+
+                if let Err(err) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .with_upgrades()
+                    .await
+                {
+                    debug!("Connection with {} failed: {:?}", peer, err);
+                }
+            });
+        }
+    }
+
+    #[instrument(skip_all, fields(method = %request.method(), path = request.uri().path()))]
+    async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let result = if request.uri().path() == "/health" {
+            Ok(json_response(StatusCode::OK, &json!({ "status": "ok" })))
+        } else if self.is_authorized(&request) {
+            self.route(request).await
+        } else {
+            Err(Error::Unauthorized)
+        };
+
+        result.unwrap_or_else(|err| {
+            debug!("Request failed: {}", err);
+            err.into_response()
+        })
+    }
+
+    async fn route(&self, request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Error> {
+        let path = request.uri().path().to_string();
+
+        match (request.method(), path.as_str()) {
+            (&Method::GET, "/events") => websocket::upgrade(request, self.events.subscribe()),
+            (&Method::POST, path) => {
+                let name = path.strip_prefix("/commands/").ok_or(Error::NotFound)?;
+                let args = read_args(request).await?;
+                let value = commands::call(&self.state, name, args).await?;
+
+                Ok(json_response(StatusCode::OK, &value))
+            }
+            _ => Err(Error::NotFound),
+        }
+    }
+
+    fn is_authorized(&self, request: &Request<Incoming>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+
+        let bearer = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let query = request
+            .uri()
+            .query()
+            .and_then(|query| {
+                query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("token="))
+            })
+            .and_then(|value| percent_decode_str(value).decode_utf8().ok());
+
+        // Compared in constant time, not to leak how much of the token a guess got right.
+        bearer
+            .map(Cow::Borrowed)
+            .or(query)
+            .is_some_and(|given| given.as_bytes().ct_eq(token.as_bytes()).into())
+    }
+}
+
+async fn read_args(request: Request<Incoming>) -> Result<Map<String, Value>, Error> {
+    let body = Limited::new(request.into_body(), MAX_BODY_SIZE)
+        .collect()
+        .await
+        .map_err(|err| Error::InvalidRequest(err.to_string()))?
+        .to_bytes();
+
+    if body.is_empty() {
+        return Ok(Map::new());
+    }
+
+    match serde_json::from_slice(&body).map_err(|err| Error::InvalidRequest(err.to_string()))? {
+        Value::Object(args) => Ok(args),
+        _ => Err(Error::InvalidRequest(
+            "arguments must be a JSON object".to_string(),
+        )),
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+
+    response
+}
@@ -0,0 +1,115 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Event streaming over WebSocket. Hyper completes the handshake and hands the upgraded connection
+//! over to `tokio-tungstenite`, which answers pings and closes on its own. Messages from the client
+//! are otherwise ignored.
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::Full;
+use hyper::{body::Incoming, header, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    select, spawn,
+    sync::broadcast,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        self, handshake::derive_accept_key, protocol::Role, protocol::WebSocketConfig, Message,
+    },
+    WebSocketStream,
+};
+use tracing::{debug, warn};
+
+use super::Error;
+
+/// Largest message accepted from the client, in bytes.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Complete the handshake and start streaming events to the client once the connection is
+/// upgraded.
+pub(super) fn upgrade(
+    mut request: Request<Incoming>,
+    events: broadcast::Receiver<String>,
+) -> Result<Response<Full<Bytes>>, Error> {
+    let is_websocket = request
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let key = request
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .filter(|_| is_websocket)
+        .ok_or_else(|| Error::InvalidRequest("expected a WebSocket handshake".to_string()))?;
+
+    let response = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::UPGRADE, "websocket")
+        .header(header::CONNECTION, "Upgrade")
+        .header(
+            header::SEC_WEBSOCKET_ACCEPT,
+            derive_accept_key(key.as_bytes()),
+        )
+        .body(Full::default())
+        .context("Failed to build WebSocket handshake response")?;
+
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                debug!("WebSocket client connected");
+
+                let config = WebSocketConfig {
+                    max_message_size: Some(MAX_MESSAGE_SIZE),
+                    max_frame_size: Some(MAX_MESSAGE_SIZE),
+                    ..WebSocketConfig::default()
+                };
+                let websocket = WebSocketStream::from_raw_socket(
+                    TokioIo::new(upgraded),
+                    Role::Server,
+                    Some(config),
+                )
+                .await;
+
+                if let Err(err) = stream(websocket, events).await {
+                    debug!("WebSocket connection failed: {:?}", err);
+                }
+
+                debug!("WebSocket client disconnected");
+            }
+            Err(err) => warn!("Failed to upgrade to WebSocket: {:?}", err),
+        }
+    });
+
+    Ok(response)
+}
+
+async fn stream<S>(
+    mut websocket: WebSocketStream<S>,
+    mut events: broadcast::Receiver<String>,
+) -> Result<(), tungstenite::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        select! {
+            event = events.recv() => match event {
+                Ok(message) => websocket.send(Message::Text(message)).await?,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket client lags behind, {} events skipped", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return websocket.close(None).await,
+            },
+            // Reading drives the replies to pings and closes, and tells when the client is gone.
+            message = websocket.next() => match message {
+                Some(Ok(_)) => {}
+                Some(Err(tungstenite::Error::ConnectionClosed)) | None => return Ok(()),
+                Some(Err(err)) => return Err(err),
+            },
+        }
+    }
+}
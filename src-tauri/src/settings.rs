@@ -0,0 +1,310 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Application settings.
+//!
+//! `bridge_common::settings::Settings` is extended with the sections only the application cares
+//! about. Both are kept in the same JSON value, so `bridge_common` still reads its part as is.
+//!
+//! The value carries its schema `version`. Values of older versions are brought up to date by the
+//! functions in `MIGRATIONS` before being parsed, so a field can be renamed or given a default
+//! which depends on other fields. To change the schema, add a migration, which bumps `VERSION`.
+
+use std::collections::BTreeMap;
+
+use bridge_common::types::models::Provider;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Migrations from every older version, `MIGRATIONS[n]` migrates from version `n` to `n + 1`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_to_v1];
+/// Current settings schema version.
+pub const VERSION: u64 = MIGRATIONS.len() as u64;
+
+const DEFAULT_CPU_TIME_LIMIT_SECS: u64 = 60;
+const DEFAULT_CPUS: f64 = 1.0;
+const DEFAULT_MEMORY_LIMIT_MB: u64 = 512;
+const DEFAULT_PROCESSES_LIMIT: u64 = 64;
+const DEFAULT_OUTPUT_LIMIT_BYTES: usize = 256 * 1024;
+const DEFAULT_TOOL_CALLS_EXECUTION_CONCURRENCY: u16 = 4;
+const DEFAULT_TOOL_CALLS_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_TOOL_CALLS_OUTPUT_LIMIT_CHARS: usize = 8 * 1024;
+
+/// Limits for the code run in containers: abilities, their function definitions and the code
+/// interpreter of the task executor.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Sandbox {
+    /// Whether the limits apply. Without them, the code runs in containers as it is.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// CPU time every process is allowed to consume before being killed.
+    #[serde(default = "default_cpu_time_limit_secs")]
+    pub cpu_time_limit_secs: u64,
+    /// Number of CPUs available, may be fractional.
+    #[serde(default = "default_cpus")]
+    pub cpus: f64,
+    #[serde(default = "default_memory_limit_mb")]
+    pub memory_limit_mb: u64,
+    /// Maximum number of processes and threads running at once.
+    #[serde(default = "default_processes_limit")]
+    pub processes_limit: u64,
+    /// Combined size of `stdout` and `stderr` after which the run is stopped.
+    #[serde(default = "default_output_limit_bytes")]
+    pub output_limit_bytes: usize,
+    #[serde(default)]
+    pub is_network_enabled: bool,
+}
+
+fn default_cpu_time_limit_secs() -> u64 {
+    DEFAULT_CPU_TIME_LIMIT_SECS
+}
+
+fn default_cpus() -> f64 {
+    DEFAULT_CPUS
+}
+
+fn default_memory_limit_mb() -> u64 {
+    DEFAULT_MEMORY_LIMIT_MB
+}
+
+fn default_processes_limit() -> u64 {
+    DEFAULT_PROCESSES_LIMIT
+}
+
+fn default_output_limit_bytes() -> usize {
+    DEFAULT_OUTPUT_LIMIT_BYTES
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cpu_time_limit_secs: DEFAULT_CPU_TIME_LIMIT_SECS,
+            cpus: DEFAULT_CPUS,
+            memory_limit_mb: DEFAULT_MEMORY_LIMIT_MB,
+            processes_limit: DEFAULT_PROCESSES_LIMIT,
+            output_limit_bytes: DEFAULT_OUTPUT_LIMIT_BYTES,
+            is_network_enabled: false,
+        }
+    }
+}
+
+/// What to do with a tool call output which is too long to be sent to LLM as is.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputOverflow {
+    /// Send the beginning of the output.
+    #[default]
+    Truncate,
+    /// Ask LLM to summarize the output and send the summary instead.
+    Summarize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolCalls {
+    /// How many approved tool calls of a message may run at once.
+    #[serde(default = "default_tool_calls_execution_concurrency")]
+    pub execution_concurrency: u16,
+    /// Wall time a tool call may take before its process is killed, unless the ability sets its
+    /// own timeout.
+    #[serde(default = "default_tool_calls_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Length of the tool call output sent to LLM. The full output is kept as an artifact.
+    #[serde(default = "default_tool_calls_output_limit_chars")]
+    pub output_limit_chars: usize,
+    #[serde(default)]
+    pub output_overflow: OutputOverflow,
+}
+
+fn default_tool_calls_execution_concurrency() -> u16 {
+    DEFAULT_TOOL_CALLS_EXECUTION_CONCURRENCY
+}
+
+fn default_tool_calls_timeout_secs() -> u64 {
+    DEFAULT_TOOL_CALLS_TIMEOUT_SECS
+}
+
+fn default_tool_calls_output_limit_chars() -> usize {
+    DEFAULT_TOOL_CALLS_OUTPUT_LIMIT_CHARS
+}
+
+impl Default for ToolCalls {
+    fn default() -> Self {
+        Self {
+            execution_concurrency: DEFAULT_TOOL_CALLS_EXECUTION_CONCURRENCY,
+            timeout_secs: DEFAULT_TOOL_CALLS_TIMEOUT_SECS,
+            output_limit_chars: DEFAULT_TOOL_CALLS_OUTPUT_LIMIT_CHARS,
+            output_overflow: OutputOverflow::default(),
+        }
+    }
+}
+
+/// Which tasks needing attention the desktop app shows notifications for, while its window is not
+/// focused.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Notifications {
+    /// A tool call of the task is waiting for approval.
+    #[serde(default = "default_true")]
+    pub tool_call_approval: bool,
+    /// The task asks the user a question.
+    #[serde(default = "default_true")]
+    pub waiting_for_user: bool,
+    #[serde(default = "default_true")]
+    pub done: bool,
+    #[serde(default = "default_true")]
+    pub failed: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            tool_call_approval: true,
+            waiting_for_user: true,
+            done: true,
+            failed: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Planning {
+    /// Propose plans for the user to review, instead of saving them straight away.
+    #[serde(default)]
+    pub review: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Settings {
+    /// Schema version the settings were saved with.
+    #[serde(default)]
+    pub version: u64,
+    #[serde(flatten)]
+    pub common: bridge_common::settings::Settings,
+    #[serde(default)]
+    pub sandbox: Sandbox,
+    #[serde(default)]
+    pub tool_calls: ToolCalls,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub planning: Planning,
+    /// API keys which can't be decrypted, e.g. encrypted by an install with another key. They're
+    /// kept as stored, to be written back unchanged unless the user enters new ones.
+    #[serde(skip)]
+    pub unreadable_api_keys: BTreeMap<Provider, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            common: bridge_common::settings::Settings::default(),
+            sandbox: Sandbox::default(),
+            tool_calls: ToolCalls::default(),
+            notifications: Notifications::default(),
+            planning: Planning::default(),
+            unreadable_api_keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Settings to share, e.g. as a team baseline: everything but the API keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the settings can't be serialized.
+    pub fn export(&self) -> std::result::Result<Value, Error> {
+        let mut value = serde_json::to_value(self).map_err(Error::JsonSerialization)?;
+        if let Value::Object(map) = &mut value {
+            map.remove("api_keys");
+        }
+
+        Ok(value)
+    }
+
+    /// Settings imported from `value` exported by `export`, possibly by an older version. API keys
+    /// are kept from the current settings, even if `value` has some.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if `value` is not valid settings or is of a newer version.
+    pub fn import(&self, value: Value) -> std::result::Result<Self, Error> {
+        let mut settings = Self::try_from(value)?;
+        settings.common.api_keys.clone_from(&self.common.api_keys);
+        settings
+            .unreadable_api_keys
+            .clone_from(&self.unreadable_api_keys);
+
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to parse settings: {0}")]
+    JsonDeserialization(serde_json::Error),
+    #[error("failed to serialize settings: {0}")]
+    JsonSerialization(serde_json::Error),
+    #[error("settings must be a JSON object")]
+    NotAnObject,
+    #[error("settings version {0} is newer than the supported version {VERSION}")]
+    UnsupportedVersion(u64),
+}
+
+impl TryFrom<Value> for Settings {
+    type Error = Error;
+
+    fn try_from(value: Value) -> std::result::Result<Self, Self::Error> {
+        serde_json::from_value(migrate(value)?).map_err(Self::Error::JsonDeserialization)
+    }
+}
+
+/// Schema version of the settings value. Values saved before versioning was introduced are
+/// version 0.
+#[must_use]
+pub fn version_of(value: &Value) -> u64 {
+    value.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Bring the settings value up to the current version.
+///
+/// # Errors
+///
+/// Returns error if the value is not an object or is of a newer version.
+pub fn migrate(mut value: Value) -> std::result::Result<Value, Error> {
+    let version = version_of(&value);
+    if version > VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let Value::Object(map) = &mut value else {
+        return Err(Error::NotAnObject);
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(map);
+    }
+    map.insert("version".to_string(), VERSION.into());
+
+    Ok(value)
+}
+
+/// Sections saved as `null` get their defaults, and `tasks.execution_concurrency`, which
+/// `bridge_common` requires, is filled in.
+fn migrate_to_v1(settings: &mut Map<String, Value>) {
+    settings.retain(|_, value| !value.is_null());
+
+    if let Some(Value::Object(tasks)) = settings.get_mut("tasks") {
+        tasks.entry("execution_concurrency").or_insert_with(|| {
+            bridge_common::settings::Tasks::default()
+                .execution_concurrency
+                .into()
+        });
+    }
+}
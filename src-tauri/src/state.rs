@@ -0,0 +1,245 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Application state, managed by Tauri in the desktop app, and owned by `bridge-server` and the CLI.
+//!
+//! Commands take their state as `State`, which Tauri resolves like its own `tauri::State`, and
+//! which the others create from the state they own. This way the same commands serve them all.
+
+use std::{
+    env,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use bridge_common::channel::Channel;
+use tauri::{
+    command::{CommandArg, CommandItem},
+    AppHandle, InvokeError, Manager, Runtime,
+};
+use tokio::sync::RwLock;
+
+use crate::{
+    database,
+    events::Events,
+    repo,
+    secrets::Cipher,
+    settings::Settings,
+    task_executor::Executor,
+    types::{DbPool, Result},
+    workspaces::ActiveWorkspace,
+};
+
+/// Identifier of the desktop app, its local data dir is named after.
+const IDENTIFIER: &str = "com.starfleetai.bridge";
+
+/// Reference to a part of the application state.
+pub struct State<'r, T: Send + Sync + 'static>(&'r T);
+
+impl<'r, T: Send + Sync + 'static> State<'r, T> {
+    #[must_use]
+    pub fn new(inner: &'r T) -> Self {
+        Self(inner)
+    }
+
+    #[must_use]
+    pub fn inner(&self) -> &'r T {
+        self.0
+    }
+}
+
+impl<T: Send + Sync + 'static> Deref for State<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: Send + Sync + 'static> Clone for State<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Send + Sync + 'static> Copy for State<'_, T> {}
+
+impl<'r, 'de: 'r, T: Send + Sync + 'static, R: Runtime> CommandArg<'de, R> for State<'r, T> {
+    fn from_command(command: CommandItem<'de, R>) -> std::result::Result<Self, InvokeError> {
+        tauri::State::<'r, T>::from_command(command).map(|state| Self(state.inner()))
+    }
+}
+
+/// Where the app keeps its local data: tool call workdirs, ability virtualenvs and the like.
+pub struct AppLocalDataDir(pub PathBuf);
+
+impl Deref for AppLocalDataDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// Access to the whole application state, for the code running outside of commands, like the
+/// task executor loop.
+pub trait Shared: Clone + Send + Sync + 'static {
+    fn pool(&self) -> &DbPool;
+    fn channel(&self) -> &Channel;
+    fn events(&self) -> &Events;
+    fn settings(&self) -> &RwLock<Settings>;
+    fn cipher(&self) -> &Cipher;
+    fn workspace(&self) -> &ActiveWorkspace;
+    fn executor(&self) -> &Executor;
+    fn app_local_data_dir(&self) -> &Path;
+}
+
+impl Shared for AppHandle {
+    fn pool(&self) -> &DbPool {
+        self.state::<DbPool>().inner()
+    }
+
+    fn channel(&self) -> &Channel {
+        self.state::<Channel>().inner()
+    }
+
+    fn events(&self) -> &Events {
+        self.state::<Events>().inner()
+    }
+
+    fn settings(&self) -> &RwLock<Settings> {
+        self.state::<RwLock<Settings>>().inner()
+    }
+
+    fn cipher(&self) -> &Cipher {
+        self.state::<Cipher>().inner()
+    }
+
+    fn workspace(&self) -> &ActiveWorkspace {
+        self.state::<ActiveWorkspace>().inner()
+    }
+
+    fn executor(&self) -> &Executor {
+        self.state::<Executor>().inner()
+    }
+
+    fn app_local_data_dir(&self) -> &Path {
+        self.state::<AppLocalDataDir>().inner()
+    }
+}
+
+impl Shared for Arc<Owned> {
+    fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    fn events(&self) -> &Events {
+        &self.events
+    }
+
+    fn settings(&self) -> &RwLock<Settings> {
+        &self.settings
+    }
+
+    fn cipher(&self) -> &Cipher {
+        &self.cipher
+    }
+
+    fn workspace(&self) -> &ActiveWorkspace {
+        &self.workspace
+    }
+
+    fn executor(&self) -> &Executor {
+        &self.executor
+    }
+
+    fn app_local_data_dir(&self) -> &Path {
+        &self.app_local_data_dir
+    }
+}
+
+/// Application state owned outside of Tauri, the same the desktop app keeps managed.
+pub struct Owned {
+    pub pool: DbPool,
+    /// `events` as `bridge_common` takes them.
+    pub channel: Channel,
+    pub events: Events,
+    pub settings: RwLock<Settings>,
+    pub cipher: Cipher,
+    pub workspace: ActiveWorkspace,
+    pub executor: Executor,
+    pub app_local_data_dir: AppLocalDataDir,
+}
+
+impl Owned {
+    #[must_use]
+    pub fn new(loaded: Loaded, events: Events, app_local_data_dir: PathBuf) -> Arc<Self> {
+        Arc::new(Self {
+            pool: loaded.pool,
+            channel: Box::new(events.clone()),
+            events,
+            settings: RwLock::new(loaded.settings),
+            cipher: loaded.cipher,
+            workspace: loaded.workspace,
+            executor: Executor::default(),
+            app_local_data_dir: AppLocalDataDir(app_local_data_dir),
+        })
+    }
+}
+
+/// Parts of the application state loaded on startup.
+pub struct Loaded {
+    pub pool: DbPool,
+    pub cipher: Cipher,
+    pub workspace: ActiveWorkspace,
+    pub settings: Settings,
+}
+
+/// Connect to the database, bring it up to date, and load the active workspace with its settings.
+///
+/// # Errors
+///
+/// Returns error if the database is not reachable, can't be migrated or seeded, or if the secrets
+/// encryption key can't be loaded.
+pub async fn load(app_local_data_dir: &Path) -> Result<Loaded> {
+    let pool = bridge_common::database::new_pool().await?;
+    database::migrate(&pool).await?;
+    database::seed(&pool).await?;
+
+    let cipher = Cipher::load(&pool).await?;
+    let workspace = ActiveWorkspace::load(&pool, app_local_data_dir).await?;
+    let settings = repo::settings::get(&pool, workspace.id(), &cipher).await?;
+
+    Ok(Loaded {
+        pool,
+        cipher,
+        workspace,
+        settings,
+    })
+}
+
+/// App local data dir when running without Tauri: `BRIDGE_DATA_DIR` if set, or the one of the
+/// desktop app. It's created if it doesn't exist.
+///
+/// # Errors
+///
+/// Returns error if the dir can't be resolved or created.
+pub fn resolve_app_local_data_dir() -> Result<PathBuf> {
+    let dir = match env::var("BRIDGE_DATA_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => dirs::data_local_dir()
+            .context("Failed to get local data dir, set `BRIDGE_DATA_DIR` instead")?
+            .join(IDENTIFIER),
+    };
+
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create app local data dir: {}", dir.display()))?;
+
+    Ok(dir)
+}
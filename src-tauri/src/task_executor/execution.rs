@@ -0,0 +1,788 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Execution of a root task along with its subtasks, each in its execution chat.
+//!
+//! The agent is given the task and completes it step by step: its messages are answered by the
+//! code interpreter and the tools it calls, and once it's done talking, it reflects on the result
+//! and marks the task as done, failed, or waiting for the user. A root task with subtasks is
+//! executed one subtask at a time, in the order of the tree.
+//!
+//! The code interpreter and the tools are subject to the tool call policies. Whatever is left for
+//! the user to decide makes the task wait for the user, until the decision puts it back in the
+//! queue.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Context};
+use askama::Template;
+use bridge_common::{
+    channel::{Channel, Event},
+    chats::CreateCompletionParams,
+    clients::openai::{ToolCall, ToolCalls},
+    repo::{self, messages::CreateParams},
+    types::{
+        abilities::Ability,
+        agents::Agent,
+        chats::{Chat, Kind},
+        messages::{self, Message, Role},
+        task_results,
+        tasks::{Status, Task},
+    },
+};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, info, instrument};
+
+use super::Error;
+use crate::{
+    abilities,
+    code_interpreter::{self, CodeBlock, Interpreter},
+    conversations,
+    settings::Settings,
+    task_plans, task_queue, tool_call_policies,
+    types::{tool_call_policies::Decision, DbPool, Result},
+};
+
+/// Execution of the root task next in line, for a single worker.
+pub struct Execution<'a> {
+    pub pool: &'a DbPool,
+    pub channel: &'a Channel,
+    pub settings: &'a Settings,
+    pub company_id: i32,
+    /// Local user of the workspace, who the events are emitted for.
+    pub user_id: i32,
+    /// Where the task workdirs live.
+    pub workdir_root: &'a Path,
+}
+
+impl Execution<'_> {
+    /// Claim the root task next in line and execute it, along with its subtasks.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoRootTasks` if there is no task to execute, or error if the execution failed.
+    #[instrument(skip_all)]
+    pub async fn execute_root_task(&self) -> Result<()> {
+        let Some(mut task) = self.pick_root_task().await? else {
+            return Err(Error::NoRootTasks.into());
+        };
+        self.emit(Event::TaskUpdated(&task)).await?;
+
+        info!("Root task for execution: #{}. {}", task.id, task.title);
+
+        let children_count =
+            repo::tasks::get_all_children_count(self.pool, self.company_id, &task).await?;
+        if children_count > 0 {
+            info!("Executing children tasks for root task #{}", task.id);
+
+            return self.execute_children_task_tree(&task).await;
+        }
+
+        info!("Executing root task #{}", task.id);
+
+        match self.execute_task(&mut task).await {
+            Ok(status) => {
+                debug!(
+                    "Transitioning root task #{} to status: {:?}",
+                    task.id, status
+                );
+
+                let task =
+                    repo::tasks::update_status(self.pool, self.company_id, task.id, status).await?;
+                self.emit(Event::TaskUpdated(&task)).await?;
+
+                Ok(())
+            }
+            Err(err) => {
+                let task = repo::tasks::fail(self.pool, self.company_id, task.id).await?;
+                self.emit(Event::TaskUpdated(&task)).await?;
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Claim the root task next in line, in a transaction of its own.
+    async fn pick_root_task(&self) -> Result<Option<Task>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to begin transaction")?;
+
+        let task = task_queue::pick(&mut tx, self.company_id).await?;
+
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        Ok(task)
+    }
+
+    async fn get_task_execution_chat(&self, task: &Task) -> Result<Chat> {
+        let cid = self.company_id;
+
+        if let Some(chat_id) = task.execution_chat_id {
+            let chat = repo::chats::get(self.pool, cid, chat_id).await?;
+            if chat.kind != Kind::Execution {
+                return Err(Error::NotAnExecutionChat(chat_id).into());
+            }
+
+            return Ok(chat);
+        }
+
+        let chat = repo::chats::create(self.pool, cid, Kind::Execution).await?;
+        repo::tasks::update_execution_chat_id(self.pool, cid, task.id, chat.id).await?;
+        repo::agents_chats::create(self.pool, cid, task.agent_id, chat.id).await?;
+
+        Ok(chat)
+    }
+
+    /// Subtask to execute next, the first one in the tree which is neither in progress nor done,
+    /// moved in progress.
+    async fn get_child_task_for_execution(&self, parent: &Task) -> Result<Option<Task>> {
+        let mut children =
+            repo::tasks::list_all_children(self.pool, self.company_id, &parent.children_ancestry())
+                .await
+                .context("Failed to list children")?;
+        task_plans::sort(self.pool, self.company_id, &mut children).await?;
+
+        let tree = TaskTree::build(parent.clone(), &children)?;
+
+        match tree.execution_candidate() {
+            Some(task) => Ok(Some(
+                repo::tasks::start_progress(self.pool, self.company_id, task.id).await?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn execute_children_task_tree(&self, parent: &Task) -> Result<()> {
+        info!("Executing children tasks tree for task #{}", parent.id);
+
+        loop {
+            let mut child = match self.get_child_task_for_execution(parent).await {
+                Ok(Some(child)) => child,
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    let task = repo::tasks::fail(self.pool, self.company_id, parent.id).await?;
+                    self.emit(Event::TaskUpdated(&task)).await?;
+
+                    return Err(err);
+                }
+            };
+
+            info!("Executing child task #{}: {}", child.id, child.title);
+
+            self.emit(Event::TaskUpdated(&child)).await?;
+
+            match self.execute_task(&mut child).await {
+                Ok(Status::Done) => {
+                    info!("Child task #{} is done", child.id);
+
+                    let task = repo::tasks::complete(self.pool, self.company_id, child.id).await?;
+                    self.emit(Event::TaskUpdated(&task)).await?;
+
+                    self.complete_parent_task(&task).await?;
+                }
+                Ok(status) => {
+                    // The whole tree stops where the subtask did, to go on from there
+                    self.update_tree_status(&child, status).await?;
+
+                    return Ok(());
+                }
+                Err(err) => {
+                    self.update_tree_status(&child, Status::Failed).await?;
+
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Complete the parent of the subtask, if every subtask of it is done.
+    async fn complete_parent_task(&self, child: &Task) -> Result<()> {
+        if !repo::tasks::is_all_siblings_done(self.pool, self.company_id, child).await? {
+            return Ok(());
+        }
+
+        let parent_id = child
+            .parent_id()?
+            .context("Parent id is not set for the child task")?;
+
+        info!(
+            "All siblings are done for the parent task #{}, marking it as done as well",
+            parent_id
+        );
+
+        let task = repo::tasks::complete(self.pool, self.company_id, parent_id).await?;
+        self.emit(Event::TaskUpdated(&task)).await?;
+
+        Ok(())
+    }
+
+    /// Set the status of the subtask, along with every task above it.
+    async fn update_tree_status(&self, child: &Task, status: Status) -> Result<()> {
+        let ids = std::iter::once(child.id).chain(child.parent_ids()?.unwrap_or_default());
+
+        for id in ids {
+            let task = repo::tasks::update_status(self.pool, self.company_id, id, status).await?;
+            self.emit(Event::TaskUpdated(&task)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute the task in its execution chat, returning the status it ends up with.
+    #[instrument(skip_all)]
+    async fn execute_task(&self, task: &mut Task) -> Result<Status> {
+        info!("Executing task #{}: {}", task.id, task.title);
+
+        let chat = self.get_task_execution_chat(task).await?;
+        task.execution_chat_id = Some(chat.id);
+
+        self.emit(Event::TaskUpdated(task)).await?;
+
+        loop {
+            let Some(message) =
+                repo::messages::get_last_message(self.pool, self.company_id, chat.id).await?
+            else {
+                self.send_to_agent(&chat, task).await?;
+                continue;
+            };
+
+            match message.role {
+                Role::CodeInterpreter | Role::Tool | Role::User => {
+                    self.send_to_agent(&chat, task).await?;
+                }
+                Role::Assistant => {
+                    let tool_calls = message.tool_calls();
+                    if tool_calls.is_empty() {
+                        let code_blocks = message
+                            .content
+                            .as_deref()
+                            .and_then(|content| code_interpreter::parse(content).ok())
+                            .filter(|code_blocks| !code_blocks.is_empty());
+
+                        if message.is_self_reflection {
+                            self.send_to_agent(&chat, task).await?;
+                        } else if let Some(code_blocks) = code_blocks {
+                            if let Some(status) = self
+                                .interpret_code_blocks(&message, &code_blocks, task)
+                                .await?
+                            {
+                                return Ok(status);
+                            }
+                        } else {
+                            self.self_reflect(&chat, task).await?;
+                        }
+
+                        continue;
+                    }
+
+                    // Only self-reflection is given the task management tools, the tools called
+                    // otherwise are up to the tool call policies
+                    if !message.is_self_reflection {
+                        match self.call_tools(&message, task).await {
+                            Ok(Some(status)) => return Ok(status),
+                            Ok(None) => continue,
+                            Err(err) => {
+                                self.update_message_status(&message, messages::Status::Failed)
+                                    .await?;
+
+                                return Err(err);
+                            }
+                        }
+                    }
+
+                    match self.call_task_tools(&message, &tool_calls, task).await {
+                        Ok(status) => {
+                            self.update_message_status(&message, messages::Status::Completed)
+                                .await?;
+
+                            if let Some(status) = status {
+                                return Ok(status);
+                            }
+                        }
+                        Err(err) => {
+                            self.update_message_status(&message, messages::Status::Failed)
+                                .await?;
+
+                            return Err(err);
+                        }
+                    }
+                }
+                Role::System => {
+                    return Err(anyhow!("Unexpected system message in the execution chat").into());
+                }
+            }
+        }
+    }
+
+    /// Call the tools of the message as the tool call policies and the user decide. Returns
+    /// `WaitingForUser` if some of them are left for the user to decide, and settles the message
+    /// once every tool call has either a result or a denial.
+    async fn call_tools(&self, message: &Message, task: &Task) -> Result<Option<Status>> {
+        tool_call_policies::decide_for_message(self.pool, message).await?;
+
+        // Decisions made by the user while the task was waiting count as well
+        let decisions = crate::repo::tool_call_policies::list_decisions_for_message(
+            self.pool,
+            self.company_id,
+            message.id,
+        )
+        .await?;
+        let decision_of = |tool_call: &ToolCall| {
+            decisions
+                .iter()
+                .find(|decision| decision.tool_call_id == tool_call.id)
+                .map_or(Decision::Ask, |decision| decision.decision)
+        };
+
+        let mut allowed = Vec::new();
+        let mut denied = Vec::new();
+        let mut is_waiting = false;
+        for tool_call in conversations::pending_tool_calls(self.pool, message, None).await? {
+            match decision_of(&tool_call) {
+                Decision::Allow => allowed.push(tool_call),
+                Decision::Ask => is_waiting = true,
+                Decision::Deny => denied.push(tool_call),
+            }
+        }
+
+        for params in conversations::tool_call_denied_params(message, &denied) {
+            let denied_message = repo::messages::create(self.pool, self.company_id, params).await?;
+            self.emit(Event::MessageCreated(&denied_message)).await?;
+        }
+
+        let (code_interpreter_calls, ability_calls): (Vec<_>, Vec<_>) = allowed
+            .into_iter()
+            .partition(|tool_call| tool_call.function.name == code_interpreter::FUNCTION_NAME);
+        for tool_call in &code_interpreter_calls {
+            self.sfai_code_interpreter(message, tool_call, task).await?;
+        }
+        if !ability_calls.is_empty() {
+            abilities::execute_tool_calls(
+                self.pool,
+                self.channel,
+                self.user_id,
+                self.workdir_root,
+                self.settings,
+                message,
+                &ability_calls,
+            )
+            .await?;
+        }
+
+        if is_waiting {
+            return Ok(Some(Status::WaitingForUser));
+        }
+
+        let is_denied = message
+            .tool_calls()
+            .iter()
+            .all(|tool_call| decision_of(tool_call) == Decision::Deny);
+        let status = if is_denied {
+            messages::Status::ToolCallDenied
+        } else {
+            messages::Status::Completed
+        };
+        self.update_message_status(message, status).await?;
+
+        Ok(None)
+    }
+
+    /// Call the task management tools of the self-reflection, returning the status the task ends
+    /// up with, if any: the agent marks the task as done, failed or waiting for the user.
+    async fn call_task_tools(
+        &self,
+        message: &Message,
+        tool_calls: &ToolCalls,
+        task: &Task,
+    ) -> Result<Option<Status>> {
+        let mut new_status = None;
+
+        for tool_call in tool_calls.iter() {
+            let status = match tool_call.function.name.as_str() {
+                "sfai_done" => self.sfai_done(message, task.id, tool_call).await?,
+                "sfai_fail" => {
+                    self.internal_tool_output(message, tool_call, "Task has been marked as failed")
+                        .await?;
+
+                    Some(Status::Failed)
+                }
+                "sfai_wait_for_user" => {
+                    self.internal_tool_output(message, tool_call, "Waiting for user input")
+                        .await?;
+
+                    Some(Status::WaitingForUser)
+                }
+                _ => None,
+            };
+
+            if status.is_some() {
+                new_status = status;
+            }
+        }
+
+        Ok(new_status)
+    }
+
+    async fn internal_tool_output(
+        &self,
+        message: &Message,
+        tool_call: &ToolCall,
+        content: &str,
+    ) -> Result<Message> {
+        let output = repo::messages::create(
+            self.pool,
+            self.company_id,
+            CreateParams {
+                content: Some(format!("```\n{content}\n```")),
+                chat_id: message.chat_id,
+                status: messages::Status::Completed,
+                role: Role::Tool,
+                tool_call_id: Some(tool_call.id.clone()),
+                is_internal_tool_output: true,
+                ..Default::default()
+            },
+        )
+        .await?;
+        self.emit(Event::MessageCreated(&output)).await?;
+
+        Ok(output)
+    }
+
+    /// Mark the task as done, with the last message of the agent which is not a self-reflection
+    /// as its result.
+    async fn sfai_done(
+        &self,
+        message: &Message,
+        task_id: i32,
+        tool_call: &ToolCall,
+    ) -> Result<Option<Status>> {
+        self.internal_tool_output(message, tool_call, "Task has been marked as done")
+            .await?;
+
+        if let Some(result_message) = repo::messages::get_last_non_self_reflection_message(
+            self.pool,
+            self.company_id,
+            message.chat_id,
+        )
+        .await?
+        {
+            let task_result = repo::task_results::create(
+                self.pool,
+                self.company_id,
+                repo::task_results::CreateParams {
+                    agent_id: result_message
+                        .agent_id
+                        .context("Agent is not set for the message with a tool call")?,
+                    task_id,
+                    kind: task_results::Kind::Text,
+                    data: result_message.content.clone().unwrap_or_default(),
+                },
+            )
+            .await?;
+
+            self.emit(Event::TaskResultCreated(&task_result)).await?;
+        }
+
+        Ok(Some(Status::Done))
+    }
+
+    /// Interpret the code blocks of the last message of the agent which is not a
+    /// self-reflection, as the agent called the code interpreter, answering with the tool message.
+    async fn sfai_code_interpreter(
+        &self,
+        message: &Message,
+        tool_call: &ToolCall,
+        task: &Task,
+    ) -> Result<()> {
+        let code_message = repo::messages::get_last_non_self_reflection_message(
+            self.pool,
+            self.company_id,
+            message.chat_id,
+        )
+        .await?;
+        let content = self
+            .interpret_code(code_message.as_ref().unwrap_or(message), task)
+            .await;
+
+        let output = repo::messages::create(
+            self.pool,
+            self.company_id,
+            CreateParams {
+                content: Some(content),
+                chat_id: message.chat_id,
+                status: messages::Status::Completed,
+                role: Role::Tool,
+                tool_call_id: Some(tool_call.id.clone()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        self.emit(Event::MessageCreated(&output)).await?;
+
+        Ok(())
+    }
+
+    /// Interpret the code blocks the agent marked in its message as the code interpreter policies
+    /// and the user decide, answering with the code interpreter message. Returns `WaitingForUser`
+    /// if it's left for the user to decide.
+    async fn interpret_code_blocks(
+        &self,
+        message: &Message,
+        code_blocks: &[CodeBlock],
+        task: &Task,
+    ) -> Result<Option<Status>> {
+        let content =
+            match tool_call_policies::decide_for_code_blocks(self.pool, message, code_blocks)
+                .await?
+            {
+                Decision::Allow => self.interpret_code(message, task).await,
+                Decision::Ask => {
+                    if message.status != messages::Status::WaitingForToolCall {
+                        self.update_message_status(message, messages::Status::WaitingForToolCall)
+                            .await?;
+                    }
+
+                    return Ok(Some(Status::WaitingForUser));
+                }
+                Decision::Deny => "Code execution denied".to_string(),
+            };
+
+        let output = repo::messages::create(
+            self.pool,
+            self.company_id,
+            CreateParams {
+                content: Some(content),
+                chat_id: message.chat_id,
+                status: messages::Status::Completed,
+                role: Role::CodeInterpreter,
+                ..Default::default()
+            },
+        )
+        .await?;
+        self.emit(Event::MessageCreated(&output)).await?;
+
+        if message.status == messages::Status::WaitingForToolCall {
+            self.update_message_status(message, messages::Status::Completed)
+                .await?;
+        }
+
+        Ok(None)
+    }
+
+    /// Run or save the code blocks of the message, describing the outcome.
+    async fn interpret_code(&self, message: &Message, task: &Task) -> String {
+        match self.run_code_blocks(message, task).await {
+            Ok(content) => content,
+            Err(err) => format!("Failed to interpret code: {err}"),
+        }
+    }
+
+    /// Run or save the code blocks of the message in the task workdir, in the sandbox.
+    async fn run_code_blocks(&self, message: &Message, task: &Task) -> Result<String> {
+        let Some(content) = message.content.as_deref() else {
+            return Ok("No content in the message to interpret".to_string());
+        };
+        let code_blocks = match code_interpreter::parse(content) {
+            Ok(code_blocks) => code_blocks,
+            Err(err) => return Ok(format!("Failed to parse code blocks in the message: {err}")),
+        };
+
+        let workdir = task.workdir(&self.workdir_root.to_path_buf()).await?;
+        fs::create_dir_all(&workdir)
+            .await
+            .with_context(|| format!("Failed to create `{}`", workdir.display()))?;
+
+        let interpreter = Interpreter::new(
+            &self.settings.sandbox,
+            Duration::from_secs(self.settings.tool_calls.timeout_secs),
+        );
+
+        Ok(interpreter.interpret(&workdir, code_blocks).await)
+    }
+
+    async fn update_message_status(
+        &self,
+        message: &Message,
+        status: messages::Status,
+    ) -> Result<()> {
+        repo::messages::update_status(self.pool, self.company_id, message.id, status).await?;
+
+        let mut message = message.clone();
+        message.status = status;
+        self.emit(Event::MessageUpdated(&message)).await?;
+
+        Ok(())
+    }
+
+    async fn send_to_agent(&self, chat: &Chat, task: &Task) -> Result<()> {
+        self.create_completion(chat, task, CreateCompletionParams::default())
+            .await
+    }
+
+    async fn self_reflect(&self, chat: &Chat, task: &Task) -> Result<()> {
+        let content = SelfReflectionMessageTemplate {}
+            .render()
+            .context("Failed to render self-reflection message")?;
+
+        self.create_completion(
+            chat,
+            task,
+            CreateCompletionParams {
+                messages_post: Some(vec![Message {
+                    chat_id: chat.id,
+                    content: Some(content),
+                    role: Role::User,
+                    ..Default::default()
+                }]),
+                abilities: Some(internal_task_abilities()),
+                is_self_reflection: true,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Get a completion of the agent, prepended with the system message and the task.
+    async fn create_completion(
+        &self,
+        chat: &Chat,
+        task: &Task,
+        params: CreateCompletionParams,
+    ) -> Result<()> {
+        let agent = repo::agents::get_for_chat(self.pool, self.company_id, chat.id).await?;
+        let (model, api_key) =
+            conversations::model_for_chat(self.pool, &self.settings.common, chat).await?;
+
+        bridge_common::chats::create_completion(
+            self.pool,
+            self.channel,
+            self.company_id,
+            self.user_id,
+            chat.id,
+            CreateCompletionParams {
+                messages_pre: Some(execution_prelude(
+                    chat.id,
+                    task,
+                    &agent,
+                    params.is_self_reflection,
+                )?),
+                ..params
+            },
+            &model,
+            &api_key,
+            &crate::USER_AGENT,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn emit(&self, event: Event<'_>) -> Result<()> {
+        Ok(self.channel.emit(self.user_id, event).await?)
+    }
+}
+
+/// Tools the agent marks the task as done, failed or waiting for the user with, while reflecting
+/// on its work.
+fn internal_task_abilities() -> Vec<Ability> {
+    vec![
+        Ability::for_fn("Mark current task as done", &json!({ "name": "sfai_done" })),
+        Ability::for_fn(
+            "Mark current task as failed",
+            &json!({ "name": "sfai_fail" }),
+        ),
+        Ability::for_fn(
+            "Wait for additional user input",
+            &json!({ "name": "sfai_wait_for_user" }),
+        ),
+    ]
+}
+
+#[derive(Template)]
+#[template(path = "task_executor/task_message.md", escape = "none")]
+struct TaskMessageTemplate<'a> {
+    task: &'a Task,
+}
+
+#[derive(Template)]
+#[template(path = "task_executor/system_message.md", escape = "none")]
+struct SystemMessageTemplate<'a> {
+    agent: &'a Agent,
+    is_self_reflection: bool,
+}
+
+#[derive(Template)]
+#[template(path = "task_executor/self_reflection_message.md", escape = "none")]
+struct SelfReflectionMessageTemplate {}
+
+/// System message for the agent, followed by the task.
+fn execution_prelude(
+    chat_id: i32,
+    task: &Task,
+    agent: &Agent,
+    is_self_reflection: bool,
+) -> Result<Vec<Message>> {
+    let system_message = SystemMessageTemplate {
+        agent,
+        is_self_reflection,
+    }
+    .render()
+    .context("Failed to render system message")?;
+    let task_message = TaskMessageTemplate { task }
+        .render()
+        .context("Failed to render task message")?;
+
+    Ok(vec![
+        Message {
+            chat_id,
+            role: Role::System,
+            content: Some(system_message),
+            ..Default::default()
+        },
+        Message {
+            chat_id,
+            role: Role::User,
+            content: Some(task_message),
+            ..Default::default()
+        },
+    ])
+}
+
+/// Task along with its subtasks at every level.
+struct TaskTree {
+    root: Task,
+    children: Vec<TaskTree>,
+}
+
+impl TaskTree {
+    /// Tree of the task, out of its subtasks at every level, each level in the order given.
+    fn build(root: Task, tasks: &[Task]) -> Result<Self> {
+        let mut children = Vec::new();
+        for task in tasks {
+            if task.parent_id()? == Some(root.id) {
+                children.push(Self::build(task.clone(), tasks)?);
+            }
+        }
+
+        Ok(Self { root, children })
+    }
+
+    /// First task of the tree to execute: its subtasks go before it, and the tasks in progress or
+    /// done are left out.
+    fn execution_candidate(&self) -> Option<&Task> {
+        if let Some(task) = self.children.iter().find_map(TaskTree::execution_candidate) {
+            return Some(task);
+        }
+
+        match self.root.status {
+            Status::InProgress | Status::Done => None,
+            Status::Draft | Status::ToDo | Status::WaitingForUser | Status::Failed => {
+                Some(&self.root)
+            }
+        }
+    }
+}
@@ -0,0 +1,443 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::Duration,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bridge_common::{
+    channel::{Channel, Emitter, Event},
+    types::tasks::Status,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::spawn;
+use tokio::time::sleep;
+use tracing::{debug, error, info, instrument, trace};
+
+use crate::{
+    errors,
+    events::AppEvent,
+    state::Shared,
+    task_queue,
+    task_traces::Recorder,
+    types::{task_queue::QueuedTask, DbPool, Result},
+};
+
+mod execution;
+
+pub use execution::Execution;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("no root tasks to execute")]
+    NoRootTasks,
+    #[error("chat #{0} is not an execution chat")]
+    NotAnExecutionChat(i32),
+}
+
+/// What an executor worker is up to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status")]
+pub enum WorkerStatus {
+    /// No root tasks to execute.
+    Idle,
+    Running {
+        task_id: i32,
+        agent_id: i32,
+        /// Subtask being executed, `None` until one is picked or if the task has none.
+        subtask_id: Option<i32>,
+        /// Messages written while executing the current (sub)task.
+        step: u32,
+        started_at: DateTime<Utc>,
+    },
+    Errored {
+        /// Root task the step failed for, if it got as far as picking one.
+        task_id: Option<i32>,
+        error: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WorkerState {
+    pub worker: u16,
+    #[serde(flatten)]
+    pub status: WorkerStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueEntry {
+    #[serde(flatten)]
+    pub task: QueuedTask,
+    /// Estimated seconds until a worker picks the task, `None` until a root task has been
+    /// executed to estimate by.
+    pub estimated_wait_secs: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecutorState {
+    pub workers: Vec<WorkerState>,
+    /// Root tasks waiting for execution, in the order they are picked.
+    pub queue: Vec<QueueEntry>,
+}
+
+/// State of the executor workers, shared with the commands.
+#[derive(Default)]
+pub struct Executor {
+    workers: Mutex<Vec<WorkerState>>,
+    /// Time the root tasks of each agent took to execute since the start, and their count.
+    durations: Mutex<HashMap<i32, (TimeDelta, i32)>>,
+}
+
+impl Executor {
+    /// State of the workers along with the queue given, and the estimated wait of its tasks.
+    #[must_use]
+    pub fn state(&self, queue: Vec<QueuedTask>, concurrency: u16) -> ExecutorState {
+        let workers = self
+            .workers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let waits = self.estimate_waits(&workers, &queue, concurrency);
+
+        ExecutorState {
+            workers,
+            queue: queue
+                .into_iter()
+                .zip(waits)
+                .map(|(task, estimated_wait_secs)| QueueEntry {
+                    task,
+                    estimated_wait_secs,
+                })
+                .collect(),
+        }
+    }
+
+    /// Set every worker idle, for as many workers as given.
+    fn reset(&self, concurrency: u16) {
+        *self.workers.lock().unwrap_or_else(PoisonError::into_inner) = (0..concurrency)
+            .map(|worker| WorkerState {
+                worker,
+                status: WorkerStatus::Idle,
+            })
+            .collect();
+    }
+
+    /// Update the status of the worker, returning its state if it changed.
+    fn update(&self, worker: u16, f: impl FnOnce(&mut WorkerStatus)) -> Option<WorkerState> {
+        let mut workers = self.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        while workers.len() <= usize::from(worker) {
+            let worker = u16::try_from(workers.len()).unwrap_or(u16::MAX);
+            workers.push(WorkerState {
+                worker,
+                status: WorkerStatus::Idle,
+            });
+        }
+
+        let state = &mut workers[usize::from(worker)];
+        let previous = state.status.clone();
+        f(&mut state.status);
+
+        (state.status != previous).then(|| state.clone())
+    }
+
+    fn record(&self, agent_id: i32, duration: TimeDelta) {
+        let mut durations = self
+            .durations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (total, count) = durations.entry(agent_id).or_default();
+        *total += duration;
+        *count += 1;
+    }
+
+    /// Simulate the workers picking the queued tasks in order, each taking as long as the root
+    /// tasks of its agent took on average, or the ones of every agent if it has none.
+    fn estimate_waits(
+        &self,
+        workers: &[WorkerState],
+        queue: &[QueuedTask],
+        concurrency: u16,
+    ) -> Vec<Option<i64>> {
+        let durations = self
+            .durations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let (total, count) = durations
+            .values()
+            .fold((TimeDelta::zero(), 0), |(total, count), (duration, n)| {
+                (total + *duration, count + n)
+            });
+        if count == 0 || concurrency == 0 {
+            return vec![None; queue.len()];
+        }
+        let average = |agent_id: i32| {
+            durations
+                .get(&agent_id)
+                .map_or(total / count, |(total, count)| *total / *count)
+        };
+
+        let now = Utc::now();
+        let mut free_in = (0..concurrency)
+            .map(|worker| {
+                match workers.iter().find(|state| state.worker == worker) {
+                    Some(WorkerState {
+                        status:
+                            WorkerStatus::Running {
+                                agent_id,
+                                started_at,
+                                ..
+                            },
+                        ..
+                    }) => average(*agent_id) - (now - *started_at),
+                    _ => TimeDelta::zero(),
+                }
+                .max(TimeDelta::zero())
+            })
+            .collect::<Vec<_>>();
+
+        queue
+            .iter()
+            .map(|task| {
+                let (worker, wait) = free_in
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .min_by_key(|(_, wait)| *wait)?;
+                free_in[worker] = wait + average(task.agent_id);
+
+                Some(wait.num_seconds())
+            })
+            .collect()
+    }
+}
+
+// TODO: implement graceful shutdown
+/// Start the task execution loop. Only the tasks of the active workspace are executed, with its
+/// settings as of the moment a root task is picked.
+#[instrument(skip_all)]
+pub async fn start_loop<S: Shared>(state: &S) {
+    let execution_concurrency = state
+        .settings()
+        .read()
+        .await
+        .common
+        .tasks
+        .execution_concurrency;
+
+    info!(
+        "Starting task execution loop with concurrency = {}",
+        execution_concurrency
+    );
+
+    state.executor().reset(execution_concurrency);
+
+    for i in 0..execution_concurrency {
+        let state = state.clone();
+
+        spawn(async move {
+            loop {
+                if !execute_step(&state, i).await {
+                    trace!("No root tasks to execute, waiting...");
+
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+        });
+
+        debug!("-- Thread #{} started", i);
+    }
+}
+
+/// Execute the root task which is next in line, if there is any. Returns whether there was
+/// something to execute.
+pub async fn execute_next<S: Shared>(state: &S) -> bool {
+    execute_step(state, 0).await
+}
+
+/// Put the tasks which entered the queue in their place, for the next one picked to be the one
+/// next in line.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn schedule(pool: &DbPool, cid: i32) -> Result<Vec<QueuedTask>> {
+    let mut tx = pool
+        .begin()
+        .await
+        .with_context(|| "Failed to begin transaction")?;
+
+    let queue = task_queue::schedule(&mut tx, cid).await?;
+
+    tx.commit()
+        .await
+        .with_context(|| "Failed to commit transaction")?;
+
+    Ok(queue)
+}
+
+/// Execute the root task which is next in line as the given worker, returning whether there was
+/// one to execute.
+async fn execute_step<S: Shared>(state: &S, worker: u16) -> bool {
+    // Switching workspaces holds the settings lock, so both belong to the same one
+    let ((company_id, user_id), settings) = {
+        let settings = state.settings().read().await;
+        (state.workspace().ids(), settings.clone())
+    };
+    if let Err(err) = schedule(state.pool(), company_id).await {
+        error!("Failed to schedule tasks: {:?}", err);
+    }
+
+    let root_task = Arc::new(OnceLock::new());
+    let recorder = Arc::new(Recorder::new(
+        state.pool().clone(),
+        company_id,
+        settings.common.default_model.clone(),
+    ));
+    let channel: Channel = Box::new(StepChannel {
+        state: state.clone(),
+        company_id,
+        user_id,
+        worker,
+        root_task: root_task.clone(),
+        recorder: recorder.clone(),
+    });
+    let execution = Execution {
+        pool: state.pool(),
+        channel: &channel,
+        settings: &settings,
+        company_id,
+        user_id,
+        workdir_root: state.app_local_data_dir(),
+    };
+
+    let result = execution.execute_root_task().await;
+    let root_task = root_task.get().copied();
+
+    let error = result.as_ref().err().map(|err| format!("{err:#}"));
+    if let Err(err) = recorder.finish(error.as_deref()).await {
+        error!("Failed to record task step: {:?}", err);
+    }
+
+    let status = match result {
+        Ok(()) => {
+            if let Some((_, agent_id, started_at)) = root_task {
+                state.executor().record(agent_id, Utc::now() - started_at);
+            }
+            WorkerStatus::Idle
+        }
+        Err(errors::Error::TaskExecutor(Error::NoRootTasks)) => WorkerStatus::Idle,
+        Err(err) => {
+            error!("Failed to execute task: {:?}", err);
+            WorkerStatus::Errored {
+                task_id: root_task.map(|(task_id, _, _)| task_id),
+                error: format!("{err:#}"),
+            }
+        }
+    };
+    set_status(state, company_id, worker, |current| *current = status).await;
+
+    root_task.is_some()
+}
+
+/// Update the status of the worker, emitting it if it changed. Workers go on from task to task, so
+/// only the changes are emitted.
+async fn set_status<S: Shared>(
+    state: &S,
+    company_id: i32,
+    worker: u16,
+    f: impl FnOnce(&mut WorkerStatus),
+) {
+    let Some(worker_state) = state.executor().update(worker, f) else {
+        return;
+    };
+
+    let event = AppEvent::ExecutorUpdated(&worker_state);
+    if let Err(err) = state.events().emit_app(company_id, event).await {
+        error!("Failed to emit executor status: {:?}", err);
+    }
+}
+
+/// Passes the events of a step on, following its progress: a step starts with the update of its
+/// root task, moved in progress, then its subtasks are moved in progress one by one, and each
+/// message created is a step of the execution. The steps are recorded for the task trace.
+struct StepChannel<S> {
+    state: S,
+    company_id: i32,
+    /// Local user of the workspace, who the events are emitted for in place of the one
+    /// `bridge_common` has no way to know.
+    user_id: i32,
+    worker: u16,
+    /// Id and agent of the root task, and when it was picked.
+    root_task: Arc<OnceLock<(i32, i32, DateTime<Utc>)>>,
+    recorder: Arc<Recorder>,
+}
+
+#[async_trait]
+impl<S: Shared> Emitter for StepChannel<S> {
+    async fn emit<'a>(&self, _user_id: i32, event: Event<'a>) -> bridge_common::types::Result<()> {
+        match &event {
+            Event::TaskUpdated(task) if task.ancestry.is_none() => {
+                let started_at = Utc::now();
+                if self
+                    .root_task
+                    .set((task.id, task.agent_id, started_at))
+                    .is_ok()
+                {
+                    let (task_id, agent_id) = (task.id, task.agent_id);
+                    self.set_status(move |status| {
+                        *status = WorkerStatus::Running {
+                            task_id,
+                            agent_id,
+                            subtask_id: None,
+                            step: 0,
+                            started_at,
+                        };
+                    })
+                    .await;
+                }
+            }
+            Event::TaskUpdated(task) if task.status == Status::InProgress => {
+                let id = task.id;
+                self.set_status(move |status| {
+                    if let WorkerStatus::Running {
+                        subtask_id, step, ..
+                    } = status
+                    {
+                        if *subtask_id != Some(id) {
+                            *subtask_id = Some(id);
+                            *step = 0;
+                        }
+                    }
+                })
+                .await;
+            }
+            Event::MessageCreated(_) => {
+                self.set_status(|status| {
+                    if let WorkerStatus::Running { step, .. } = status {
+                        *step += 1;
+                    }
+                })
+                .await;
+            }
+            _ => {}
+        }
+
+        if let Err(err) = self.recorder.record(&event).await {
+            error!("Failed to record task step: {:?}", err);
+        }
+
+        self.state.channel().emit(self.user_id, event).await
+    }
+}
+
+impl<S: Shared> StepChannel<S> {
+    async fn set_status(&self, f: impl FnOnce(&mut WorkerStatus)) {
+        set_status(&self.state, self.company_id, self.worker, f).await;
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Manual editing of the subtask tree of a task, as planned or built by hand.
+//!
+//! Subtasks are added, removed, reordered and moved between parents within the tree of their root
+//! task, which must not be executing meanwhile. Moving a subtask rewrites the `ancestry` and
+//! `ancestry_level` of it and its own subtasks. Siblings are executed in the order they were
+//! created, until they're put in order by hand, which gives each of them its position.
+
+use std::collections::HashMap;
+
+use bridge_common::{
+    repo::{self, tasks::CreateParams},
+    types::tasks::{Status, Task},
+};
+use sqlx::{Executor, Postgres, Transaction};
+
+use crate::types::Result;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("subtask title cannot be empty")]
+    EmptyTitle,
+    #[error("task #{0} is a root task, not a subtask")]
+    NotSubtask(i32),
+    #[error("task cannot be moved under itself or its own subtask")]
+    Cycle,
+    #[error("subtask can only be moved within the tree of its root task")]
+    OtherTree,
+    #[error("task tree is being executed")]
+    InProgress,
+    #[error("subtasks to reorder must be every subtask of the parent, once each")]
+    OrderMismatch,
+    #[error("position {position} is out of range, the parent has {count} subtasks")]
+    InvalidPosition { position: usize, count: usize },
+}
+
+pub struct NewSubtask<'a> {
+    pub parent_id: i32,
+    /// Agent of the subtask, the one of its parent if not set.
+    pub agent_id: Option<i32>,
+    pub title: &'a str,
+    pub summary: &'a str,
+    /// Position among the siblings, last if not set.
+    pub position: Option<usize>,
+}
+
+/// Add subtask to the task, returning it along with the siblings it moved.
+///
+/// # Errors
+///
+/// Returns error if the parent does not exist, its tree is being executed, the title is empty, or
+/// the position is out of range.
+pub async fn add(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    subtask: NewSubtask<'_>,
+) -> Result<(Task, Vec<Task>)> {
+    if subtask.title.trim().is_empty() {
+        return Err(Error::EmptyTitle.into());
+    }
+
+    let parent = repo::tasks::get(&mut **tx, cid, subtask.parent_id).await?;
+    let before = editable_tree(tx, cid, root_id(&parent)?).await?;
+
+    let task = repo::tasks::create(
+        &mut **tx,
+        cid,
+        CreateParams {
+            agent_id: subtask.agent_id.unwrap_or(parent.agent_id),
+            title: subtask.title,
+            summary: Some(subtask.summary),
+            ancestry: Some(&parent.children_ancestry()),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let mut siblings = direct_children(tx, cid, &parent).await?;
+    let added = siblings.pop().filter(|sibling| sibling.id == task.id);
+    if let (Some(added), Some(position)) = (added, subtask.position) {
+        insert_at(&mut siblings, added, position)?;
+        reposition(tx, cid, &siblings).await?;
+    }
+
+    let after = tree(tx, cid, root_id(&parent)?).await?;
+    let task = after
+        .iter()
+        .find(|after| after.id == task.id)
+        .cloned()
+        .unwrap_or(task);
+
+    Ok((task, changed(&before, after)))
+}
+
+/// Remove subtask, along with its own subtasks.
+///
+/// # Errors
+///
+/// Returns error if the task does not exist, is a root task, or its tree is being executed.
+pub async fn remove(tx: &mut Transaction<'_, Postgres>, cid: i32, id: i32) -> Result<()> {
+    let task = repo::tasks::get(&mut **tx, cid, id).await?;
+    if task.ancestry.is_none() {
+        return Err(Error::NotSubtask(id).into());
+    }
+    editable_tree(tx, cid, root_id(&task)?).await?;
+
+    crate::repo::tasks::delete_subtree(&mut **tx, cid, id, &task.children_ancestry()).await
+}
+
+/// Put the subtasks of the task in the given order, returning the ones moved.
+///
+/// # Errors
+///
+/// Returns error if the parent does not exist, its tree is being executed, or the ids given are
+/// not every subtask of the parent once each.
+pub async fn reorder(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    parent_id: i32,
+    ids: &[i32],
+) -> Result<Vec<Task>> {
+    let parent = repo::tasks::get(&mut **tx, cid, parent_id).await?;
+    let before = editable_tree(tx, cid, root_id(&parent)?).await?;
+
+    let mut siblings: HashMap<i32, Task> =
+        repo::tasks::list_direct_children(&mut **tx, cid, &parent)
+            .await?
+            .into_iter()
+            .map(|sibling| (sibling.id, sibling))
+            .collect();
+    if ids.len() != siblings.len() {
+        return Err(Error::OrderMismatch.into());
+    }
+    let ordered = ids
+        .iter()
+        .map(|id| siblings.remove(id).ok_or(Error::OrderMismatch))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    reposition(tx, cid, &ordered).await?;
+
+    let after = tree(tx, cid, root_id(&parent)?).await?;
+    Ok(changed(&before, after))
+}
+
+/// Move subtask under another parent within the same tree, or to another position under the same
+/// one, returning it along with every other task moved.
+///
+/// # Errors
+///
+/// Returns error if either task does not exist, the task is a root task, the parent is in another
+/// tree or under the task itself, the tree is being executed, or the position is out of range.
+pub async fn move_to(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    id: i32,
+    parent_id: i32,
+    position: Option<usize>,
+) -> Result<Vec<Task>> {
+    let task = repo::tasks::get(&mut **tx, cid, id).await?;
+    let parent = repo::tasks::get(&mut **tx, cid, parent_id).await?;
+    if task.ancestry.is_none() {
+        return Err(Error::NotSubtask(id).into());
+    }
+    if root_id(&task)? != root_id(&parent)? {
+        return Err(Error::OtherTree.into());
+    }
+    if parent.id == task.id || parent.parent_ids()?.unwrap_or_default().contains(&task.id) {
+        return Err(Error::Cycle.into());
+    }
+    let before = editable_tree(tx, cid, root_id(&task)?).await?;
+
+    let ancestry = parent.children_ancestry();
+    if task.ancestry.as_deref() != Some(&ancestry) {
+        let moved = format!("{ancestry}/{}", task.id);
+        let level_delta = parent.ancestry_level + 1 - task.ancestry_level;
+
+        crate::repo::tasks::update_ancestry(&mut **tx, cid, task.id, &ancestry).await?;
+        crate::repo::tasks::replace_ancestry(
+            &mut **tx,
+            cid,
+            &task.children_ancestry(),
+            &moved,
+            level_delta,
+        )
+        .await?;
+    }
+
+    let mut siblings = direct_children(tx, cid, &parent).await?;
+    if let Some(index) = siblings.iter().position(|sibling| sibling.id == task.id) {
+        let moved = siblings.remove(index);
+        let position = position.unwrap_or(siblings.len());
+        insert_at(&mut siblings, moved, position)?;
+        reposition(tx, cid, &siblings).await?;
+    }
+
+    let after = tree(tx, cid, root_id(&task)?).await?;
+    Ok(changed(&before, after))
+}
+
+/// Put the tasks in the order of their siblings: the ones put in order by hand first, then the
+/// others in the order they were created.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn sort<'a, E>(executor: E, cid: i32, tasks: &mut [Task]) -> Result<()>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let ids = tasks.iter().map(|task| task.id).collect::<Vec<_>>();
+    let positions = crate::repo::tasks::list_positions(executor, cid, &ids).await?;
+
+    tasks.sort_by_key(|task| {
+        let position = positions.get(&task.id);
+        (
+            position.is_none(),
+            position.copied(),
+            task.created_at,
+            task.id,
+        )
+    });
+
+    Ok(())
+}
+
+/// Subtasks of the task, in their order.
+async fn direct_children(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    parent: &Task,
+) -> Result<Vec<Task>> {
+    let mut children = repo::tasks::list_direct_children(&mut **tx, cid, parent).await?;
+    sort(&mut **tx, cid, &mut children).await?;
+
+    Ok(children)
+}
+
+/// Id of the root of the tree the task belongs to.
+fn root_id(task: &Task) -> Result<i32> {
+    Ok(task
+        .parent_ids()?
+        .and_then(|ids| ids.first().copied())
+        .unwrap_or(task.id))
+}
+
+/// Every task of the tree, the root first and the siblings in their order.
+async fn tree(tx: &mut Transaction<'_, Postgres>, cid: i32, root_id: i32) -> Result<Vec<Task>> {
+    let root = repo::tasks::get(&mut **tx, cid, root_id).await?;
+    let mut children = repo::tasks::list_all_children(&mut **tx, cid, &root.children_ancestry())
+        .await?
+        .into_iter()
+        .filter(|child| child.company_id == cid)
+        .collect::<Vec<_>>();
+    sort(&mut **tx, cid, &mut children).await?;
+
+    Ok(std::iter::once(root).chain(children).collect())
+}
+
+/// Every task of the tree, after checking that none of them is being executed.
+async fn editable_tree(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    root_id: i32,
+) -> Result<Vec<Task>> {
+    let tree = tree(tx, cid, root_id).await?;
+    if tree.iter().any(|task| task.status == Status::InProgress) {
+        return Err(Error::InProgress.into());
+    }
+
+    Ok(tree)
+}
+
+fn insert_at(siblings: &mut Vec<Task>, task: Task, position: usize) -> Result<()> {
+    if position > siblings.len() {
+        return Err(Error::InvalidPosition {
+            position,
+            count: siblings.len(),
+        }
+        .into());
+    }
+
+    siblings.insert(position, task);
+    Ok(())
+}
+
+/// Give the siblings their positions, for them to be executed in the given order.
+async fn reposition(tx: &mut Transaction<'_, Postgres>, cid: i32, ordered: &[Task]) -> Result<()> {
+    let ids = ordered.iter().map(|task| task.id).collect::<Vec<_>>();
+
+    crate::repo::tasks::update_positions(&mut **tx, cid, &ids).await
+}
+
+/// Tasks which existed before, but have been moved since, under another parent or among their
+/// siblings. Both trees are in the order of the siblings.
+fn changed(before: &[Task], after: Vec<Task>) -> Vec<Task> {
+    let before_places = places(before);
+    let after_places = places(&after);
+
+    after
+        .into_iter()
+        .filter(|task| {
+            before_places
+                .get(&task.id)
+                .is_some_and(|place| Some(place) != after_places.get(&task.id))
+        })
+        .collect()
+}
+
+/// Parent and index among the siblings of every task of the tree.
+fn places(tree: &[Task]) -> HashMap<i32, (Option<String>, usize)> {
+    let mut counts: HashMap<Option<&str>, usize> = HashMap::new();
+
+    tree.iter()
+        .map(|task| {
+            let count = counts.entry(task.ancestry.as_deref()).or_default();
+            let place = (task.ancestry.clone(), *count);
+            *count += 1;
+
+            (task.id, place)
+        })
+        .collect()
+}
@@ -0,0 +1,178 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Order of the root tasks waiting for execution.
+//!
+//! The queue is kept by the app, in the priority and queue time of the tasks. Tasks of higher
+//! priority come first. A task entering the queue is given a fair place among the tasks of its
+//! priority: the n-th queued task of an agent goes after the n-th queued task of every other agent,
+//! so an agent with many tasks queued doesn't hold the others up. The place stays until the user
+//! moves the task or changes its priority.
+//!
+//! A worker claims the task next in line by locking it, skipping the tasks other workers are
+//! claiming, and moves it in progress in the same transaction.
+
+use bridge_common::{repo, types::tasks::Task};
+use chrono::{Duration, Utc};
+use sqlx::{Postgres, Transaction};
+
+use crate::types::{task_queue::QueuedTask, Result};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("task #{0} is a subtask, only root tasks are queued")]
+    NotRootTask(i32),
+    #[error("tasks to reorder must be every queued task, once each")]
+    OrderMismatch,
+    #[error(
+        "tasks of higher priority must come first, change the priority to move a task past them"
+    )]
+    PriorityOrder,
+}
+
+/// Give the tasks which entered the queue since the last time their place, returning the queue in
+/// execution order.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn schedule(tx: &mut Transaction<'_, Postgres>, cid: i32) -> Result<Vec<QueuedTask>> {
+    crate::repo::tasks::clear_stale_queued_at(&mut **tx, cid).await?;
+
+    let (mut queue, entered): (Vec<_>, Vec<_>) = crate::repo::tasks::list_queued(&mut **tx, cid)
+        .await?
+        .into_iter()
+        .partition(|task| task.queued_at.is_some());
+
+    if entered.is_empty() {
+        return Ok(queue);
+    }
+
+    for task in entered {
+        let position = fair_position(&queue, &task);
+        queue.insert(position, task);
+    }
+
+    reposition(tx, cid, &mut queue).await?;
+    Ok(queue)
+}
+
+/// Claim the root task next in line, moving it in progress. The tasks other workers are claiming
+/// at the same time are skipped, so a task is only ever claimed by one of them.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn pick(tx: &mut Transaction<'_, Postgres>, cid: i32) -> Result<Option<Task>> {
+    let Some(id) = crate::repo::tasks::lock_next_queued(&mut **tx, cid).await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(repo::tasks::start_progress(&mut **tx, cid, id).await?))
+}
+
+/// Put the queue in the given order, returning it.
+///
+/// # Errors
+///
+/// Returns error if the ids given are not every queued task once each, or they put a task before
+/// the ones of higher priority.
+pub async fn reorder(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    ids: &[i32],
+) -> Result<Vec<QueuedTask>> {
+    let mut queue = schedule(tx, cid).await?;
+    if ids.len() != queue.len() {
+        return Err(Error::OrderMismatch.into());
+    }
+
+    let mut ordered = ids
+        .iter()
+        .map(|id| {
+            queue
+                .iter()
+                .position(|task| task.id == *id)
+                .map(|index| queue.swap_remove(index))
+                .ok_or(Error::OrderMismatch)
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    if ordered
+        .windows(2)
+        .any(|pair| pair[0].priority < pair[1].priority)
+    {
+        return Err(Error::PriorityOrder.into());
+    }
+
+    reposition(tx, cid, &mut ordered).await?;
+    Ok(ordered)
+}
+
+/// Set the priority of the root task, giving it a new place if it's queued. Returns the queue.
+///
+/// # Errors
+///
+/// Returns error if the task does not exist or is a subtask.
+pub async fn set_priority(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    id: i32,
+    priority: i32,
+) -> Result<Vec<QueuedTask>> {
+    let task = repo::tasks::get(&mut **tx, cid, id).await?;
+    if task.ancestry.is_some() {
+        return Err(Error::NotRootTask(id).into());
+    }
+
+    crate::repo::tasks::update_priority(&mut **tx, cid, id, priority).await?;
+    crate::repo::tasks::update_queued_at(&mut **tx, cid, id, None).await?;
+
+    schedule(tx, cid).await
+}
+
+/// Place of the task entering the queue: after the tasks of higher priority, and after the ones
+/// of its priority in the same round as the task or an earlier one. The round of a task is the
+/// number of tasks of its agent and priority ahead of it.
+fn fair_position(queue: &[QueuedTask], task: &QueuedTask) -> usize {
+    let round = |index: usize, task: &QueuedTask| {
+        queue[..index]
+            .iter()
+            .filter(|ahead| ahead.priority == task.priority && ahead.agent_id == task.agent_id)
+            .count()
+    };
+    let task_round = round(queue.len(), task);
+
+    queue
+        .iter()
+        .enumerate()
+        .position(|(index, queued)| {
+            queued.priority < task.priority
+                || (queued.priority == task.priority && round(index, queued) > task_round)
+        })
+        .unwrap_or(queue.len())
+}
+
+/// Rewrite the queue time of the tasks for them to be executed in the given order, starting from
+/// the earliest of them, or from now if none of them has its place yet.
+async fn reposition(
+    tx: &mut Transaction<'_, Postgres>,
+    cid: i32,
+    ordered: &mut [QueuedTask],
+) -> Result<()> {
+    let start = ordered
+        .iter()
+        .filter_map(|task| task.queued_at)
+        .min()
+        .unwrap_or_else(Utc::now);
+
+    let mut queued_at = start;
+    for task in ordered {
+        if task.queued_at != Some(queued_at) {
+            crate::repo::tasks::update_queued_at(&mut **tx, cid, task.id, Some(queued_at)).await?;
+            task.queued_at = Some(queued_at);
+        }
+        queued_at += Duration::milliseconds(1);
+    }
+
+    Ok(())
+}
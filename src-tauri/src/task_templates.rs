@@ -0,0 +1,178 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Task templates: title, summary and subtasks with `{{name}}` placeholders, filled with the
+//! arguments given for the typed parameters of the template when it's instantiated.
+
+use std::collections::{BTreeMap, HashSet};
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde_json::{Map, Value};
+
+use crate::types::{
+    task_templates::{Parameter, ParameterKind, SubtaskTemplate},
+    Result,
+};
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{\{\s*(?P<name>[A-Za-z_]\w*)\s*\}\}")
+        .expect("Failed to compile placeholder regex");
+    static ref PARAMETER_NAME: Regex =
+        Regex::new(r"^[A-Za-z_]\w*$").expect("Failed to compile parameter name regex");
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("template name cannot be empty")]
+    EmptyName,
+    #[error("template title cannot be empty")]
+    EmptyTitle,
+    #[error("subtask title cannot be empty")]
+    EmptySubtaskTitle,
+    #[error("invalid parameter name `{0}`, must be letters, digits and underscores")]
+    InvalidParameterName(String),
+    #[error("parameter `{0}` is declared more than once")]
+    DuplicateParameter(String),
+    #[error("default of parameter `{0}` doesn't match its kind")]
+    InvalidDefault(String),
+    #[error("placeholder `{0}` has no parameter declared")]
+    UnknownPlaceholder(String),
+    #[error("no agent is given, and the template has none")]
+    NoAgent,
+    #[error("argument `{0}` is required")]
+    MissingArgument(String),
+    #[error("argument `{name}` must be of kind `{kind:?}`")]
+    InvalidArgument { name: String, kind: ParameterKind },
+    #[error("unknown argument `{0}`")]
+    UnknownArgument(String),
+}
+
+/// Check that the template makes sense before saving it.
+///
+/// # Errors
+///
+/// Returns error if the name or a title is empty, a parameter is invalid, or a placeholder has no
+/// parameter declared.
+pub fn validate(
+    name: &str,
+    title: &str,
+    summary: &str,
+    parameters: &[Parameter],
+    subtasks: &[SubtaskTemplate],
+) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::EmptyName.into());
+    }
+    if title.trim().is_empty() {
+        return Err(Error::EmptyTitle.into());
+    }
+
+    let mut names = HashSet::new();
+    for parameter in parameters {
+        if !PARAMETER_NAME.is_match(&parameter.name) {
+            return Err(Error::InvalidParameterName(parameter.name.clone()).into());
+        }
+        if !names.insert(parameter.name.as_str()) {
+            return Err(Error::DuplicateParameter(parameter.name.clone()).into());
+        }
+        if let Some(default) = &parameter.default {
+            if !is_of_kind(default, parameter.kind) {
+                return Err(Error::InvalidDefault(parameter.name.clone()).into());
+            }
+        }
+    }
+
+    check_placeholders(title, &names)?;
+    check_placeholders(summary, &names)?;
+    check_subtasks(subtasks, &names)
+}
+
+/// Values to put in place of the placeholders, from the arguments given or the defaults.
+///
+/// # Errors
+///
+/// Returns error if an argument is missing, unknown or of the wrong kind.
+pub fn arguments(
+    parameters: &[Parameter],
+    given: &Map<String, Value>,
+) -> Result<BTreeMap<String, String>> {
+    if let Some(name) = given
+        .keys()
+        .find(|name| !parameters.iter().any(|parameter| &parameter.name == *name))
+    {
+        return Err(Error::UnknownArgument(name.clone()).into());
+    }
+
+    let mut values = BTreeMap::new();
+    for parameter in parameters {
+        let value = match given.get(&parameter.name) {
+            Some(Value::Null) | None => parameter.default.as_ref(),
+            Some(value) => Some(value),
+        };
+        let Some(value) = value else {
+            return Err(Error::MissingArgument(parameter.name.clone()).into());
+        };
+        if !is_of_kind(value, parameter.kind) {
+            return Err(Error::InvalidArgument {
+                name: parameter.name.clone(),
+                kind: parameter.kind,
+            }
+            .into());
+        }
+
+        let value = match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        values.insert(parameter.name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+/// Text with its placeholders replaced by the values. Placeholders without a value are kept.
+#[must_use]
+pub fn render(text: &str, values: &BTreeMap<String, String>) -> String {
+    PLACEHOLDER
+        .replace_all(text, |captures: &Captures| {
+            match values.get(&captures["name"]) {
+                Some(value) => value.clone(),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn is_of_kind(value: &Value, kind: ParameterKind) -> bool {
+    matches!(
+        (kind, value),
+        (ParameterKind::String, Value::String(_))
+            | (ParameterKind::Number, Value::Number(_))
+            | (ParameterKind::Boolean, Value::Bool(_))
+    )
+}
+
+fn check_placeholders(text: &str, names: &HashSet<&str>) -> Result<()> {
+    match PLACEHOLDER
+        .captures_iter(text)
+        .find(|captures| !names.contains(&captures["name"]))
+    {
+        Some(captures) => Err(Error::UnknownPlaceholder(captures["name"].to_string()).into()),
+        None => Ok(()),
+    }
+}
+
+fn check_subtasks(subtasks: &[SubtaskTemplate], names: &HashSet<&str>) -> Result<()> {
+    for subtask in subtasks {
+        if subtask.title.trim().is_empty() {
+            return Err(Error::EmptySubtaskTitle.into());
+        }
+
+        check_placeholders(&subtask.title, names)?;
+        check_placeholders(&subtask.summary, names)?;
+        check_subtasks(&subtask.children, names)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Trace of task execution, step by step.
+//!
+//! The task executor gets the completions of a task in its execution chat from `bridge_common`, so
+//! the steps are recorded from the events emitted along the way. A step starts with the assistant message of a completion, gets its
+//! token usage, tool calls and verdict once the message is written, and finishes once the message
+//! is completed or failed, or the execution of the task ends. An error the execution fails with is
+//! put on the last step.
+
+use bridge_common::{
+    channel::Event,
+    repo,
+    types::{
+        messages::{Message, Role, Status as MessageStatus},
+        tasks::{Status, Task},
+    },
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    repo::task_steps::{CreateParams, UpdateCompletionParams},
+    types::{
+        task_steps::{StepToolCall, TaskStep, Verdict},
+        DbPool, Result,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaskTrace {
+    /// Task along with its subtasks at every level, the task first.
+    pub tasks: Vec<Task>,
+    /// Steps of every task, in the order they were started.
+    pub steps: Vec<TaskStep>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+/// Records the steps of the execution of a root task and its subtasks.
+pub struct Recorder {
+    pool: DbPool,
+    company_id: i32,
+    /// Model the executor completes with.
+    model: String,
+    progress: Mutex<Progress>,
+}
+
+#[derive(Default)]
+struct Progress {
+    /// Task being executed.
+    task_id: Option<i32>,
+    /// Last step started, along with its message.
+    step: Option<(i64, i64)>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new(pool: DbPool, company_id: i32, model: String) -> Self {
+        Self {
+            pool,
+            company_id,
+            model,
+            progress: Mutex::default(),
+        }
+    }
+
+    /// Record the progress the event tells of.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if there was a problem while accessing database.
+    pub async fn record(&self, event: &Event<'_>) -> Result<()> {
+        let mut progress = self.progress.lock().await;
+
+        match event {
+            Event::TaskUpdated(task)
+                if task.status == Status::InProgress
+                    && (task.ancestry.is_some() || progress.task_id.is_none()) =>
+            {
+                progress.task_id = Some(task.id);
+            }
+            Event::MessageCreated(message) if message.role == Role::Assistant => {
+                let Some(task_id) = progress.task_id else {
+                    return Ok(());
+                };
+                if let Some((step_id, _)) = progress.step {
+                    crate::repo::task_steps::finish(&self.pool, self.company_id, step_id, None)
+                        .await?;
+                }
+
+                let step = crate::repo::task_steps::create(
+                    &self.pool,
+                    self.company_id,
+                    CreateParams {
+                        task_id,
+                        message_id: message.id,
+                        is_self_reflection: message.is_self_reflection,
+                        model: &self.model,
+                        started_at: message.created_at,
+                    },
+                )
+                .await?;
+                progress.step = Some((step.id, message.id));
+            }
+            Event::MessageUpdated(message) if message.status != MessageStatus::Writing => {
+                let Some((step_id, message_id)) = progress.step else {
+                    return Ok(());
+                };
+                if message.id != message_id {
+                    return Ok(());
+                }
+
+                self.complete(step_id, message).await?;
+                if matches!(
+                    message.status,
+                    MessageStatus::Completed | MessageStatus::Failed
+                ) {
+                    crate::repo::task_steps::finish(&self.pool, self.company_id, step_id, None)
+                        .await?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Finish the last step, with the error the execution failed with if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if there was a problem while accessing database.
+    pub async fn finish(&self, error: Option<&str>) -> Result<()> {
+        let progress = self.progress.lock().await;
+        if let Some((step_id, _)) = progress.step {
+            crate::repo::task_steps::finish(&self.pool, self.company_id, step_id, error).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn complete(&self, step_id: i64, message: &Message) -> Result<()> {
+        let tool_calls = message
+            .tool_calls()
+            .iter()
+            .map(|tool_call| StepToolCall {
+                id: tool_call.id.clone(),
+                name: tool_call.function.name.clone(),
+                arguments: tool_call.function.arguments.clone(),
+            })
+            .collect::<Vec<_>>();
+        let verdict = tool_calls
+            .iter()
+            .find_map(|tool_call| Verdict::for_tool(&tool_call.name))
+            .unwrap_or_default();
+
+        crate::repo::task_steps::update_completion(
+            &self.pool,
+            self.company_id,
+            UpdateCompletionParams {
+                id: step_id,
+                prompt_tokens: message.prompt_tokens,
+                completion_tokens: message.completion_tokens,
+                tool_calls,
+                verdict,
+            },
+        )
+        .await
+    }
+}
+
+/// Trace of the execution of the task and its subtasks at every level.
+///
+/// # Errors
+///
+/// Returns error if the task does not exist, or there was a problem while accessing database.
+pub async fn trace(pool: &DbPool, cid: i32, id: i32) -> Result<TaskTrace> {
+    let task = repo::tasks::get(pool, cid, id).await?;
+    let children = repo::tasks::list_all_children(pool, cid, &task.children_ancestry())
+        .await?
+        .into_iter()
+        .filter(|child| child.company_id == cid);
+    let tasks = std::iter::once(task).chain(children).collect::<Vec<_>>();
+
+    let ids = tasks.iter().map(|task| task.id).collect::<Vec<_>>();
+    let steps = crate::repo::task_steps::list_for_tasks(pool, cid, &ids).await?;
+
+    let prompt_tokens = steps
+        .iter()
+        .filter_map(|step| step.prompt_tokens)
+        .map(i64::from)
+        .sum();
+    let completion_tokens = steps
+        .iter()
+        .filter_map(|step| step.completion_tokens)
+        .map(i64::from)
+        .sum();
+
+    Ok(TaskTrace {
+        tasks,
+        steps,
+        prompt_tokens,
+        completion_tokens,
+    })
+}
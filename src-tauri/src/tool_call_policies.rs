@@ -0,0 +1,340 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auto-approval of tool calls.
+//!
+//! A tool call is decided by the most specific policy applicable to it: a policy for the exact
+//! tool beats a policy for any tool, and a policy for the agent beats a policy for every agent.
+//! Tool calls without an applicable policy are left for the user to decide, except for the code
+//! interpreter, which runs on its own unless a policy says otherwise.
+//!
+//! The code interpreter of the task executor is subject to its policies both when the agent calls
+//! it and when it marks the code blocks of its message to be executed.
+
+use bridge_common::{clients::openai::ToolCall, types::messages::Message};
+use regex::Regex;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::{
+    code_interpreter::{self, CodeBlock},
+    repo::{self, tool_call_policies::CreateDecisionParams},
+    types::{
+        abilities::Ability,
+        tool_call_policies::{Action, ArgumentRule, Decision, Operator, Target, ToolCallPolicy},
+        DbPool, Result,
+    },
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("ability must be set if, and only if, the policy target is `Ability`")]
+    AbilityMismatch,
+    #[error("`AllowIfArgumentsMatch` policy must have at least one rule")]
+    NoRules,
+    #[error("invalid rule for argument `{argument}`: {reason}")]
+    InvalidRule { argument: String, reason: String },
+}
+
+/// Decision on a single tool call, along with the policy it was made by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub decision: Decision,
+    pub policy_id: Option<i32>,
+    pub reason: String,
+}
+
+/// Check that the policy makes sense before saving it.
+///
+/// # Errors
+///
+/// Returns error if the ability doesn't match the target, or if the rules are missing or invalid.
+pub fn validate(
+    target: Target,
+    ability_id: Option<i32>,
+    action: Action,
+    rules: &[ArgumentRule],
+) -> Result<()> {
+    if (target == Target::Ability) != ability_id.is_some() {
+        return Err(Error::AbilityMismatch.into());
+    }
+
+    if action == Action::AllowIfArgumentsMatch && rules.is_empty() {
+        return Err(Error::NoRules.into());
+    }
+
+    for rule in rules {
+        let invalid = |reason: &str| Error::InvalidRule {
+            argument: rule.argument.clone(),
+            reason: reason.to_string(),
+        };
+
+        match (rule.operator, &rule.value) {
+            (Operator::Equals, _)
+            | (Operator::OneOf, Value::Array(_))
+            | (Operator::StartsWith, Value::String(_)) => {}
+            (Operator::OneOf, _) => return Err(invalid("value must be an array").into()),
+            (Operator::Matches, Value::String(pattern)) => {
+                if let Err(err) = whole_match_regex(pattern) {
+                    return Err(invalid(&err.to_string()).into());
+                }
+            }
+            (Operator::StartsWith | Operator::Matches, _) => {
+                return Err(invalid("value must be a string").into())
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decide on every tool call of the message and record the decisions. Tool calls already decided
+/// by the user are left out.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn decide_for_message(
+    pool: &DbPool,
+    message: &Message,
+) -> Result<Vec<(ToolCall, Decision)>> {
+    let (policies, abilities) = match message.agent_id {
+        Some(agent_id) => (
+            repo::tool_call_policies::list_for_agent(pool, message.company_id, agent_id).await?,
+            repo::abilities::list_for_agent(pool, message.company_id, agent_id).await?,
+        ),
+        None => (vec![], vec![]),
+    };
+
+    let tool_calls = message.tool_calls().0;
+    let mut params = Vec::with_capacity(tool_calls.len());
+    for tool_call in &tool_calls {
+        let evaluation = evaluate(&policies, message.agent_id, &abilities, tool_call);
+
+        debug!(
+            "Tool call `{}` of message `{}`: {:?}",
+            tool_call.id, message.id, evaluation
+        );
+
+        params.push(CreateDecisionParams {
+            tool_call_id: tool_call.id.clone(),
+            decision: evaluation.decision,
+            policy_id: evaluation.policy_id,
+            reason: evaluation.reason,
+        });
+    }
+
+    let decisions =
+        repo::tool_call_policies::claim_decisions(pool, message.company_id, message.id, params)
+            .await?;
+
+    Ok(tool_calls
+        .into_iter()
+        .filter_map(|tool_call| {
+            decisions
+                .iter()
+                .find(|decision| decision.tool_call_id == tool_call.id)
+                .map(|decision| (tool_call, decision.decision))
+        })
+        .collect())
+}
+
+/// Decide on interpreting the code blocks of the message, unless the user already has, and record
+/// the decision. The code blocks have no tool call of their own, so the decision is recorded under
+/// the code interpreter function, as if it was called.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn decide_for_code_blocks(
+    pool: &DbPool,
+    message: &Message,
+    code_blocks: &[CodeBlock],
+) -> Result<Decision> {
+    let policies = match message.agent_id {
+        Some(agent_id) => {
+            repo::tool_call_policies::list_for_agent(pool, message.company_id, agent_id).await?
+        }
+        None => vec![],
+    };
+
+    let evaluation = evaluate_code_blocks(&policies, message.agent_id, code_blocks);
+
+    debug!("Code blocks of message `{}`: {:?}", message.id, evaluation);
+
+    repo::tool_call_policies::claim_decisions(
+        pool,
+        message.company_id,
+        message.id,
+        vec![CreateDecisionParams {
+            tool_call_id: code_interpreter::FUNCTION_NAME.to_string(),
+            decision: evaluation.decision,
+            policy_id: evaluation.policy_id,
+            reason: evaluation.reason,
+        }],
+    )
+    .await?;
+
+    // The user may have decided before, in which case their decision stands
+    let decisions =
+        repo::tool_call_policies::list_decisions_for_message(pool, message.company_id, message.id)
+            .await?;
+
+    Ok(decisions
+        .into_iter()
+        .find(|decision| decision.tool_call_id == code_interpreter::FUNCTION_NAME)
+        .map_or(Decision::Ask, |decision| decision.decision))
+}
+
+/// Decide on a tool call made by the agent, given the policies applicable to it.
+#[must_use]
+pub fn evaluate(
+    policies: &[ToolCallPolicy],
+    agent_id: Option<i32>,
+    abilities: &[Ability],
+    tool_call: &ToolCall,
+) -> Evaluation {
+    let name = tool_call.function.name.as_str();
+    let (target, ability_id) = match Target::for_builtin_function(name) {
+        Some(target) => (Some(target), None),
+        None => match abilities
+            .iter()
+            .find(|ability| ability.function_name() == Some(name))
+        {
+            Some(ability) => (Some(Target::Ability), Some(ability.id)),
+            None => (None, None),
+        },
+    };
+    let arguments =
+        serde_json::from_str::<Value>(&tool_call.function.arguments).unwrap_or_default();
+
+    evaluate_target(policies, agent_id, target, ability_id, &arguments)
+}
+
+/// Decide on interpreting the code blocks, given the policies applicable to the code interpreter.
+/// Each code block is matched against the rules by its `language`, `code` and `filename`, and the
+/// strictest decision on them is the decision on all of them.
+#[must_use]
+pub fn evaluate_code_blocks(
+    policies: &[ToolCallPolicy],
+    agent_id: Option<i32>,
+    code_blocks: &[CodeBlock],
+) -> Evaluation {
+    code_blocks
+        .iter()
+        .map(|code_block| {
+            let arguments = json!({
+                "language": code_block.language,
+                "code": code_block.code,
+                "filename": code_block.filename,
+            });
+
+            evaluate_target(
+                policies,
+                agent_id,
+                Some(Target::CodeInterpreter),
+                None,
+                &arguments,
+            )
+        })
+        .max_by_key(|evaluation| match evaluation.decision {
+            Decision::Allow => 0,
+            Decision::Ask => 1,
+            Decision::Deny => 2,
+        })
+        .unwrap_or_else(|| Evaluation {
+            decision: Decision::Allow,
+            policy_id: None,
+            reason: "no code blocks to interpret".to_string(),
+        })
+}
+
+fn evaluate_target(
+    policies: &[ToolCallPolicy],
+    agent_id: Option<i32>,
+    target: Option<Target>,
+    ability_id: Option<i32>,
+    arguments: &Value,
+) -> Evaluation {
+    let policy = policies
+        .iter()
+        .filter(|policy| policy.agent_id.is_none() || policy.agent_id == agent_id)
+        .filter_map(|policy| {
+            let is_exact = Some(policy.target) == target && policy.ability_id == ability_id;
+            if !is_exact && policy.target != Target::AnyTool {
+                return None;
+            }
+
+            let rank = u8::from(is_exact) * 2 + u8::from(policy.agent_id.is_some());
+            Some((rank, policy))
+        })
+        .max_by_key(|(rank, _)| *rank)
+        .map(|(_, policy)| policy);
+
+    let Some(policy) = policy else {
+        // The code interpreter ran on its own before there were policies
+        if target == Some(Target::CodeInterpreter) {
+            return Evaluation {
+                decision: Decision::Allow,
+                policy_id: None,
+                reason: "no policy applies to the code interpreter".to_string(),
+            };
+        }
+
+        return Evaluation {
+            decision: Decision::Ask,
+            policy_id: None,
+            reason: "no policy applies to the tool call".to_string(),
+        };
+    };
+
+    let (decision, reason) = match policy.action {
+        Action::AlwaysAllow => (Decision::Allow, "always allowed".to_string()),
+        Action::AlwaysAsk => (Decision::Ask, "always asked".to_string()),
+        Action::NeverAllow => (Decision::Deny, "never allowed".to_string()),
+        Action::AllowIfArgumentsMatch => match mismatched_rule(&policy.rules, arguments) {
+            None => (Decision::Allow, "arguments match the rules".to_string()),
+            Some(argument) => (
+                Decision::Ask,
+                format!("argument `{argument}` does not match the rules"),
+            ),
+        },
+    };
+
+    Evaluation {
+        decision,
+        policy_id: Some(policy.id),
+        reason,
+    }
+}
+
+/// Name of the first argument which doesn't match its rule, if any.
+fn mismatched_rule<'a>(rules: &'a [ArgumentRule], arguments: &Value) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| {
+            !arguments
+                .get(&rule.argument)
+                .is_some_and(|arg| matches(rule, arg))
+        })
+        .map(|rule| rule.argument.as_str())
+}
+
+fn matches(rule: &ArgumentRule, argument: &Value) -> bool {
+    match (rule.operator, &rule.value, argument) {
+        (Operator::Equals, value, argument) => value == argument,
+        (Operator::OneOf, Value::Array(values), argument) => values.contains(argument),
+        (Operator::StartsWith, Value::String(prefix), Value::String(argument)) => {
+            argument.starts_with(prefix.as_str())
+        }
+        (Operator::Matches, Value::String(pattern), Value::String(argument)) => {
+            whole_match_regex(pattern).is_ok_and(|regex| regex.is_match(argument))
+        }
+        _ => false,
+    }
+}
+
+/// Patterns must match the whole argument, or `ls .*` would let `rm -rf / # ls -la` through.
+fn whole_match_regex(pattern: &str) -> std::result::Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{pattern})$"))
+}
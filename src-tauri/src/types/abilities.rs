@@ -0,0 +1,76 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Default, Clone, Copy)]
+pub enum Language {
+    #[default]
+    Python,
+    JavaScript,
+    TypeScript,
+    Shell,
+}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for Language {
+    fn from(language: String) -> Self {
+        match language.as_str() {
+            "JavaScript" => Language::JavaScript,
+            "TypeScript" => Language::TypeScript,
+            "Shell" => Language::Shell,
+            _ => Language::Python,
+        }
+    }
+}
+
+/// Ability as stored by the application.
+///
+/// Mirrors `bridge_common::types::abilities::Ability`, plus the columns of its `ability_runtimes`
+/// row.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, FromRow)]
+pub struct Ability {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    pub description: String,
+    pub code: String,
+    pub parameters_json: Value,
+    #[sqlx(try_from = "String")]
+    pub language: Language,
+    /// Pinned Python requirements, e.g. `pandas==2.2.2`.
+    pub requirements: Vec<String>,
+    /// Wall time a single run may take, overriding the global tool call timeout.
+    pub timeout_secs: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Ability {
+    /// Name of the function the ability exposes to LLM.
+    #[must_use]
+    pub fn function_name(&self) -> Option<&str> {
+        self.parameters_json["name"].as_str()
+    }
+
+    /// Wall time a single run may take: the ability own timeout, or the `default` one.
+    #[must_use]
+    pub fn timeout(&self, default: Duration) -> Duration {
+        self.timeout_secs
+            .and_then(|secs| u64::try_from(secs).ok())
+            .map_or(default, Duration::from_secs)
+    }
+}
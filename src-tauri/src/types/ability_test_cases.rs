@@ -0,0 +1,18 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct AbilityTestCase {
+    pub id: i32,
+    pub company_id: i32,
+    pub ability_id: i32,
+    pub name: String,
+    pub arguments: Value,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,20 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Full output of a tool call which was too long to be sent to LLM as is.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Artifact {
+    pub id: i64,
+    pub company_id: i32,
+    pub chat_id: i32,
+    /// Message with the tool call which produced the artifact.
+    pub message_id: i64,
+    pub tool_call_id: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,20 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+
+/// Event as it was emitted, kept in the append-only event log.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct LoggedEvent {
+    /// Position of the event in the log, growing with every event.
+    pub seq: i64,
+    pub company_id: i32,
+    /// Name the event was emitted under, e.g. `tasks:updated`.
+    pub name: String,
+    /// Payload the event was emitted with, without `seq`.
+    pub payload: Json<Value>,
+    pub created_at: DateTime<Utc>,
+}
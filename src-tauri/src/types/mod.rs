@@ -0,0 +1,20 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use sqlx::{Pool, Postgres};
+
+pub mod abilities;
+pub mod ability_test_cases;
+pub mod artifacts;
+pub mod events;
+pub mod plan_proposals;
+pub mod task_queue;
+pub mod task_steps;
+pub mod task_templates;
+pub mod tool_call_policies;
+pub mod webhooks;
+pub mod workspaces;
+
+pub type Result<T> = std::result::Result<T, crate::errors::Error>;
+
+pub type DbPool = Pool<Postgres>;
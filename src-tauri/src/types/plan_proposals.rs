@@ -0,0 +1,67 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub enum ProposalStatus {
+    /// Waiting for the user to review it.
+    #[default]
+    Pending,
+    /// Saved as the subtasks of the task, possibly edited.
+    Accepted,
+    /// Rejected with feedback, which the next proposal is made with.
+    Rejected,
+    /// Replaced by a newer proposal without being reviewed.
+    Superseded,
+}
+
+impl Display for ProposalStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for ProposalStatus {
+    fn from(status: String) -> Self {
+        match status.as_str() {
+            "Accepted" => ProposalStatus::Accepted,
+            "Rejected" => ProposalStatus::Rejected,
+            "Superseded" => ProposalStatus::Superseded,
+            _ => ProposalStatus::Pending,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProposedSubtask {
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    pub agent_id: i32,
+    /// How many execution steps the subtask is expected to take, as estimated by the planner.
+    #[serde(default)]
+    pub estimated_steps: u32,
+}
+
+/// Plan proposed for a task, to be reviewed before it's saved.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct PlanProposal {
+    pub id: i32,
+    pub company_id: i32,
+    pub task_id: i32,
+    #[sqlx(try_from = "String")]
+    pub status: ProposalStatus,
+    /// Why the task is split this way, as explained by the planner.
+    pub rationale: String,
+    /// Subtasks to create, or a single one if the task needs no plan, and is only to be assigned
+    /// to its agent.
+    pub subtasks: Json<Vec<ProposedSubtask>>,
+    pub feedback: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,19 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Root task waiting in the execution queue.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct QueuedTask {
+    pub id: i32,
+    pub agent_id: i32,
+    pub title: String,
+    pub priority: i32,
+    /// When the task took its place in the queue, `None` until it's given one. Tasks of the same
+    /// priority are executed in this order, so it's moved along with the task.
+    pub queued_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
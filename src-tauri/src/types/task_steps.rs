@@ -0,0 +1,80 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+
+/// How the step left the task.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub enum Verdict {
+    /// The task goes on with the next step.
+    #[default]
+    Continue,
+    /// Marked as done.
+    Done,
+    /// Marked as failed.
+    Fail,
+    /// Waiting for the user to answer.
+    Wait,
+}
+
+impl Verdict {
+    /// Verdict of the internal tool, if the tool is one of them.
+    #[must_use]
+    pub fn for_tool(name: &str) -> Option<Self> {
+        match name {
+            "sfai_done" => Some(Verdict::Done),
+            "sfai_fail" => Some(Verdict::Fail),
+            "sfai_wait_for_user" => Some(Verdict::Wait),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for Verdict {
+    fn from(verdict: String) -> Self {
+        match verdict.as_str() {
+            "Done" => Verdict::Done,
+            "Fail" => Verdict::Fail,
+            "Wait" => Verdict::Wait,
+            _ => Verdict::Continue,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StepToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+/// Step of task execution: a completion of the agent, or a self-reflection, along with the tool
+/// calls it made.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct TaskStep {
+    pub id: i64,
+    pub company_id: i32,
+    pub task_id: i32,
+    pub message_id: Option<i64>,
+    pub is_self_reflection: bool,
+    pub model: String,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub tool_calls: Json<Vec<StepToolCall>>,
+    #[sqlx(try_from = "String")]
+    pub verdict: Verdict,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the step is being executed.
+    pub finished_at: Option<DateTime<Utc>>,
+}
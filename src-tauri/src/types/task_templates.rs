@@ -0,0 +1,55 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParameterKind {
+    String,
+    Number,
+    Boolean,
+}
+
+/// Value a template is instantiated with, put in place of its `{{name}}` placeholders.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: ParameterKind,
+    #[serde(default)]
+    pub description: String,
+    /// Value used if none is given. Parameters without one are required.
+    #[serde(default)]
+    pub default: Option<Value>,
+}
+
+/// Subtask created along with the task, in the order given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubtaskTemplate {
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    /// Agent of the subtask, the one of its parent if not set.
+    #[serde(default)]
+    pub agent_id: Option<i32>,
+    #[serde(default)]
+    pub children: Vec<SubtaskTemplate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct TaskTemplate {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    pub title: String,
+    pub summary: String,
+    /// Default agent of the task, which has to be given on instantiation if not set.
+    pub agent_id: Option<i32>,
+    pub parameters: Json<Vec<Parameter>>,
+    /// Pre-planned subtask tree. The task is planned as usual if it's empty.
+    pub subtasks: Json<Vec<SubtaskTemplate>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,153 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+
+/// Tools a policy applies to.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub enum Target {
+    /// Every tool the agent has, unless there is a more specific policy.
+    #[default]
+    AnyTool,
+    Ability,
+    CodeInterpreter,
+    WebBrowser,
+}
+
+impl Target {
+    /// Built-in tool the function belongs to, if any.
+    #[must_use]
+    pub fn for_builtin_function(name: &str) -> Option<Self> {
+        match name {
+            "sfai_code_interpreter" => Some(Target::CodeInterpreter),
+            "sfai_web_browser" => Some(Target::WebBrowser),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for Target {
+    fn from(target: String) -> Self {
+        match target.as_str() {
+            "Ability" => Target::Ability,
+            "CodeInterpreter" => Target::CodeInterpreter,
+            "WebBrowser" => Target::WebBrowser,
+            _ => Target::AnyTool,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub enum Action {
+    AlwaysAllow,
+    #[default]
+    AlwaysAsk,
+    NeverAllow,
+    /// Allow if the arguments match every rule of the policy, ask otherwise.
+    AllowIfArgumentsMatch,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for Action {
+    fn from(action: String) -> Self {
+        match action.as_str() {
+            "AlwaysAllow" => Action::AlwaysAllow,
+            "NeverAllow" => Action::NeverAllow,
+            "AllowIfArgumentsMatch" => Action::AllowIfArgumentsMatch,
+            _ => Action::AlwaysAsk,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operator {
+    /// Argument is equal to the value.
+    Equals,
+    /// Argument is equal to one of the values in the array.
+    OneOf,
+    /// Argument is a string starting with the value.
+    StartsWith,
+    /// Argument is a string matching the regular expression.
+    Matches,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArgumentRule {
+    pub argument: String,
+    pub operator: Operator,
+    pub value: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct ToolCallPolicy {
+    pub id: i32,
+    pub company_id: i32,
+    /// Agent the policy applies to, or every agent if not set.
+    pub agent_id: Option<i32>,
+    #[sqlx(try_from = "String")]
+    pub target: Target,
+    /// Set if, and only if, the target is `Ability`.
+    pub ability_id: Option<i32>,
+    #[sqlx(try_from = "String")]
+    pub action: Action,
+    pub rules: Json<Vec<ArgumentRule>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub enum Decision {
+    Allow,
+    #[default]
+    Ask,
+    Deny,
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for Decision {
+    fn from(decision: String) -> Self {
+        match decision.as_str() {
+            "Allow" => Decision::Allow,
+            "Deny" => Decision::Deny,
+            _ => Decision::Ask,
+        }
+    }
+}
+
+/// Decision made on a single tool call of a message.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct ToolCallDecision {
+    pub id: i64,
+    pub company_id: i32,
+    pub message_id: i64,
+    pub tool_call_id: String,
+    #[sqlx(try_from = "String")]
+    pub decision: Decision,
+    /// Policy the decision was made by, if any.
+    pub policy_id: Option<i32>,
+    /// Human-readable explanation of the decision.
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Webhook {
+    pub id: i32,
+    pub company_id: i32,
+    pub url: String,
+    /// Names of the events to send, e.g. `tasks:updated`.
+    pub events: Vec<String>,
+    /// Key the payloads are signed with. Encrypted in the database, masked in the listings.
+    pub secret: String,
+    pub is_enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub enum DeliveryStatus {
+    /// Not delivered yet, but will be retried.
+    #[default]
+    Pending,
+    Delivered,
+    /// Not delivered, and no more retries are left.
+    Failed,
+}
+
+impl Display for DeliveryStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl From<String> for DeliveryStatus {
+    fn from(status: String) -> Self {
+        match status.as_str() {
+            "Delivered" => DeliveryStatus::Delivered,
+            "Failed" => DeliveryStatus::Failed,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+}
+
+/// Event sent to a webhook, along with the outcome of the last attempt.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub company_id: i32,
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: Json<Value>,
+    #[sqlx(try_from = "String")]
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// HTTP status of the last attempt, if there was a response.
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
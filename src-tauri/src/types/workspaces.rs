@@ -0,0 +1,16 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Workspace, stored as a company. Everything in it is scoped by its id as the company id.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,337 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Outbound webhooks, sending events to the URLs subscribed to them.
+//!
+//! `Events` queues every event it emits. The queue is drained in the background: an event is
+//! logged as a delivery for every enabled webhook subscribed to it, and sent with retries, backing
+//! off exponentially. The webhook is read anew for every attempt, so retries stop once it's
+//! disabled or deleted. Deliveries still pending when the app quits are resumed on the next start.
+//!
+//! The body is `{"id": ..., "name": ..., "payload": ...}`, `id` being the delivery id and `payload`
+//! the event as the frontend gets it. It's signed with the webhook secret: `X-Bridge-Signature` is
+//! `sha256=` followed by the hex HMAC-SHA256 of `{X-Bridge-Timestamp}.{body}`.
+
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::{spawn, sync::mpsc, time::sleep};
+use tracing::{debug, error, warn};
+
+use crate::{
+    events::EVENT_NAMES,
+    repo::{
+        self,
+        webhooks::{CreateDeliveryParams, UpdateDeliveryParams},
+    },
+    state::Shared,
+    types::{
+        webhooks::{DeliveryStatus, Webhook, WebhookDelivery},
+        Result,
+    },
+};
+
+const MAX_ATTEMPTS: i32 = 8;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the enabled webhooks are cached for, so they're not queried for every event.
+const WEBHOOKS_TTL: Duration = Duration::from_secs(5);
+const SECRET_PREFIX: &str = "whsec_";
+/// Events sent for every streamed chunk, which only the clients of the app get.
+const STREAMED_EVENTS: &[&str] = &["messages:delta"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("webhook URL must be an absolute `http` or `https` URL")]
+    InvalidUrl,
+    #[error("webhook must have at least one event")]
+    NoEvents,
+    #[error("unknown event `{0}`")]
+    UnknownEvent(String),
+    #[error("event `{0}` is streamed, so it's not sent to webhooks")]
+    StreamedEvent(String),
+}
+
+/// Event queued for delivery.
+struct Queued {
+    company_id: i32,
+    name: String,
+    payload: Value,
+}
+
+/// Events emitted since the webhooks were started, waiting to be delivered.
+pub struct Queue(mpsc::UnboundedReceiver<Queued>);
+
+/// Sending end of the queue, `Events` pushes every event to.
+#[derive(Clone)]
+pub struct Sender(mpsc::UnboundedSender<Queued>);
+
+impl Sender {
+    /// Queue the event for the webhooks subscribed to it.
+    pub fn push(&self, company_id: i32, name: &str, payload: Value) {
+        // The queue is gone only if the webhooks are not started, so there is nobody to send to
+        let _ = self.0.send(Queued {
+            company_id,
+            name: name.to_string(),
+            payload,
+        });
+    }
+}
+
+/// Create the queue of events for the webhooks.
+#[must_use]
+pub fn queue() -> (Sender, Queue) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    (Sender(sender), Queue(receiver))
+}
+
+/// Deliver the queued events in the background, for as long as `Events` lives.
+pub fn start<S: Shared>(state: &S, mut queue: Queue) {
+    let state = state.clone();
+
+    spawn(async move {
+        let mut webhooks = Vec::new();
+        let mut loaded_at: Option<Instant> = None;
+
+        while let Some(queued) = queue.0.recv().await {
+            if loaded_at.map_or(true, |loaded_at| loaded_at.elapsed() > WEBHOOKS_TTL) {
+                match repo::webhooks::list_enabled(state.pool()).await {
+                    Ok(enabled) => {
+                        webhooks = enabled;
+                        loaded_at = Some(Instant::now());
+                    }
+                    Err(err) => error!("Failed to list webhooks: {:?}", err),
+                }
+            }
+
+            let subscribed = webhooks.iter().filter(|webhook| {
+                webhook.company_id == queued.company_id && webhook.events.contains(&queued.name)
+            });
+            for webhook in subscribed {
+                if let Err(err) = dispatch(&state, webhook, &queued).await {
+                    error!("Failed to dispatch webhook {}: {:?}", webhook.id, err);
+                }
+            }
+        }
+
+        debug!("Webhooks queue is closed");
+    });
+}
+
+/// Resume the deliveries left pending, e.g. when the app quit in the middle of retries.
+///
+/// # Errors
+///
+/// Returns error if there was a problem while accessing database.
+pub async fn resume<S: Shared>(state: &S) -> Result<()> {
+    for delivery in repo::webhooks::list_pending_deliveries(state.pool()).await? {
+        spawn(deliver(state.clone(), delivery));
+    }
+
+    Ok(())
+}
+
+/// Check that the webhook makes sense before saving it.
+///
+/// # Errors
+///
+/// Returns error if the URL is not an HTTP one, or if the events are missing, unknown or streamed.
+pub fn validate(url: &str, events: &[String]) -> Result<()> {
+    let is_http = Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+    if !is_http {
+        return Err(Error::InvalidUrl.into());
+    }
+
+    if events.is_empty() {
+        return Err(Error::NoEvents.into());
+    }
+
+    if let Some(event) = events
+        .iter()
+        .find(|event| !EVENT_NAMES.contains(&event.as_str()))
+    {
+        return Err(Error::UnknownEvent(event.clone()).into());
+    }
+
+    if let Some(event) = events
+        .iter()
+        .find(|event| STREAMED_EVENTS.contains(&event.as_str()))
+    {
+        return Err(Error::StreamedEvent(event.clone()).into());
+    }
+
+    Ok(())
+}
+
+/// Events listed once each, in the order given.
+#[must_use]
+pub fn dedup_events(events: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    events
+        .into_iter()
+        .filter(|event| seen.insert(event.clone()))
+        .collect()
+}
+
+/// Generate a new webhook secret.
+#[must_use]
+pub fn generate_secret() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    format!("{SECRET_PREFIX}{}", hex::encode(bytes))
+}
+
+async fn dispatch<S: Shared>(state: &S, webhook: &Webhook, queued: &Queued) -> Result<()> {
+    let delivery = repo::webhooks::create_delivery(
+        state.pool(),
+        queued.company_id,
+        CreateDeliveryParams {
+            webhook_id: webhook.id,
+            event: queued.name.clone(),
+            payload: queued.payload.clone(),
+        },
+    )
+    .await?;
+
+    spawn(deliver(state.clone(), delivery));
+
+    Ok(())
+}
+
+/// Send the delivery until it succeeds or runs out of attempts, recording every attempt.
+async fn deliver<S: Shared>(state: S, delivery: WebhookDelivery) {
+    let mut attempts = delivery.attempts;
+    loop {
+        attempts += 1;
+
+        let (response_status, error, is_retriable) = attempt(&state, &delivery).await;
+
+        let is_retriable = is_retriable && attempts < MAX_ATTEMPTS;
+        let status = match error {
+            None => DeliveryStatus::Delivered,
+            Some(_) if is_retriable => DeliveryStatus::Pending,
+            Some(_) => DeliveryStatus::Failed,
+        };
+
+        if let Some(error) = &error {
+            warn!(
+                "Delivery {} to webhook {} failed, attempt {}: {}",
+                delivery.id, delivery.webhook_id, attempts, error
+            );
+        }
+
+        let update = repo::webhooks::update_delivery(
+            state.pool(),
+            delivery.company_id,
+            UpdateDeliveryParams {
+                id: delivery.id,
+                status,
+                attempts,
+                response_status: response_status.map(i32::from),
+                error,
+            },
+        )
+        .await;
+        if let Err(err) = update {
+            error!("Failed to record delivery {}: {:?}", delivery.id, err);
+        }
+
+        if status != DeliveryStatus::Pending {
+            return;
+        }
+
+        sleep(backoff(attempts)).await;
+    }
+}
+
+/// Send the delivery to the webhook as it is now, returning the response status, the error if
+/// the attempt failed, and whether it's worth retrying.
+async fn attempt<S: Shared>(
+    state: &S,
+    delivery: &WebhookDelivery,
+) -> (Option<u16>, Option<String>, bool) {
+    let webhook =
+        match repo::webhooks::find(state.pool(), delivery.company_id, delivery.webhook_id).await {
+            Ok(Some(webhook)) => webhook,
+            Ok(None) => return (None, Some("Webhook is deleted".to_string()), false),
+            Err(err) => return (None, Some(format!("Failed to get webhook: {err}")), true),
+        };
+
+    if !webhook.is_enabled {
+        return (None, Some("Webhook is disabled".to_string()), false);
+    }
+
+    let secret = match state.cipher().decrypt(&webhook.secret) {
+        Ok(secret) => secret,
+        Err(err) => {
+            error!(
+                "Failed to decrypt secret of webhook {}: {:?}",
+                webhook.id, err
+            );
+            return (
+                None,
+                Some("Failed to decrypt webhook secret".to_string()),
+                false,
+            );
+        }
+    };
+
+    match send(&webhook.url, &secret, delivery).await {
+        Ok(status) if (200..300).contains(&status) => (Some(status), None, true),
+        Ok(status) => (Some(status), Some(format!("Responded with {status}")), true),
+        Err(err) => (None, Some(format!("{err:#}")), true),
+    }
+}
+
+async fn send(url: &str, secret: &str, delivery: &WebhookDelivery) -> anyhow::Result<u16> {
+    let body = json!({
+        "id": delivery.id,
+        "name": delivery.event,
+        "payload": delivery.payload,
+    })
+    .to_string();
+    let timestamp = Utc::now().timestamp().to_string();
+
+    let response = reqwest::Client::builder()
+        .user_agent(crate::USER_AGENT.as_str())
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Bridge-Delivery", delivery.id.to_string())
+        .header("X-Bridge-Event", &delivery.event)
+        .header("X-Bridge-Timestamp", &timestamp)
+        .header("X-Bridge-Signature", sign(secret, &timestamp, &body)?)
+        .body(body)
+        .send()
+        .await?;
+
+    Ok(response.status().as_u16())
+}
+
+fn sign(secret: &str, timestamp: &str, body: &str) -> anyhow::Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+fn backoff(attempts: i32) -> Duration {
+    INITIAL_BACKOFF * 2_u32.pow(u32::try_from(attempts - 1).unwrap_or_default())
+}
@@ -0,0 +1,217 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+//! Workspaces keep agents, abilities, chats, tasks, pages and settings strictly apart.
+//!
+//! A workspace is a company, so everything in it is scoped by the workspace id as the company id.
+//! One workspace is active at a time: commands and the task executor work with the active one
+//! only. The active workspace is remembered in the app local data dir.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{PoisonError, RwLock as SyncRwLock},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::{
+    repo,
+    secrets::Cipher,
+    settings::Settings,
+    types::{workspaces::Workspace, DbPool, Result},
+};
+
+/// Workspace created by the seeds, which can't be deleted.
+pub const DEFAULT_ID: i32 = 0;
+/// Agent created by the seeds, copied into every new workspace.
+const SEEDED_AGENT_ID: i32 = 1;
+const ACTIVE_WORKSPACE_FILE: &str = "workspace.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("active workspace can't be deleted, switch to another one first")]
+    IsActive,
+    #[error("default workspace can't be deleted")]
+    IsDefault,
+    #[error("workspace name can't be empty")]
+    EmptyName,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ActiveWorkspaceFile {
+    id: i32,
+}
+
+/// Id of the active workspace and of its local user, kept in the app state.
+///
+/// Both ids are kept behind one lock, so they're always read from the same workspace.
+#[allow(clippy::module_name_repetitions)]
+pub struct ActiveWorkspace {
+    ids: SyncRwLock<(i32, i32)>,
+    file: PathBuf,
+}
+
+impl ActiveWorkspace {
+    /// Load the workspace which was active last time, falling back to the default one if it's
+    /// gone.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if there was a problem while accessing database.
+    pub async fn load(pool: &DbPool, app_local_data_dir: &Path) -> Result<Self> {
+        let file = app_local_data_dir.join(ACTIVE_WORKSPACE_FILE);
+
+        let id = std::fs::read_to_string(&file)
+            .ok()
+            .and_then(|content| serde_json::from_str::<ActiveWorkspaceFile>(&content).ok())
+            .map_or(DEFAULT_ID, |active| active.id);
+
+        let id = match repo::workspaces::get(pool, id).await {
+            Ok(workspace) => workspace.id,
+            Err(crate::errors::Error::Sqlx(sqlx::Error::RowNotFound)) => {
+                warn!("Workspace {id} is gone, switching to the default one");
+                DEFAULT_ID
+            }
+            Err(err) => return Err(err),
+        };
+        let user_id = user_id(pool, id).await?;
+
+        Ok(Self {
+            ids: SyncRwLock::new((id, user_id)),
+            file,
+        })
+    }
+
+    /// Id of the active workspace, to be used as the company id.
+    #[must_use]
+    pub fn id(&self) -> i32 {
+        self.ids().0
+    }
+
+    /// Ids of the active workspace and of its local user, who the events are emitted for and the
+    /// user messages are written by.
+    #[must_use]
+    pub fn ids(&self) -> (i32, i32) {
+        *self.ids.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set(&self, id: i32, user_id: i32) -> Result<()> {
+        let content = serde_json::to_string(&ActiveWorkspaceFile { id })
+            .with_context(|| "Failed to serialize active workspace")?;
+        std::fs::write(&self.file, content)
+            .with_context(|| format!("Failed to write `{}`", self.file.display()))?;
+
+        *self.ids.write().unwrap_or_else(PoisonError::into_inner) = (id, user_id);
+
+        Ok(())
+    }
+}
+
+/// Create workspace with the models and the agent the default workspace is seeded with.
+///
+/// # Errors
+///
+/// Returns error if the name is empty or if there was a problem while accessing database.
+pub async fn create(pool: &DbPool, name: &str) -> Result<Workspace> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyName.into());
+    }
+
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let base_slug = slugify(name);
+    let mut slug = base_slug.clone();
+    let mut suffix = 1;
+    while repo::workspaces::is_slug_taken(&mut *tx, &slug).await? {
+        suffix += 1;
+        slug = format!("{base_slug}-{suffix}");
+    }
+
+    let workspace = repo::workspaces::create(&mut *tx, name, &slug).await?;
+    repo::workspaces::create_user(&mut *tx, workspace.id).await?;
+    repo::workspaces::copy_models(&mut *tx, DEFAULT_ID, workspace.id).await?;
+    repo::workspaces::copy_agent(&mut *tx, DEFAULT_ID, SEEDED_AGENT_ID, workspace.id).await?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    info!("Created workspace {} `{}`", workspace.id, workspace.slug);
+
+    Ok(workspace)
+}
+
+/// Make the workspace active, loading its settings.
+///
+/// # Errors
+///
+/// Returns error if the workspace does not exist or its settings can't be loaded.
+pub async fn switch(
+    pool: &DbPool,
+    active: &ActiveWorkspace,
+    settings: &RwLock<Settings>,
+    cipher: &Cipher,
+    id: i32,
+) -> Result<Workspace> {
+    let workspace = repo::workspaces::get(pool, id).await?;
+    let user_id = user_id(pool, workspace.id).await?;
+
+    let mut settings = settings.write().await;
+    *settings = repo::settings::get(pool, workspace.id, cipher).await?;
+    active.set(workspace.id, user_id)?;
+
+    info!(
+        "Switched to workspace {} `{}`",
+        workspace.id, workspace.slug
+    );
+
+    Ok(workspace)
+}
+
+/// Delete the workspace with everything in it.
+///
+/// # Errors
+///
+/// Returns error if the workspace is the active or the default one, or if there was a problem
+/// while accessing database.
+pub async fn delete(pool: &DbPool, active: &ActiveWorkspace, id: i32) -> Result<()> {
+    if id == DEFAULT_ID {
+        return Err(Error::IsDefault.into());
+    }
+    if id == active.id() {
+        return Err(Error::IsActive.into());
+    }
+
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+    repo::workspaces::delete(&mut tx, id).await?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    info!("Deleted workspace {id}");
+
+    Ok(())
+}
+
+/// Id of the local user of the workspace, created if the workspace has none.
+async fn user_id(pool: &DbPool, id: i32) -> Result<i32> {
+    match repo::workspaces::get_user_id(pool, id).await? {
+        Some(user_id) => Ok(user_id),
+        None => repo::workspaces::create_user(pool, id).await,
+    }
+}
+
+fn slugify(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        "workspace".to_string()
+    } else {
+        slug
+    }
+}
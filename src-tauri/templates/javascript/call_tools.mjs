@@ -0,0 +1,28 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+import { createRequire as __bridgeCreateRequire } from 'node:module'
+import { writeFileSync as __bridgeWriteFileSync } from 'node:fs'
+
+// Let CommonJS-style abilities work as well
+const require = __bridgeCreateRequire(import.meta.url)
+const module = { exports: {} }
+
+{{ code }}
+
+const __bridgeArguments = JSON.parse({{ arguments }})
+const __bridgeResult = { return_value: null, error: null }
+const __bridgeStartedAt = performance.now()
+try {
+  __bridgeResult.return_value = await {{ name }}({{ call_arguments }})
+} catch (e) {
+  __bridgeResult.error = e instanceof Error ? e.stack ?? String(e) : String(e)
+}
+__bridgeResult.duration_ms = performance.now() - __bridgeStartedAt
+
+__bridgeWriteFileSync(
+  {{ result_path }},
+  JSON.stringify(__bridgeResult, (_, value) =>
+    value === undefined ? null : typeof value === 'bigint' ? value.toString() : value,
+  ),
+)
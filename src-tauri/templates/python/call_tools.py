@@ -2,14 +2,19 @@
 # SPDX-License-Identifier: Apache-2.0
 
 import json
+import time
+import traceback
 from typing import Annotated, Callable, Dict, Any, get_origin, get_args
 
 {{ code }}
 
-tool_call = {{ tool_call }}
-name = tool_call['function']['name']
+_bridge_result = {"return_value": None, "error": None}
+_bridge_started_at = time.perf_counter()
 try:
-    arguments = json.loads(tool_call['function']['arguments'])
-    print(globals()[name](**arguments))
-except Exception as e:
-    print(str(e))
+    _bridge_result["return_value"] = globals()[{{ name }}](**json.loads({{ arguments }}))
+except Exception:
+    _bridge_result["error"] = traceback.format_exc()
+_bridge_result["duration_ms"] = (time.perf_counter() - _bridge_started_at) * 1000
+
+with open({{ result_path }}, "w") as _bridge_file:
+    json.dump(_bridge_result, _bridge_file, default=repr)
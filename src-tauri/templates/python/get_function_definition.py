@@ -85,8 +85,14 @@ class Ability:
 
 ability = Ability()
 
-@ability.register()
-{{ code }}
+# Run the code as is, so it can import whatever it needs, and register the first function it defines
+_bridge_namespace = {}
+exec(compile({{ code }}, "<ability>", "exec"), _bridge_namespace)
+_bridge_function = next(
+    value for value in _bridge_namespace.values()
+    if getattr(getattr(value, "__code__", None), "co_filename", None) == "<ability>"
+)
+ability.register()(_bridge_function)
 
 print(json.dumps(ability.functions_definitions()[0]))
 
@@ -0,0 +1,23 @@
+# Copyright 2024 StarfleetAI
+# SPDX-License-Identifier: Apache-2.0
+
+{{ code }}
+
+{{ exports }}
+
+_bridge_started_at=$(date +%s%N)
+{{ name }} {{ call_arguments }}
+_bridge_exit_code=$?
+_bridge_finished_at=$(date +%s%N)
+
+if [ "$_bridge_exit_code" -eq 0 ]; then
+  _bridge_error=null
+else
+  _bridge_error="\"exited with status $_bridge_exit_code\""
+fi
+
+printf '{"return_value": %s, "error": %s, "duration_ms": %s}\n' \
+  "$_bridge_exit_code" \
+  "$_bridge_error" \
+  "$(( (_bridge_finished_at - _bridge_started_at) / 1000000 ))" \
+  > {{ result_path }}
@@ -0,0 +1,21 @@
+// Copyright 2024 StarfleetAI
+// SPDX-License-Identifier: Apache-2.0
+
+{{ code }}
+
+const __bridgeArguments: Record<string, any> = JSON.parse({{ arguments }})
+const __bridgeResult: Record<string, any> = { return_value: null, error: null }
+const __bridgeStartedAt = performance.now()
+try {
+  __bridgeResult.return_value = await {{ name }}({{ call_arguments }})
+} catch (e) {
+  __bridgeResult.error = e instanceof Error ? e.stack ?? String(e) : String(e)
+}
+__bridgeResult.duration_ms = performance.now() - __bridgeStartedAt
+
+Deno.writeTextFileSync(
+  {{ result_path }},
+  JSON.stringify(__bridgeResult, (_, value) =>
+    value === undefined ? null : typeof value === 'bigint' ? value.toString() : value,
+  ),
+)